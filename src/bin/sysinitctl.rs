@@ -0,0 +1,77 @@
+//! # `sysinitctl`
+//!
+//! `sysinitctl` controls a running `sysinitd` via its control socket
+//! (see [`sysinitd::control`]).
+
+/// Command-line arguments of `sysinitctl`
+#[derive(Debug, ::clap::Parser)]
+#[command(version, about = "Controls a running sysinitd")]
+struct Arguments {
+    /// The path of the control socket of `sysinitd`
+    #[clap(
+        long,
+        env = sysinitd::control::SOCKET_PATH_ENVIRONMENT_VARIABLE,
+        default_value = sysinitd::control::DEFAULT_SOCKET_PATH
+    )]
+    control_socket: std::path::PathBuf,
+
+    /// What to ask `sysinitd` to do
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// The requests `sysinitctl` can send
+#[derive(Debug, ::clap::Subcommand)]
+enum Command {
+    /// Show the state of all services
+    Status,
+    /// Start a service, e.g. one that is not started automatically
+    Start {
+        /// The ID of the service
+        id: String,
+    },
+    /// Switch to another target, stopping the services it does not need
+    Target {
+        /// The name of the target
+        target: String,
+    },
+    /// Add or remove instances of a service declared with replicas
+    Scale {
+        /// The ID of the service, without the instance
+        id: String,
+        /// The number of instances to run
+        replicas: usize,
+    },
+}
+
+impl From<Command> for sysinitd::control::Request {
+    fn from(command: Command) -> Self {
+        match command {
+            Command::Status => Self::Status,
+            Command::Start { id } => Self::Start(id),
+            Command::Target { target } => Self::Target(target),
+            Command::Scale { id, replicas } => Self::Scale(id, replicas),
+        }
+    }
+}
+
+#[::tokio::main(flavor = "current_thread")]
+async fn main() {
+    let arguments = <Arguments as ::clap::Parser>::parse();
+    let request = sysinitd::control::Request::from(arguments.command);
+
+    match sysinitd::control::request(&arguments.control_socket, &request).await {
+        Ok(sysinitd::control::Response::Ok(output)) => print!("{output}"),
+        Ok(sysinitd::control::Response::Error(message)) => {
+            eprintln!("sysinitd could not handle '{request}': {message}");
+            std::process::exit(1);
+        }
+        Err(error) => {
+            eprintln!(
+                "Could not talk to sysinitd via '{}': {error}",
+                arguments.control_socket.display()
+            );
+            std::process::exit(2);
+        }
+    }
+}
@@ -6,5 +6,10 @@
 
 pub use library::arguments::Arguments;
 
+pub use library::capability::Capability;
+pub use library::control;
+pub use library::process;
 pub use library::service;
 pub use library::service::Service;
+pub use library::signal::Signal;
+pub use library::supervisor::Supervisor;
@@ -9,8 +9,45 @@ pub struct Arguments {
     verbosity: ::clap_verbosity_flag::Verbosity<::clap_verbosity_flag::InfoLevel>,
 
     /// List of directories containing service definitions
-    #[clap(required = true)]
+    #[clap(required_unless_present = "command")]
     service_directories: Vec<::std::path::PathBuf>,
+
+    /// The path of the control socket `sysinitctl` connects to
+    #[clap(
+        long,
+        env = crate::library::control::SOCKET_PATH_ENVIRONMENT_VARIABLE,
+        default_value = crate::library::control::DEFAULT_SOCKET_PATH
+    )]
+    control_socket: ::std::path::PathBuf,
+
+    /// The directory the notify sockets of notify services are created in
+    #[clap(
+        long,
+        env = crate::library::notify::DIRECTORY_ENVIRONMENT_VARIABLE,
+        default_value = crate::library::notify::DEFAULT_DIRECTORY
+    )]
+    notify_directory: ::std::path::PathBuf,
+
+    /// The directory persistent state (like the last runs of scheduled
+    /// services) is kept in
+    #[clap(
+        long,
+        env = crate::library::schedule::STATE_DIRECTORY_ENVIRONMENT_VARIABLE,
+        default_value = crate::library::schedule::DEFAULT_STATE_DIRECTORY
+    )]
+    state_directory: ::std::path::PathBuf,
+
+    /// The target whose services (and their dependencies) are started
+    #[clap(
+        long,
+        env = crate::library::service::TARGET_ENVIRONMENT_VARIABLE,
+        default_value = crate::library::service::DEFAULT_TARGET
+    )]
+    target: String,
+
+    /// A command (with arguments) that is run as the main service
+    #[clap(last = true, value_name = "COMMAND")]
+    command: Vec<String>,
 }
 
 impl Arguments {
@@ -24,11 +61,47 @@ pub fn services_directories(&self) -> &[::std::path::PathBuf] {
         &self.service_directories
     }
 
+    /// The path of the control socket
+    pub fn control_socket(&self) -> &::std::path::Path {
+        &self.control_socket
+    }
+
+    /// The directory the notify sockets of notify services are created in
+    pub fn notify_directory(&self) -> &::std::path::Path {
+        &self.notify_directory
+    }
+
+    /// The directory persistent state is kept in
+    pub fn state_directory(&self) -> &::std::path::Path {
+        &self.state_directory
+    }
+
+    /// The target whose services are started
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// The command given after `--`, if any
+    ///
+    /// This command is run as an implicit main service.
+    pub fn command(&self) -> Option<&[String]> {
+        (!self.command.is_empty()).then_some(self.command.as_slice())
+    }
+
     #[cfg(test)]
     pub fn new_test(service_directories: Vec<::std::path::PathBuf>) -> Self {
         Self {
             verbosity: ::clap_verbosity_flag::Verbosity::new(2, 0),
             service_directories,
+            control_socket: ::std::path::PathBuf::from(
+                crate::library::control::DEFAULT_SOCKET_PATH,
+            ),
+            notify_directory: ::std::path::PathBuf::from(crate::library::notify::DEFAULT_DIRECTORY),
+            state_directory: ::std::path::PathBuf::from(
+                crate::library::schedule::DEFAULT_STATE_DIRECTORY,
+            ),
+            target: String::from(crate::library::service::DEFAULT_TARGET),
+            command: vec![],
         }
     }
 }
@@ -107,4 +180,31 @@ fn test_services_directories() {
             ]
         );
     }
+
+    #[test]
+    fn test_command() {
+        assert!(<Arguments as ::clap::Parser>::try_parse_from(["sysinitd"]).is_err());
+
+        let arguments =
+            <Arguments as ::clap::Parser>::try_parse_from(["sysinitd", "--", "sleep", "-h"])
+                .expect("could not parse command argument");
+        assert!(arguments.services_directories().is_empty());
+        assert_eq!(
+            arguments.command(),
+            Some(&[String::from("sleep"), String::from("-h")][..])
+        );
+
+        let arguments =
+            <Arguments as ::clap::Parser>::try_parse_from(["sysinitd", "/tmp", "--", "sleep"])
+                .expect("could not parse service-path and command arguments");
+        assert_eq!(
+            arguments.services_directories(),
+            &[::std::path::PathBuf::from_str("/tmp").unwrap()]
+        );
+        assert_eq!(arguments.command(), Some(&[String::from("sleep")][..]));
+
+        let arguments = <Arguments as ::clap::Parser>::try_parse_from(["sysinitd", "/tmp"])
+            .expect("could not parse single service-path argument");
+        assert_eq!(arguments.command(), None);
+    }
 }
@@ -0,0 +1,134 @@
+//! Contains [`Capability`], a Linux capability that can be parsed from
+//! its name (e.g. `NET_BIND_SERVICE` or `CAP_NET_BIND_SERVICE`) in service
+//! definitions.
+
+/// A Linux capability (see `capabilities(7)`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ::serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Capability(u8);
+
+/// All capabilities that can be named in service definitions, in the
+/// order of their numbers
+const CAPABILITIES: &[&str] = &[
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "KILL",
+    "SETGID",
+    "SETUID",
+    "SETPCAP",
+    "LINUX_IMMUTABLE",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_ADMIN",
+    "NET_RAW",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "SYS_MODULE",
+    "SYS_RAWIO",
+    "SYS_CHROOT",
+    "SYS_PTRACE",
+    "SYS_PACCT",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_NICE",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "MKNOD",
+    "LEASE",
+    "AUDIT_WRITE",
+    "AUDIT_CONTROL",
+    "SETFCAP",
+    "MAC_OVERRIDE",
+    "MAC_ADMIN",
+    "SYSLOG",
+    "WAKE_ALARM",
+    "BLOCK_SUSPEND",
+    "AUDIT_READ",
+    "PERFMON",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+];
+
+impl Capability {
+    /// `CAP_NET_BIND_SERVICE`
+    pub const NET_BIND_SERVICE: Self = Self(10);
+    /// `CAP_SYS_ADMIN`
+    pub const SYS_ADMIN: Self = Self(21);
+
+    /// All capabilities known to `sysinitd`
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..CAPABILITIES.len() as u8).map(Self)
+    }
+
+    /// The raw capability number
+    pub fn as_raw(&self) -> u8 {
+        self.0
+    }
+
+    /// The name of the capability without the `CAP_` prefix
+    pub fn name(&self) -> &'static str {
+        CAPABILITIES
+            .get(usize::from(self.0))
+            .copied()
+            .unwrap_or("UNKNOWN")
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "CAP_{}", self.name())
+    }
+}
+
+impl std::str::FromStr for Capability {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let upper_case = value.trim().to_ascii_uppercase();
+        let name = upper_case.strip_prefix("CAP_").unwrap_or(&upper_case);
+        CAPABILITIES
+            .iter()
+            .position(|known| *known == name)
+            .map(|number| Self(number as u8))
+            .ok_or_else(|| format!("'{value}' is not a known capability"))
+    }
+}
+
+impl TryFrom<String> for Capability {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_capability_names() {
+        assert_eq!(
+            "NET_BIND_SERVICE".parse::<Capability>(),
+            Ok(Capability::NET_BIND_SERVICE)
+        );
+        assert_eq!(
+            "cap_net_bind_service".parse::<Capability>(),
+            Ok(Capability::NET_BIND_SERVICE)
+        );
+        assert_eq!(
+            "CAP_CHECKPOINT_RESTORE".parse::<Capability>(),
+            Ok(Capability(40))
+        );
+        assert!("CAP_FLY".parse::<Capability>().is_err());
+        assert_eq!(
+            Capability::NET_BIND_SERVICE.to_string(),
+            "CAP_NET_BIND_SERVICE"
+        );
+        assert_eq!(Capability::all().count(), 41);
+    }
+}
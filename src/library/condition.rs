@@ -0,0 +1,215 @@
+//! Contains [`Condition`], which describes something about the system a
+//! service's `conditions` and `assertions` check
+//!
+//! Conditions are checked whenever a service is about to start: if one of
+//! them is not met, the service is skipped. Assertions are checked together
+//! with the service definitions: if one of them is not met, `sysinitd`
+//! does not start at all.
+
+/// The environment variable container managers (e.g. `systemd-nspawn`,
+/// Podman or LXC) set in the init process of a container
+const CONTAINER_ENVIRONMENT_VARIABLE: &str = "container";
+
+/// Files container managers create in the root of a container
+const CONTAINER_MARKER_FILES: [&str; 2] = ["/.dockerenv", "/run/.containerenv"];
+
+/// The file containing the kernel command line
+const KERNEL_COMMAND_LINE_FILE: &str = "/proc/cmdline";
+
+/// Something about the system that is checked before a service starts
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(try_from = "deserialize::Condition")]
+pub enum Condition {
+    /// The path exists
+    PathExists(std::path::PathBuf),
+    /// The path does not exist
+    PathAbsent(std::path::PathBuf),
+    /// The environment variable of `sysinitd` is set, or has the value
+    Environment {
+        /// The name of the variable
+        name: String,
+        /// The value the variable must have, if any
+        equals: Option<String>,
+    },
+    /// The file exists and is not empty
+    FileNotEmpty(std::path::PathBuf),
+    /// The flag (`flag` or `flag=...`) is on the kernel command line
+    KernelCommandLine(String),
+    /// `sysinitd` runs (or does not run) in a container
+    Container(bool),
+    /// The system has the architecture (as in [`std::env::consts::ARCH`],
+    /// e.g. `x86_64` or `aarch64`)
+    Architecture(String),
+}
+
+impl Condition {
+    /// Whether the condition is met
+    pub fn holds(&self) -> bool {
+        match self {
+            Self::PathExists(path) => path.exists(),
+            Self::PathAbsent(path) => !path.exists(),
+            Self::Environment { name, equals } => match (std::env::var_os(name), equals) {
+                (Some(value), Some(equals)) => value == equals.as_str(),
+                (value, None) => value.is_some(),
+                (None, Some(_)) => false,
+            },
+            Self::FileNotEmpty(path) => std::fs::metadata(path)
+                .is_ok_and(|metadata| metadata.is_file() && metadata.len() > 0),
+            Self::KernelCommandLine(flag) => std::fs::read_to_string(KERNEL_COMMAND_LINE_FILE)
+                .is_ok_and(|command_line| has_flag(&command_line, flag)),
+            Self::Container(expected) => in_container() == *expected,
+            Self::Architecture(architecture) => std::env::consts::ARCH == architecture,
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PathExists(path) => write!(formatter, "'{}' exists", path.display()),
+            Self::PathAbsent(path) => write!(formatter, "'{}' is absent", path.display()),
+            Self::Environment { name, equals: None } => {
+                write!(formatter, "environment variable '{name}' is set")
+            }
+            Self::Environment {
+                name,
+                equals: Some(value),
+            } => write!(formatter, "environment variable '{name}' is '{value}'"),
+            Self::FileNotEmpty(path) => write!(formatter, "'{}' is not empty", path.display()),
+            Self::KernelCommandLine(flag) => {
+                write!(formatter, "kernel command line contains '{flag}'")
+            }
+            Self::Container(true) => write!(formatter, "running in a container"),
+            Self::Container(false) => write!(formatter, "not running in a container"),
+            Self::Architecture(architecture) => write!(formatter, "architecture is {architecture}"),
+        }
+    }
+}
+
+/// Whether `flag` is on `command_line`, either on its own or with a value
+fn has_flag(command_line: &str, flag: &str) -> bool {
+    command_line.split_whitespace().any(|word| {
+        word == flag
+            || word
+                .strip_prefix(flag)
+                .is_some_and(|value| value.starts_with('='))
+    })
+}
+
+/// Whether `sysinitd` runs in a container
+fn in_container() -> bool {
+    std::env::var_os(CONTAINER_ENVIRONMENT_VARIABLE).is_some()
+        || CONTAINER_MARKER_FILES
+            .iter()
+            .any(|file| std::path::Path::new(file).exists())
+}
+
+mod deserialize {
+    //! Contains the representation of conditions in service definitions
+
+    /// The representation of a [`super::Condition`], which sets exactly one
+    /// of its fields
+    #[derive(::serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Condition {
+        /// The path that must exist
+        path_exists: Option<std::path::PathBuf>,
+        /// The path that must not exist
+        path_absent: Option<std::path::PathBuf>,
+        /// The environment variable that must be set (or have a value)
+        environment: Option<Environment>,
+        /// The file that must not be empty
+        file_not_empty: Option<std::path::PathBuf>,
+        /// The flag that must be on the kernel command line
+        kernel_command_line: Option<String>,
+        /// Whether `sysinitd` must run in a container
+        container: Option<bool>,
+        /// The architecture the system must have
+        architecture: Option<String>,
+    }
+
+    /// The representation of an environment variable condition: either just
+    /// the name or the name and the value
+    #[derive(::serde::Deserialize)]
+    #[serde(untagged)]
+    pub enum Environment {
+        /// The variable must be set
+        Name(String),
+        /// The variable must have the value
+        NameAndValue {
+            /// The name of the variable
+            name: String,
+            /// The value of the variable
+            equals: String,
+        },
+    }
+
+    impl TryFrom<Condition> for super::Condition {
+        type Error = String;
+
+        fn try_from(value: Condition) -> Result<Self, Self::Error> {
+            let conditions: Vec<Self> = [
+                value.path_exists.map(Self::PathExists),
+                value.path_absent.map(Self::PathAbsent),
+                value.environment.map(|environment| match environment {
+                    Environment::Name(name) => Self::Environment { name, equals: None },
+                    Environment::NameAndValue { name, equals } => Self::Environment {
+                        name,
+                        equals: Some(equals),
+                    },
+                }),
+                value.file_not_empty.map(Self::FileNotEmpty),
+                value.kernel_command_line.map(Self::KernelCommandLine),
+                value.container.map(Self::Container),
+                value.architecture.map(Self::Architecture),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+
+            match <[Self; 1]>::try_from(conditions) {
+                Ok([condition]) => Ok(condition),
+                Err(_) => Err(String::from(
+                    "a condition needs exactly one of path_exists, path_absent, environment, file_not_empty, kernel_command_line, container and architecture",
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_conditions() {
+        assert!(Condition::PathExists(std::path::PathBuf::from("/")).holds());
+        assert!(Condition::PathAbsent(std::path::PathBuf::from("/nonexistent")).holds());
+        assert!(
+            Condition::Environment {
+                name: String::from("PATH"),
+                equals: None
+            }
+            .holds()
+        );
+        assert!(
+            !Condition::Environment {
+                name: String::from("PATH"),
+                equals: Some(String::from("/nonexistent"))
+            }
+            .holds()
+        );
+        assert!(!Condition::FileNotEmpty(std::path::PathBuf::from("/dev/null")).holds());
+        assert!(Condition::Architecture(std::env::consts::ARCH.to_string()).holds());
+        assert_ne!(
+            Condition::Container(true).holds(),
+            Condition::Container(false).holds()
+        );
+
+        let command_line = "BOOT_IMAGE=/vmlinuz root=/dev/sda1 quiet debug\n";
+        assert!(has_flag(command_line, "debug"));
+        assert!(has_flag(command_line, "root"));
+        assert!(!has_flag(command_line, "roo"));
+        assert!(!has_flag(command_line, "/dev/sda1"));
+    }
+}
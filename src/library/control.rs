@@ -0,0 +1,169 @@
+//! Contains the protocol of the control socket through which `sysinitctl`
+//! talks to a running `sysinitd`
+//!
+//! The protocol is line-based: a client connects, sends a single
+//! [`Request`] terminated by a newline and reads the [`Response`] until
+//! `sysinitd` closes the connection. The first line of a response is
+//! either `ok` or `error: <MESSAGE>`; all following lines are the output
+//! of the request.
+
+/// The path of the control socket if none is given
+pub const DEFAULT_SOCKET_PATH: &str = "/run/sysinitd.sock";
+
+/// The environment variable that overrides [`DEFAULT_SOCKET_PATH`]
+pub const SOCKET_PATH_ENVIRONMENT_VARIABLE: &str = "SYSINITD_CONTROL_SOCKET";
+
+/// Something a client asks `sysinitd` to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Report the state of all services
+    Status,
+    /// Start the service with the contained ID
+    Start(String),
+    /// Switch to the contained target
+    Target(String),
+    /// Run the contained number of replicas of the replicated service with
+    /// the contained ID
+    Scale(String, usize),
+}
+
+impl std::str::FromStr for Request {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let request = match words.next() {
+            Some("status") => Self::Status,
+            Some("start") => match words.next() {
+                Some(id) => Self::Start(id.to_string()),
+                None => return Err(String::from("Missing service ID")),
+            },
+            Some("target") => match words.next() {
+                Some(target) => Self::Target(target.to_string()),
+                None => return Err(String::from("Missing target")),
+            },
+            Some("scale") => match (words.next(), words.next()) {
+                (Some(id), Some(replicas)) => match replicas.parse() {
+                    Ok(replicas) => Self::Scale(id.to_string(), replicas),
+                    Err(_) => return Err(format!("Invalid number of replicas '{replicas}'")),
+                },
+                (Some(_), None) => return Err(String::from("Missing number of replicas")),
+                (None, _) => return Err(String::from("Missing service ID")),
+            },
+            Some(command) => return Err(format!("Unknown request '{command}'")),
+            None => return Err(String::from("Empty request")),
+        };
+        if let Some(argument) = words.next() {
+            return Err(format!("Unexpected argument '{argument}'"));
+        }
+        Ok(request)
+    }
+}
+
+impl std::fmt::Display for Request {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Status => write!(formatter, "status"),
+            Self::Start(id) => write!(formatter, "start {id}"),
+            Self::Target(target) => write!(formatter, "target {target}"),
+            Self::Scale(id, replicas) => write!(formatter, "scale {id} {replicas}"),
+        }
+    }
+}
+
+/// The answer of `sysinitd` to a [`Request`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// The request succeeded with the contained output
+    Ok(String),
+    /// The request failed with the contained message
+    Error(String),
+}
+
+impl Response {
+    /// Parses a response as sent over the control socket
+    pub fn parse(content: &str) -> Self {
+        let (status, output) = content.split_once('\n').unwrap_or((content, ""));
+        match status.strip_prefix("error: ") {
+            Some(message) => Self::Error(message.to_string()),
+            None if status == "ok" => Self::Ok(output.to_string()),
+            None => Self::Error(format!("Malformed response '{status}'")),
+        }
+    }
+}
+
+impl std::fmt::Display for Response {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok(output) => write!(formatter, "ok\n{output}"),
+            Self::Error(message) => writeln!(formatter, "error: {message}"),
+        }
+    }
+}
+
+/// Sends `request` to the `sysinitd` listening on `socket_path` and
+/// returns its response
+pub async fn request(
+    socket_path: &std::path::Path,
+    request: &Request,
+) -> std::io::Result<Response> {
+    use ::tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    let mut stream = ::tokio::net::UnixStream::connect(socket_path).await?;
+    stream.write_all(format!("{request}\n").as_bytes()).await?;
+    stream.shutdown().await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(Response::parse(&response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_requests_and_responses() {
+        assert_eq!("status\n".parse(), Ok(Request::Status));
+        assert_eq!(
+            "restart".parse::<Request>(),
+            Err(String::from("Unknown request 'restart'"))
+        );
+        assert!("status now".parse::<Request>().is_err());
+        assert_eq!(
+            "start cron\n".parse(),
+            Ok(Request::Start(String::from("cron")))
+        );
+        assert_eq!(
+            "start".parse::<Request>(),
+            Err(String::from("Missing service ID"))
+        );
+        assert_eq!(
+            "target debug".parse(),
+            Ok(Request::Target(String::from("debug")))
+        );
+        assert_eq!(
+            "scale worker 8".parse(),
+            Ok(Request::Scale(String::from("worker"), 8))
+        );
+        assert_eq!(
+            "scale worker -1".parse::<Request>(),
+            Err(String::from("Invalid number of replicas '-1'"))
+        );
+        assert_eq!(
+            "scale worker".parse::<Request>(),
+            Err(String::from("Missing number of replicas"))
+        );
+        let request = Request::Scale(String::from("worker"), 3);
+        assert_eq!(request.to_string().parse(), Ok(request));
+
+        for response in [
+            Response::Ok(String::from("a: running\nb: failed\n")),
+            Response::Ok(String::new()),
+            Response::Error(String::from("Unknown request 'restart'")),
+        ] {
+            assert_eq!(Response::parse(&response.to_string()), response);
+        }
+        assert!(matches!(Response::parse(""), Response::Error(_)));
+    }
+}
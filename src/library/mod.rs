@@ -1,4 +1,14 @@
 //! TODO
 
 pub mod arguments;
+pub mod capability;
+pub mod condition;
+pub mod control;
+pub mod notify;
+pub mod process;
+pub mod schedule;
 pub mod service;
+pub mod signal;
+pub mod supervisor;
+pub mod syscall;
+pub mod trigger;
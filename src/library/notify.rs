@@ -0,0 +1,211 @@
+//! Contains the `sd_notify(3)`-compatible protocol through which services
+//! of type `notify` report their readiness and status
+//!
+//! Every notify service gets a datagram socket of its own in the notify
+//! directory, whose path is passed in `NOTIFY_SOCKET`. Each datagram
+//! consists of newline-separated `KEY=VALUE` assignments; unknown keys are
+//! ignored, as `sd_notify(3)` clients may send more than `sysinitd`
+//! understands. The kernel attaches the PID of the sender to every
+//! datagram (`SO_PASSCRED`), so that datagrams of processes that do not
+//! belong to the service can be dropped.
+
+/// The directory notify sockets are created in if none is given
+pub const DEFAULT_DIRECTORY: &str = "/run/sysinitd/notify";
+
+/// The environment variable that overrides [`DEFAULT_DIRECTORY`]
+pub const DIRECTORY_ENVIRONMENT_VARIABLE: &str = "SYSINITD_NOTIFY_DIRECTORY";
+
+/// The environment variable through which services learn the path of
+/// their notify socket
+pub const SOCKET_ENVIRONMENT_VARIABLE: &str = "NOTIFY_SOCKET";
+
+/// The environment variable through which services with a watchdog learn
+/// its interval in microseconds
+pub const WATCHDOG_ENVIRONMENT_VARIABLE: &str = "WATCHDOG_USEC";
+
+/// The maximum size of a datagram that is read
+const MAXIMUM_DATAGRAM_SIZE: usize = 4096;
+
+/// Something a service reported via its notify socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    /// `READY=1`: the service finished starting up or reloading
+    Ready,
+    /// `RELOADING=1`: the service is reloading its configuration
+    Reloading,
+    /// `STOPPING=1`: the service is shutting down
+    Stopping,
+    /// `STATUS=...`: a human-readable description of the service's state
+    Status(String),
+    /// `MAINPID=...`: the process that is to be supervised instead of the
+    /// one `sysinitd` spawned
+    MainPid(::libc::pid_t),
+    /// `WATCHDOG=1`: the service is still alive
+    Watchdog,
+}
+
+/// Parses the notifications contained in a datagram
+pub fn parse(datagram: &[u8]) -> Vec<Notification> {
+    String::from_utf8_lossy(datagram)
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            match (key, value) {
+                ("READY", "1") => Some(Notification::Ready),
+                ("RELOADING", "1") => Some(Notification::Reloading),
+                ("STOPPING", "1") => Some(Notification::Stopping),
+                ("STATUS", status) => Some(Notification::Status(status.to_string())),
+                ("MAINPID", pid) => pid
+                    .parse()
+                    .ok()
+                    .filter(|pid| *pid > 0)
+                    .map(Notification::MainPid),
+                ("WATCHDOG", "1") => Some(Notification::Watchdog),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Creates the notify socket at `path`, owned by `uid` and `gid` so that
+/// services running with other credentials can write to it
+///
+/// A stale socket file is replaced. Must be called from within a
+/// [`::tokio`] runtime.
+pub fn bind(
+    path: &std::path::Path,
+    uid: Option<::libc::uid_t>,
+    gid: Option<::libc::gid_t>,
+) -> std::io::Result<::tokio::net::UnixDatagram> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    match std::fs::remove_file(path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error),
+        _ => {}
+    }
+
+    let socket = ::tokio::net::UnixDatagram::bind(path)?;
+    let enable: ::libc::c_int = 1;
+    // SAFETY: `enable` is valid for the duration of the call
+    if unsafe {
+        ::libc::setsockopt(
+            std::os::fd::AsRawFd::as_raw_fd(&socket),
+            ::libc::SOL_SOCKET,
+            ::libc::SO_PASSCRED,
+            (&raw const enable).cast(),
+            std::mem::size_of::<::libc::c_int>() as ::libc::socklen_t,
+        )
+    } == -1
+    {
+        return Err(std::io::Error::last_os_error());
+    }
+    std::os::unix::fs::chown(path, uid, gid)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660))?;
+    Ok(socket)
+}
+
+/// Receives the next datagram on `socket` and parses it
+///
+/// Returns the PID of the sender along with the notifications, which is
+/// only known on sockets created by [`bind`]. File descriptors sent along
+/// (e.g. by `FDSTORE=1`) are closed right away.
+pub async fn receive(
+    socket: &::tokio::net::UnixDatagram,
+) -> std::io::Result<(Option<::libc::pid_t>, Vec<Notification>)> {
+    let mut buffer = [0; MAXIMUM_DATAGRAM_SIZE];
+    let (length, sender) = socket
+        .async_io(::tokio::io::Interest::READABLE, || {
+            receive_with_credentials(std::os::fd::AsRawFd::as_raw_fd(socket), &mut buffer)
+        })
+        .await?;
+    Ok((sender, parse(&buffer[..length])))
+}
+
+/// Receives a datagram on `socket` without blocking and returns its length
+/// and the PID in its `SCM_CREDENTIALS`, if there are any
+fn receive_with_credentials(
+    socket: std::os::fd::RawFd,
+    buffer: &mut [u8],
+) -> std::io::Result<(usize, Option<::libc::pid_t>)> {
+    // `u64` aligns the buffer suitably for `cmsghdr`
+    let mut control = [0_u64; 64];
+    let mut vector = ::libc::iovec {
+        iov_base: buffer.as_mut_ptr().cast(),
+        iov_len: buffer.len(),
+    };
+    // SAFETY: `msghdr` is a plain C struct for which all zeros is valid
+    let mut message: ::libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &raw mut vector;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    // SAFETY: `message` and the buffers it points to are valid for the
+    // duration of the call
+    let length = unsafe {
+        ::libc::recvmsg(
+            socket,
+            &raw mut message,
+            ::libc::MSG_DONTWAIT | ::libc::MSG_CMSG_CLOEXEC,
+        )
+    };
+    if length == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut sender = None;
+    // SAFETY: the kernel filled the control buffer with valid control
+    // messages, whose data lies within the buffer
+    unsafe {
+        let mut header = ::libc::CMSG_FIRSTHDR(&raw const message);
+        while !header.is_null() {
+            let data = ::libc::CMSG_DATA(header);
+            let data_length = (*header).cmsg_len as usize - ::libc::CMSG_LEN(0) as usize;
+            match ((*header).cmsg_level, (*header).cmsg_type) {
+                (::libc::SOL_SOCKET, ::libc::SCM_CREDENTIALS) => {
+                    let credentials: ::libc::ucred = std::ptr::read_unaligned(data.cast());
+                    sender = Some(credentials.pid);
+                }
+                (::libc::SOL_SOCKET, ::libc::SCM_RIGHTS) => {
+                    for index in 0..data_length / std::mem::size_of::<::libc::c_int>() {
+                        ::libc::close(std::ptr::read_unaligned(
+                            data.cast::<::libc::c_int>().add(index),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+            header = ::libc::CMSG_NXTHDR(&raw const message, header);
+        }
+    }
+    Ok((length as usize, sender))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_notifications() {
+        assert_eq!(
+            parse(b"READY=1\nSTATUS=Accepting connections\nMAINPID=42\n"),
+            [
+                Notification::Ready,
+                Notification::Status(String::from("Accepting connections")),
+                Notification::MainPid(42)
+            ]
+        );
+        assert_eq!(
+            parse(b"RELOADING=1\nMONOTONIC_USEC=1\nSTOPPING=1\nWATCHDOG=1"),
+            [
+                Notification::Reloading,
+                Notification::Stopping,
+                Notification::Watchdog
+            ]
+        );
+        assert!(parse(b"READY=0\nMAINPID=-1\nMAINPID=x\ngarbage").is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! Contains [`CapabilitySetup`], the part of [`super::setup::ChildSetup`]
+//! that restricts the capabilities of a new process
+//!
+//! Capabilities are applied in two steps around dropping credentials:
+//! the bounding set can only be reduced while `CAP_SETPCAP` is still
+//! held, and the ambient set can only be raised once the process runs
+//! as its final user.
+//!
+//! Dropping all capabilities also sets the `SECBIT_NOROOT` and
+//! `SECBIT_NO_SETUID_FIXUP` securebits (see `capabilities(7)`), as
+//! `execve(2)` would otherwise grant `root` its bounding set again.
+
+use crate::library::{capability::Capability, service::Service};
+
+/// `_LINUX_CAPABILITY_VERSION_3`, which uses two [`CapabilityData`]
+const CAPABILITY_VERSION: u32 = 0x2008_0522;
+
+/// `struct __user_cap_header_struct` of `capget(2)` and `capset(2)`
+#[repr(C)]
+struct CapabilityHeader {
+    /// The version of the interface
+    version: u32,
+    /// The process to operate on (0 is the calling process)
+    pid: ::libc::c_int,
+}
+
+/// `struct __user_cap_data_struct` of `capget(2)` and `capset(2)`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct CapabilityData {
+    /// The effective set
+    effective: u32,
+    /// The permitted set
+    permitted: u32,
+    /// The inheritable set
+    inheritable: u32,
+}
+
+/// The securebits that keep `root` from regaining capabilities, locked so
+/// that the service can not unset them
+const NOROOT_SECUREBITS: ::libc::c_int = ::libc::SECBIT_NOROOT
+    | ::libc::SECBIT_NOROOT_LOCKED
+    | ::libc::SECBIT_NO_SETUID_FIXUP
+    | ::libc::SECBIT_NO_SETUID_FIXUP_LOCKED;
+
+/// Returns a bit mask containing `capabilities`
+fn mask<'a>(capabilities: impl IntoIterator<Item = &'a Capability>) -> u64 {
+    capabilities
+        .into_iter()
+        .fold(0, |mask, capability| mask | 1 << capability.as_raw())
+}
+
+/// Whether `capability` is in the effective set of the calling process
+pub fn is_effective(capability: Capability) -> std::io::Result<bool> {
+    let mut header = CapabilityHeader {
+        version: CAPABILITY_VERSION,
+        pid: 0,
+    };
+    let mut data = [CapabilityData::default(); 2];
+    // SAFETY: `header` and `data` are valid for the duration of the call
+    if unsafe { ::libc::syscall(::libc::SYS_capget, &mut header, data.as_mut_ptr()) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let effective = u64::from(data[0].effective) | u64::from(data[1].effective) << 32;
+    Ok(effective & 1 << capability.as_raw() != 0)
+}
+
+/// How the capabilities of a new process are restricted
+#[derive(Debug)]
+pub struct CapabilitySetup {
+    /// The capabilities to drop from the bounding set
+    bounding_drops: Vec<u8>,
+    /// The capabilities to raise in the ambient set
+    ambient: Vec<u8>,
+    /// [`CapabilitySetup::ambient`] as a bit mask
+    ambient_mask: u64,
+    /// Whether all capabilities not in [`CapabilitySetup::ambient`] are
+    /// dropped from the permitted, effective and inheritable sets
+    drop_all: bool,
+    /// Whether capabilities must survive changing the user
+    keep_capabilities: bool,
+}
+
+impl CapabilitySetup {
+    /// Resolves the capability setup of `service`
+    ///
+    /// Returns [`None`] if the service does not restrict its capabilities.
+    pub fn new(service: &Service) -> Option<Self> {
+        let capabilities = service.capabilities();
+        if capabilities.is_empty() {
+            return None;
+        }
+
+        let kept = match capabilities.bounding() {
+            Some(bounding) => mask(bounding),
+            None if capabilities.drop_all() => mask(capabilities.ambient()),
+            None => u64::MAX,
+        };
+        let bounding_drops = Capability::all()
+            .filter(|capability| kept & 1 << capability.as_raw() == 0)
+            .map(|capability| capability.as_raw())
+            .collect();
+
+        Some(Self {
+            bounding_drops,
+            ambient: capabilities
+                .ambient()
+                .iter()
+                .map(Capability::as_raw)
+                .collect(),
+            ambient_mask: mask(capabilities.ambient()),
+            drop_all: capabilities.drop_all(),
+            keep_capabilities: service.user().is_some() && !capabilities.ambient().is_empty(),
+        })
+    }
+
+    /// Reduces the bounding set and sets the securebits; runs before
+    /// credentials are dropped
+    pub fn apply_before_credentials(&self) -> std::io::Result<()> {
+        for capability in &self.bounding_drops {
+            // SAFETY: `prctl(2)` with `PR_CAPBSET_DROP` has no memory safety preconditions
+            if unsafe {
+                ::libc::prctl(
+                    ::libc::PR_CAPBSET_DROP,
+                    ::libc::c_ulong::from(*capability),
+                    0,
+                    0,
+                    0,
+                )
+            } == -1
+            {
+                let error = std::io::Error::last_os_error();
+                // capabilities newer than the running kernel do not exist anyway
+                if error.raw_os_error() != Some(::libc::EINVAL) {
+                    return Err(error);
+                }
+            }
+        }
+
+        if self.drop_all {
+            // SAFETY: `prctl(2)` with `PR_SET_SECUREBITS` has no memory safety preconditions
+            if unsafe {
+                ::libc::prctl(
+                    ::libc::PR_SET_SECUREBITS,
+                    NOROOT_SECUREBITS as ::libc::c_ulong,
+                    0,
+                    0,
+                    0,
+                )
+            } == -1
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if self.keep_capabilities {
+            // SAFETY: `prctl(2)` with `PR_SET_KEEPCAPS` has no memory safety preconditions
+            if unsafe { ::libc::prctl(::libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Restricts the process sets and raises the ambient set; runs after
+    /// credentials were dropped
+    pub fn apply_after_credentials(&self) -> std::io::Result<()> {
+        let mut header = CapabilityHeader {
+            version: CAPABILITY_VERSION,
+            pid: 0,
+        };
+        let mut data = [CapabilityData::default(); 2];
+        // SAFETY: `header` and `data` are valid for the duration of the call
+        if unsafe { ::libc::syscall(::libc::SYS_capget, &mut header, data.as_mut_ptr()) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        for (index, data) in data.iter_mut().enumerate() {
+            let ambient = (self.ambient_mask >> (32 * index)) as u32;
+            if self.drop_all {
+                data.effective = ambient;
+                data.permitted = ambient;
+                data.inheritable = ambient;
+            } else {
+                // raising an ambient capability requires it to be
+                // permitted and inheritable
+                data.effective |= ambient & data.permitted;
+                data.inheritable |= ambient;
+            }
+        }
+        // SAFETY: `header` and `data` are valid for the duration of the call
+        if unsafe { ::libc::syscall(::libc::SYS_capset, &mut header, data.as_ptr()) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        for capability in &self.ambient {
+            // SAFETY: `prctl(2)` with `PR_CAP_AMBIENT` has no memory safety preconditions
+            if unsafe {
+                ::libc::prctl(
+                    ::libc::PR_CAP_AMBIENT,
+                    ::libc::PR_CAP_AMBIENT_RAISE,
+                    ::libc::c_ulong::from(*capability),
+                    0,
+                    0,
+                )
+            } == -1
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,536 @@
+//! Contains support for cgroup v2: every service gets a cgroup of its
+//! own below the cgroup of `sysinitd`
+//!
+//! This requires a delegated subtree, i.e. `sysinitd` must be allowed to
+//! create cgroups below its own one. That is the case in containers with
+//! a cgroup namespace, where the cgroup of `sysinitd` is the root of the
+//! namespace, or if the cgroup is owned by the user `sysinitd` runs as.
+//! Because cgroup v2 does not allow processes in cgroups that distribute
+//! resources to children, all processes of the cgroup are moved into the
+//! leaf cgroup [`SUPERVISOR_CGROUP`] first.
+
+/// Where the cgroup v2 hierarchy is mounted
+const MOUNT_POINT: &str = "/sys/fs/cgroup";
+/// The name of the leaf cgroup `sysinitd` moves itself into
+pub const SUPERVISOR_CGROUP: &str = "sysinitd";
+/// The controllers that are enabled for the cgroups of services
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+
+/// The delegated cgroup `sysinitd` manages, initialized by [`initialize`]
+static HIERARCHY: std::sync::OnceLock<Option<Hierarchy>> = std::sync::OnceLock::new();
+
+/// The delegated cgroup of `sysinitd` that contains the cgroups of services
+#[derive(Debug)]
+pub struct Hierarchy {
+    /// The path of the delegated cgroup
+    root: std::path::PathBuf,
+}
+
+/// Sets up the delegated cgroup of `sysinitd` if there is one
+///
+/// Returns the path of the delegated cgroup, or [`None`] if cgroup v2 is
+/// not mounted at [`MOUNT_POINT`] or `sysinitd` may not manage its cgroup.
+/// Only the first call has an effect.
+pub fn initialize() -> std::io::Result<Option<&'static std::path::Path>> {
+    if HIERARCHY.get().is_none() {
+        let _ = HIERARCHY.set(Hierarchy::delegated()?);
+    }
+    Ok(hierarchy().map(Hierarchy::root))
+}
+
+/// The delegated cgroup if [`initialize`] set it up
+pub fn hierarchy() -> Option<&'static Hierarchy> {
+    HIERARCHY.get().and_then(Option::as_ref)
+}
+
+/// Parses the cgroup v2 path from the contents of `/proc/<PID>/cgroup`
+fn own_cgroup_path(content: &str) -> Option<&str> {
+    content.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+/// Whether the cgroup at `root` was delegated to `sysinitd`: it is the root
+/// of the cgroup namespace, or it is owned by the effective user of
+/// `sysinitd`
+///
+/// Being allowed to write to the cgroup is not enough, as root may write to
+/// every cgroup, including those owned by another manager such as systemd.
+fn is_delegated(root: &std::path::Path) -> std::io::Result<bool> {
+    use std::os::unix::fs::MetadataExt as _;
+
+    if root == std::path::Path::new(MOUNT_POINT) {
+        return Ok(true);
+    }
+    // SAFETY: `geteuid` has no preconditions and can not fail
+    let euid = unsafe { ::libc::geteuid() };
+    Ok(std::fs::metadata(root)?.uid() == euid)
+}
+
+impl Hierarchy {
+    /// Determines and prepares the delegated cgroup of `sysinitd`
+    fn delegated() -> std::io::Result<Option<Self>> {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let mount_point = std::ffi::CString::new(MOUNT_POINT.as_bytes())?;
+        // SAFETY: `statfs` is a plain C struct for which all zeros is valid
+        let mut statfs: ::libc::statfs = unsafe { std::mem::zeroed() };
+        // SAFETY: `mount_point` is a valid C string and `statfs` is valid for writes
+        if unsafe { ::libc::statfs(mount_point.as_ptr(), &mut statfs) } == -1
+            // the types of both differ between architectures
+            || i128::from(statfs.f_type) != i128::from(::libc::CGROUP2_SUPER_MAGIC)
+        {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string("/proc/self/cgroup")?;
+        let Some(path) = own_cgroup_path(&content) else {
+            return Ok(None);
+        };
+        let mut root = std::path::PathBuf::from(MOUNT_POINT).join(path.trim_start_matches('/'));
+        // when `sysinitd` is restarted, it still lives in its leaf cgroup
+        if root
+            .file_name()
+            .is_some_and(|name| name.as_bytes() == SUPERVISOR_CGROUP.as_bytes())
+        {
+            root.pop();
+        }
+
+        if !is_delegated(&root)? {
+            ::tracing::debug!(
+                "Cgroup '{}' is neither the root of the cgroup namespace nor owned by the user of sysinitd",
+                root.display()
+            );
+            return Ok(None);
+        }
+
+        // even a delegated subtree may not be writable, e.g. across a
+        // read-only mount
+        let hierarchy = Self { root };
+        match hierarchy.enable_controllers() {
+            Ok(()) => Ok(Some(hierarchy)),
+            Err(error)
+                if matches!(
+                    error.raw_os_error(),
+                    Some(::libc::EACCES | ::libc::EPERM | ::libc::EROFS)
+                ) =>
+            {
+                ::tracing::debug!(
+                    "Cgroup '{}' is not delegated: {error}",
+                    hierarchy.root.display()
+                );
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The path of the delegated cgroup
+    pub fn root(&self) -> &std::path::Path {
+        &self.root
+    }
+
+    /// Moves all processes of the delegated cgroup into [`SUPERVISOR_CGROUP`]
+    /// and enables [`CONTROLLERS`] for the cgroups of services
+    fn enable_controllers(&self) -> std::io::Result<()> {
+        let leaf = Cgroup::create(&self.root, SUPERVISOR_CGROUP)?;
+        let own_pid = std::process::id() as ::libc::pid_t;
+        for pid in Cgroup::at(self.root.clone()).pids() {
+            let result = std::fs::write(leaf.path.join("cgroup.procs"), pid.to_string());
+            // other processes may have exited in the meantime
+            if pid == own_pid {
+                result?;
+            }
+        }
+
+        let available = std::fs::read_to_string(self.root.join("cgroup.controllers"))?;
+        let controllers: Vec<String> = CONTROLLERS
+            .iter()
+            .filter(|controller| {
+                available
+                    .split_whitespace()
+                    .any(|name| name == **controller)
+            })
+            .map(|controller| format!("+{controller}"))
+            .collect();
+        if !controllers.is_empty() {
+            std::fs::write(
+                self.root.join("cgroup.subtree_control"),
+                controllers.join(" "),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Creates (or reuses) the cgroup of the service with ID `service_id`
+    pub fn create(&self, service_id: &str) -> std::io::Result<Cgroup> {
+        // `/` would create a nested cgroup and `sysinitd` is taken
+        let name = match service_id.replace('/', "_") {
+            name if name == SUPERVISOR_CGROUP => format!("{name}_"),
+            name => name,
+        };
+        Cgroup::create(&self.root, &name)
+    }
+}
+
+/// The accounting data of a cgroup
+///
+/// Values the kernel does not provide (e.g. because a controller is not
+/// enabled) are [`None`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Statistics {
+    /// The current memory usage in bytes (`memory.current`)
+    pub memory: Option<u64>,
+    /// The CPU time consumed (`usage_usec` of `cpu.stat`)
+    pub cpu: Option<std::time::Duration>,
+    /// The current number of processes (`pids.current`)
+    pub pids: Option<u64>,
+    /// How often a process was killed by the OOM killer (`oom_kill`
+    /// of `memory.events`)
+    pub oom_kills: Option<u64>,
+}
+
+impl std::fmt::Display for Statistics {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut separator = "";
+        if let Some(memory) = self.memory {
+            write!(formatter, "memory={memory}")?;
+            separator = " ";
+        }
+        if let Some(cpu) = self.cpu {
+            write!(
+                formatter,
+                "{separator}cpu={}",
+                ::humantime::format_duration(cpu)
+            )?;
+            separator = " ";
+        }
+        if let Some(pids) = self.pids {
+            write!(formatter, "{separator}pids={pids}")?;
+            separator = " ";
+        }
+        if let Some(oom_kills) = self.oom_kills {
+            write!(formatter, "{separator}oom_kills={oom_kills}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the value of `key` in a flat keyed file such as `memory.events`
+fn keyed_value(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// The cgroup of a service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cgroup {
+    /// The path of the cgroup in the cgroup file system
+    path: std::path::PathBuf,
+}
+
+impl Cgroup {
+    /// Refers to the cgroup at `path`
+    fn at(path: std::path::PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Creates the cgroup `name` below `parent` unless it exists already
+    fn create(parent: &std::path::Path, name: &str) -> std::io::Result<Self> {
+        let path = parent.join(name);
+        match std::fs::create_dir(&path) {
+            Ok(()) => Ok(Self::at(path)),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => Ok(Self::at(path)),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The path of the cgroup in the cgroup file system
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Writes the settings of a service (e.g. `memory.max`) to the cgroup
+    pub fn configure(
+        &self,
+        settings: &crate::library::service::CgroupSettings,
+    ) -> std::io::Result<()> {
+        for (file, value) in settings.files() {
+            let path = self.path.join(file);
+            if !path.exists() {
+                let controller = file.split('.').next().unwrap_or(file);
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!(
+                        "Could not set {file} because the {controller} controller is not available"
+                    ),
+                ));
+            }
+            std::fs::write(path, &value).map_err(|error| {
+                std::io::Error::new(
+                    error.kind(),
+                    format!("Could not set {file} to '{value}': {error}"),
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Opens `cgroup.procs` for writing
+    ///
+    /// Writing `0` to the returned file moves the writing process into
+    /// the cgroup, which a new process does before `exec(2)`.
+    pub fn open_procs(&self) -> std::io::Result<std::os::fd::OwnedFd> {
+        Ok(std::fs::OpenOptions::new()
+            .write(true)
+            .open(self.path.join("cgroup.procs"))?
+            .into())
+    }
+
+    /// The IDs of all processes in the cgroup
+    pub fn pids(&self) -> Vec<::libc::pid_t> {
+        std::fs::read_to_string(self.path.join("cgroup.procs"))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect()
+    }
+
+    /// Starts watching `memory.events` of the cgroup for changes, e.g.
+    /// because a process was killed by the OOM killer
+    ///
+    /// Must be called from within a [`::tokio`] runtime.
+    pub fn watch_events(&self) -> std::io::Result<EventsWatcher> {
+        EventsWatcher::new(&self.path.join("memory.events"))
+    }
+
+    /// Whether the process `pid` is in the cgroup
+    pub fn contains(&self, pid: ::libc::pid_t) -> bool {
+        std::fs::read_to_string(format!("/proc/{pid}/cgroup")).is_ok_and(|content| {
+            own_cgroup_path(&content).is_some_and(|path| {
+                std::path::Path::new(MOUNT_POINT).join(path.trim_start_matches('/')) == self.path
+            })
+        })
+    }
+
+    /// How often a process of the cgroup was killed by the OOM killer
+    pub fn oom_kills(&self) -> u64 {
+        std::fs::read_to_string(self.path.join("memory.events"))
+            .ok()
+            .and_then(|content| keyed_value(&content, "oom_kill"))
+            .unwrap_or_default()
+    }
+
+    /// Reads the accounting data of the cgroup
+    pub fn statistics(&self) -> Statistics {
+        let read = |file: &str| std::fs::read_to_string(self.path.join(file)).ok();
+        Statistics {
+            memory: read("memory.current").and_then(|content| content.trim().parse().ok()),
+            cpu: read("cpu.stat")
+                .and_then(|content| keyed_value(&content, "usage_usec"))
+                .map(std::time::Duration::from_micros),
+            pids: read("pids.current").and_then(|content| content.trim().parse().ok()),
+            oom_kills: read("memory.events").and_then(|content| keyed_value(&content, "oom_kill")),
+        }
+    }
+
+    /// Kills all processes in the cgroup with `SIGKILL`
+    ///
+    /// Uses `cgroup.kill` (available since Linux 5.14), which also catches
+    /// processes forked concurrently, and falls back to killing all
+    /// processes listed in `cgroup.procs`.
+    pub fn kill(&self) -> std::io::Result<()> {
+        if std::fs::write(self.path.join("cgroup.kill"), "1").is_ok() {
+            return Ok(());
+        }
+        for pid in self.pids() {
+            // SAFETY: `kill(2)` has no memory safety preconditions
+            unsafe { ::libc::kill(pid, ::libc::SIGKILL) };
+        }
+        Ok(())
+    }
+
+    /// Removes the cgroup, which fails while it still contains processes
+    pub fn remove(&self) -> std::io::Result<()> {
+        std::fs::remove_dir(&self.path)
+    }
+}
+
+/// Watches `memory.events` of a cgroup via `inotify(7)`, which the
+/// kernel notifies whenever one of its counters changes
+#[derive(Debug)]
+pub struct EventsWatcher {
+    /// The `inotify(7)` instance
+    inotify: ::tokio::io::unix::AsyncFd<std::os::fd::OwnedFd>,
+}
+
+impl EventsWatcher {
+    /// Starts watching `path`
+    fn new(path: &std::path::Path) -> std::io::Result<Self> {
+        use std::os::{fd::FromRawFd as _, unix::ffi::OsStrExt as _};
+
+        // SAFETY: `inotify_init1(2)` has no memory safety preconditions
+        let fd = unsafe { ::libc::inotify_init1(::libc::IN_NONBLOCK | ::libc::IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just opened and is owned by nobody else
+        let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) };
+
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+        // SAFETY: `c_path` is a valid C string
+        let descriptor = unsafe {
+            ::libc::inotify_add_watch(
+                std::os::fd::AsRawFd::as_raw_fd(&fd),
+                c_path.as_ptr(),
+                ::libc::IN_MODIFY,
+            )
+        };
+        if descriptor == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // SAFETY: the `AsyncFd` owns the file descriptor, which thus stays
+        // open for as long as it is registered
+        let inotify = unsafe {
+            ::tokio::io::unix::AsyncFd::register_with_interest(fd, ::tokio::io::Interest::READABLE)?
+        };
+        Ok(Self { inotify })
+    }
+
+    /// Waits until the watched file changed, and returns `false` instead
+    /// once the cgroup was removed
+    pub async fn changed(&self) -> std::io::Result<bool> {
+        use std::os::fd::AsRawFd as _;
+
+        let mut buffer = [0_u8; 1024];
+        let length = loop {
+            let mut guard = self.inotify.readable().await?;
+            let result = guard.try_io(|inotify| {
+                // SAFETY: `buffer` is valid for writes of its length
+                let length = unsafe {
+                    ::libc::read(
+                        inotify.as_raw_fd(),
+                        buffer.as_mut_ptr().cast(),
+                        buffer.len(),
+                    )
+                };
+                if length == -1 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(length as usize)
+                }
+            });
+            if let Ok(result) = result {
+                break result?;
+            }
+        };
+
+        let header_size = std::mem::size_of::<::libc::inotify_event>();
+        let mut offset = 0;
+        while offset + header_size <= length {
+            // SAFETY: the kernel only writes complete events, and the
+            // header lies within `buffer`
+            let event: ::libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr().cast()) };
+            // the watch is removed together with the file
+            if event.mask & ::libc::IN_IGNORED != 0 {
+                return Ok(false);
+            }
+            offset += header_size + event.len as usize;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cgroup_files() {
+        assert_eq!(
+            own_cgroup_path("12:pids:/docker/abc\n0::/docker/abc\n"),
+            Some("/docker/abc")
+        );
+        assert_eq!(own_cgroup_path("12:pids:/\n"), None);
+        assert!(is_delegated(std::path::Path::new(MOUNT_POINT)).unwrap());
+
+        let directory =
+            std::env::temp_dir().join(format!("sysinitd-cgroup-{}", std::process::id()));
+        let cgroup = Cgroup::create(
+            &std::env::temp_dir(),
+            directory.file_name().unwrap().to_str().unwrap(),
+        )
+        .expect("Could not create fake cgroup");
+        std::fs::write(cgroup.path().join("memory.current"), "4096\n").unwrap();
+        std::fs::write(
+            cgroup.path().join("cpu.stat"),
+            "usage_usec 1500000\nuser_usec 1000000\n",
+        )
+        .unwrap();
+        std::fs::write(
+            cgroup.path().join("memory.events"),
+            "low 0\nhigh 2\nmax 3\noom 1\noom_kill 1\noom_group_kill 0\n",
+        )
+        .unwrap();
+        std::fs::write(cgroup.path().join("cgroup.procs"), "42\n43\n").unwrap();
+
+        let statistics = cgroup.statistics();
+        assert_eq!(
+            statistics,
+            Statistics {
+                memory: Some(4096),
+                cpu: Some(std::time::Duration::from_millis(1500)),
+                pids: None,
+                oom_kills: Some(1),
+            }
+        );
+        assert_eq!(
+            statistics.to_string(),
+            "memory=4096 cpu=1s 500ms oom_kills=1"
+        );
+        assert_eq!(cgroup.oom_kills(), 1);
+        assert_eq!(cgroup.pids(), vec![42, 43]);
+
+        // cgroups owned by another user belong to another manager
+        assert!(is_delegated(cgroup.path()).unwrap());
+        // SAFETY: `geteuid` has no preconditions and can not fail
+        if unsafe { ::libc::geteuid() } == 0 {
+            std::os::unix::fs::chown(cgroup.path(), Some(65534), None).unwrap();
+            assert!(!is_delegated(cgroup.path()).unwrap());
+        }
+
+        std::fs::remove_dir_all(cgroup.path()).unwrap();
+    }
+
+    #[test]
+    fn cgroup_settings() {
+        let service = crate::library::service::Service::serde_from_slice(
+            b"meta: { version: 0.1.0 }\nid: test\nstart: { command: _ }\ncgroup: { memory.max: 512M, memory.high: 402653184, cpu.max: max 100000, cpu.weight: 50, pids.max: max }",
+            std::path::Path::new("test.yaml"),
+        )
+        .expect("Could not parse service definition");
+        assert_eq!(
+            service.cgroup().files(),
+            vec![
+                ("memory.max", String::from("536870912")),
+                ("memory.high", String::from("402653184")),
+                ("cpu.max", String::from("max 100000")),
+                ("cpu.weight", String::from("50")),
+                ("pids.max", String::from("max")),
+            ]
+        );
+
+        for invalid in ["cpu.weight: 0", "cpu.max: 1 2 3", "memory.max: 1X"] {
+            assert!(
+                crate::library::service::Service::serde_from_slice(
+                    format!("meta: {{ version: 0.1.0 }}\nid: test\nstart: {{ command: _ }}\ncgroup: {{ {invalid} }}").as_bytes(),
+                    std::path::Path::new("test.yaml"),
+                )
+                .is_err(),
+                "'{invalid}' must be rejected"
+            );
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Contains the resolution of users and groups given in service
+//! definitions to numeric IDs via `/etc/passwd` and `/etc/group`
+//!
+//! The files are parsed directly (instead of using NSS) because
+//! `sysinitd` is linked statically.
+
+use crate::library::service::{NameOrId, Service};
+
+/// The path to the user database
+const PASSWD_PATH: &str = "/etc/passwd";
+/// The path to the group database
+const GROUP_PATH: &str = "/etc/group";
+
+/// Errors that occur while resolving users and groups
+#[derive(Debug, ::thiserror::Error)]
+pub enum CredentialsError {
+    /// A user name is not in the user database
+    #[error("User '{0}' does not exist")]
+    UnknownUser(String),
+    /// A group name is not in the group database
+    #[error("Group '{0}' does not exist")]
+    UnknownGroup(String),
+    /// A database could not be read
+    #[error("Could not read '{path}'")]
+    Io {
+        /// The path of the database
+        path: &'static str,
+        /// The underlying error
+        source: std::io::Error,
+    },
+}
+
+/// The numeric credentials a service runs with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    /// The user ID
+    pub uid: Option<::libc::uid_t>,
+    /// The primary group ID
+    pub gid: Option<::libc::gid_t>,
+    /// The supplementary group IDs
+    pub groups: Vec<::libc::gid_t>,
+}
+
+/// An entry of `/etc/passwd`
+struct User<'a> {
+    /// The user name
+    name: &'a str,
+    /// The user ID
+    uid: ::libc::uid_t,
+    /// The primary group ID
+    gid: ::libc::gid_t,
+}
+
+/// An entry of `/etc/group`
+struct Group<'a> {
+    /// The group name
+    name: &'a str,
+    /// The group ID
+    gid: ::libc::gid_t,
+    /// The names of the users that are members of the group
+    members: Vec<&'a str>,
+}
+
+/// Parses the entries of `/etc/passwd`
+fn users(passwd: &str) -> impl Iterator<Item = User<'_>> {
+    passwd.lines().filter_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let _password = fields.next()?;
+        Some(User {
+            name,
+            uid: fields.next()?.parse().ok()?,
+            gid: fields.next()?.parse().ok()?,
+        })
+    })
+}
+
+/// Parses the entries of `/etc/group`
+fn groups(group: &str) -> impl Iterator<Item = Group<'_>> {
+    group.lines().filter_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let _password = fields.next()?;
+        Some(Group {
+            name,
+            gid: fields.next()?.parse().ok()?,
+            members: fields
+                .next()
+                .unwrap_or_default()
+                .split(',')
+                .filter(|member| !member.is_empty())
+                .collect(),
+        })
+    })
+}
+
+/// Reads a database lazily, i.e. only once it is needed
+struct Database {
+    /// The path to the database
+    path: &'static str,
+    /// The contents of the database once read
+    content: Option<String>,
+}
+
+impl Database {
+    /// Creates a database that is read from `path` on first use
+    fn new(path: &'static str) -> Self {
+        Self {
+            path,
+            content: None,
+        }
+    }
+
+    /// Returns the contents of the database
+    fn content(&mut self) -> Result<&str, CredentialsError> {
+        if self.content.is_none() {
+            let content =
+                std::fs::read_to_string(self.path).map_err(|source| CredentialsError::Io {
+                    path: self.path,
+                    source,
+                })?;
+            self.content = Some(content);
+        }
+        Ok(self.content.as_deref().unwrap_or_default())
+    }
+}
+
+/// Resolves the user, group and supplementary groups of `service`
+///
+/// Returns [`None`] if the service does not change its credentials.
+pub fn resolve(service: &Service) -> Result<Option<Credentials>, CredentialsError> {
+    resolve_with(
+        service,
+        &mut Database::new(PASSWD_PATH),
+        &mut Database::new(GROUP_PATH),
+    )
+}
+
+/// Resolves the credentials of `service` using the given databases
+///
+/// - A user given by name is looked up in the user database. Unless a
+///   group is given explicitly, the primary group of the user is used
+///   and the user becomes a member of all groups that list it in the
+///   group database (as `getgrouplist(3)` would determine them).
+/// - A numeric user ID does not need to exist. If it does, it is treated
+///   like the name it has in the user database. If it does not and no
+///   group is given, the primary group ID is 0 (as with `docker run --user`).
+/// - Whenever the user or group is changed, the supplementary groups are
+///   set to exactly the ones determined above plus the ones given explicitly.
+fn resolve_with(
+    service: &Service,
+    passwd: &mut Database,
+    group: &mut Database,
+) -> Result<Option<Credentials>, CredentialsError> {
+    if service.user().is_none()
+        && service.group().is_none()
+        && service.supplementary_groups().is_empty()
+    {
+        return Ok(None);
+    }
+
+    let mut credentials = Credentials {
+        uid: None,
+        gid: None,
+        groups: vec![],
+    };
+
+    let resolve_group =
+        |group_database: &mut Database, name_or_id: &NameOrId| match name_or_id.as_id() {
+            Some(gid) => Ok(gid),
+            None => groups(group_database.content()?)
+                .find(|group| group.name == name_or_id.to_string())
+                .map(|group| group.gid)
+                .ok_or_else(|| CredentialsError::UnknownGroup(name_or_id.to_string())),
+        };
+
+    if let Some(user) = service.user() {
+        let entry = match user.as_id() {
+            Some(uid) => users(passwd.content().unwrap_or_default())
+                .find(|entry| entry.uid == uid)
+                .map(|entry| (entry.name.to_string(), entry.uid, entry.gid)),
+            None => {
+                let name = user.to_string();
+                let entry = users(passwd.content()?)
+                    .find(|entry| entry.name == name)
+                    .map(|entry| (entry.name.to_string(), entry.uid, entry.gid))
+                    .ok_or(CredentialsError::UnknownUser(name))?;
+                Some(entry)
+            }
+        };
+        match entry {
+            Some((name, uid, gid)) => {
+                credentials.uid = Some(uid);
+                credentials.gid = Some(gid);
+                if service.group().is_none() {
+                    credentials.groups = groups(group.content()?)
+                        .filter(|group| group.members.contains(&name.as_str()))
+                        .map(|group| group.gid)
+                        .collect();
+                }
+            }
+            None => {
+                credentials.uid = user.as_id();
+                credentials.gid = Some(0);
+            }
+        }
+    }
+
+    if let Some(name_or_id) = service.group() {
+        credentials.gid = Some(resolve_group(group, name_or_id)?);
+    }
+
+    for name_or_id in service.supplementary_groups() {
+        let gid = resolve_group(group, name_or_id)?;
+        if !credentials.groups.contains(&gid) {
+            credentials.groups.push(gid);
+        }
+    }
+
+    Ok(Some(credentials))
+}
+
+impl Credentials {
+    /// Drops the credentials of the calling process to these credentials
+    ///
+    /// Called in the child process before `exec(2)`; only async-signal-safe
+    /// functions are used.
+    pub fn apply(&self) -> std::io::Result<()> {
+        // SAFETY: `self.groups` is valid for the duration of the call
+        if unsafe { ::libc::setgroups(self.groups.len(), self.groups.as_ptr()) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if let Some(gid) = self.gid {
+            // SAFETY: `setgid(2)` has no memory safety preconditions
+            if unsafe { ::libc::setgid(gid) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if let Some(uid) = self.uid {
+            // SAFETY: `setuid(2)` has no memory safety preconditions
+            if unsafe { ::libc::setuid(uid) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWD: &str = "root:x:0:0:root:/root:/bin/sh\napp:x:1000:1000::/home/app:/bin/sh\n";
+    const GROUP: &str = "root:x:0:\napp:x:1000:\nvideo:x:44:app,other\naudio:x:29:\n";
+
+    fn resolve_test(definition: &str) -> Result<Option<Credentials>, CredentialsError> {
+        let service = Service::serde_from_slice(
+            format!("meta: {{ version: 0.1.0 }}\nid: test\nstart: {{ command: _ }}\n{definition}")
+                .as_bytes(),
+            std::path::Path::new("test.yaml"),
+        )
+        .expect("Could not parse service definition");
+        let mut passwd = Database {
+            path: PASSWD_PATH,
+            content: Some(PASSWD.to_string()),
+        };
+        let mut group = Database {
+            path: GROUP_PATH,
+            content: Some(GROUP.to_string()),
+        };
+        resolve_with(&service, &mut passwd, &mut group)
+    }
+
+    #[test]
+    fn resolve_credentials() {
+        assert_eq!(resolve_test("").unwrap(), None);
+        assert_eq!(
+            resolve_test("user: app\nsupplementary_groups: [audio, 7]").unwrap(),
+            Some(Credentials {
+                uid: Some(1000),
+                gid: Some(1000),
+                groups: vec![44, 29, 7],
+            })
+        );
+        assert_eq!(
+            resolve_test("user: 2000").unwrap(),
+            Some(Credentials {
+                uid: Some(2000),
+                gid: Some(0),
+                groups: vec![],
+            })
+        );
+        assert_eq!(
+            resolve_test("user: 1000").unwrap(),
+            Some(Credentials {
+                uid: Some(1000),
+                gid: Some(1000),
+                groups: vec![44],
+            })
+        );
+        assert_eq!(
+            resolve_test("user: '1000'\ngroup: audio").unwrap(),
+            Some(Credentials {
+                uid: Some(1000),
+                gid: Some(29),
+                groups: vec![],
+            })
+        );
+        assert!(matches!(
+            resolve_test("user: nobody"),
+            Err(CredentialsError::UnknownUser(user)) if user == "nobody"
+        ));
+        assert!(matches!(
+            resolve_test("group: nogroup"),
+            Err(CredentialsError::UnknownGroup(group)) if group == "nogroup"
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+//! Contains functionality to find all processes that belong to a
+//! service by inspecting `/proc`, which is used for services that do not
+//! run in a cgroup of their own
+//!
+//! A running (i.e. not zombie) process belongs to a service if
+//!
+//! 1. it is in the session or process group of the service's main
+//!    process (every service is spawned in a session of its own),
+//! 2. its parent belongs to the service, or
+//! 3. it was orphaned and thus re-parented to `sysinitd` (a child
+//!    subreaper), and its environment contains
+//!    [`SERVICE_ENVIRONMENT_VARIABLE`] with the ID of the service. This
+//!    attributes double-forked processes that started a new session and
+//!    whose parent already exited.
+//!
+//! Only the environments of orphans are read, as reading the environment
+//! of every process is costly on busy hosts.
+
+/// The environment variable that contains the ID of the service a process
+/// belongs to
+pub const SERVICE_ENVIRONMENT_VARIABLE: &str = "SYSINITD_SERVICE";
+
+/// The fields of `/proc/<PID>/stat` we are interested in
+#[derive(Debug, Clone, Copy)]
+struct Stat {
+    /// Whether the process has terminated and only waits to be reaped
+    zombie: bool,
+    /// The parent process ID
+    parent: ::libc::pid_t,
+    /// The process group ID
+    process_group: ::libc::pid_t,
+    /// The session ID
+    session: ::libc::pid_t,
+}
+
+impl Stat {
+    /// Parses the contents of `/proc/<PID>/stat`
+    fn parse(content: &str) -> Option<Self> {
+        // the command name is in parentheses and may contain spaces and parentheses
+        let mut fields = content.get(content.rfind(')')? + 1..)?.split_whitespace();
+        let state = fields.next()?;
+        Some(Self {
+            zombie: state == "Z" || state == "X",
+            parent: fields.next()?.parse().ok()?,
+            process_group: fields.next()?.parse().ok()?,
+            session: fields.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// Reads the fields of `/proc/<PID>/stat` of a process that has not
+/// terminated
+fn stat(pid: ::libc::pid_t) -> Option<Stat> {
+    let content = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    Stat::parse(&content).filter(|stat| !stat.zombie)
+}
+
+/// The parent process ID of `pid`, unless it has terminated
+pub fn parent(pid: ::libc::pid_t) -> Option<::libc::pid_t> {
+    stat(pid).map(|stat| stat.parent)
+}
+
+/// The process group ID of `pid`, unless it has terminated
+pub fn process_group(pid: ::libc::pid_t) -> Option<::libc::pid_t> {
+    stat(pid).map(|stat| stat.process_group)
+}
+
+/// Returns the IDs of all running processes in the process group `group`
+pub fn in_process_group(group: ::libc::pid_t) -> Vec<::libc::pid_t> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return vec![];
+    };
+    let mut members: Vec<::libc::pid_t> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .filter(|process| process_group(*process) == Some(group))
+        .collect();
+    members.sort_unstable();
+    members
+}
+
+/// Whether the environment of `pid` marks it as belonging to `service_id`
+fn has_service_marker(pid: ::libc::pid_t, service_id: &str) -> bool {
+    let Ok(environment) = std::fs::read(format!("/proc/{pid}/environ")) else {
+        return false;
+    };
+    let marker = format!("{SERVICE_ENVIRONMENT_VARIABLE}={service_id}");
+    environment
+        .split(|byte| *byte == 0)
+        .any(|variable| variable == marker.as_bytes())
+}
+
+/// Returns the IDs of all processes except `pid` itself that belong to
+/// the service with ID `service_id` whose main process has (or had) `pid`,
+/// each together with the ID of its parent at the time of the scan
+pub fn of(pid: ::libc::pid_t, service_id: &str) -> Vec<(::libc::pid_t, ::libc::pid_t)> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return vec![];
+    };
+
+    let own_pid = std::process::id() as ::libc::pid_t;
+    let processes: std::collections::HashMap<::libc::pid_t, Stat> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .filter(|process| *process != own_pid && *process != pid)
+        .filter_map(|process| Some((process, stat(process)?)))
+        .collect();
+
+    let mut members: std::collections::HashSet<::libc::pid_t> = processes
+        .iter()
+        .filter(|(process, stat)| {
+            stat.session == pid
+                || stat.process_group == pid
+                || stat.parent == pid
+                || stat.parent == own_pid && has_service_marker(**process, service_id)
+        })
+        .map(|(process, _)| *process)
+        .collect();
+
+    loop {
+        let children: Vec<::libc::pid_t> = processes
+            .iter()
+            .filter(|(process, stat)| !members.contains(process) && members.contains(&stat.parent))
+            .map(|(process, _)| *process)
+            .collect();
+        if children.is_empty() {
+            break;
+        }
+        members.extend(children);
+    }
+
+    let mut members: Vec<(::libc::pid_t, ::libc::pid_t)> = members
+        .into_iter()
+        .map(|member| (member, processes[&member].parent))
+        .collect();
+    members.sort_unstable();
+    members
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stat() {
+        let stat = Stat::parse("42 (a (weird) name) S 1 42 40 0 -1 4194560")
+            .expect("Could not parse stat");
+        assert_eq!(stat.parent, 1);
+        assert_eq!(stat.process_group, 42);
+        assert_eq!(stat.session, 40);
+        assert!(!stat.zombie);
+        assert!(Stat::parse("42 (truncated").is_none());
+    }
+
+    #[test]
+    fn own_lineage() {
+        let pid = std::process::id() as ::libc::pid_t;
+        // SAFETY: `getppid(2)` and `getpgrp(2)` have no memory safety preconditions
+        let (parent, group) = unsafe { (::libc::getppid(), ::libc::getpgrp()) };
+        assert_eq!(super::parent(pid), Some(parent));
+        assert_eq!(process_group(pid), Some(group));
+        assert!(in_process_group(group).contains(&pid));
+    }
+}
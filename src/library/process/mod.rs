@@ -0,0 +1,534 @@
+//! Contains everything related to operating system processes: spawning
+//! them from a [`Service`], signaling them and collecting their exit
+//! status.
+
+pub mod capabilities;
+pub mod cgroup;
+pub mod credentials;
+pub mod descendants;
+mod namespaces;
+pub mod pidfd;
+mod reaper;
+pub mod scheduling;
+mod seccomp;
+mod setup;
+pub mod sockets;
+
+use crate::library::{service::Service, signal::Signal};
+
+/// How a process terminated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// The process exited with the contained exit code
+    Exited(i32),
+    /// The process was killed by the contained signal
+    Signaled(i32),
+    /// The process exited, but its exit status was lost, e.g. because it
+    /// was reaped before it was watched
+    Unknown,
+}
+
+impl ExitStatus {
+    /// Converts a raw status as returned by `waitpid(2)`
+    fn from_raw(raw_status: i32) -> Self {
+        if ::libc::WIFSIGNALED(raw_status) {
+            Self::Signaled(::libc::WTERMSIG(raw_status))
+        } else {
+            Self::Exited(::libc::WEXITSTATUS(raw_status))
+        }
+    }
+
+    /// Converts the status of an exited child as returned by `waitid(2)`
+    fn from_siginfo(info: &::libc::siginfo_t) -> Self {
+        // SAFETY: `waitid` filled `info` for an exited child
+        let status = unsafe { info.si_status() };
+        match info.si_code {
+            ::libc::CLD_EXITED => Self::Exited(status),
+            ::libc::CLD_KILLED | ::libc::CLD_DUMPED => Self::Signaled(status),
+            _ => Self::Unknown,
+        }
+    }
+
+    /// The exit code a shell would report, i.e. `128 + signal`
+    /// if the process was killed by a signal, and 255 if the exit status
+    /// is unknown
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Exited(code) => *code,
+            Self::Signaled(signal) => 128 + signal,
+            Self::Unknown => 255,
+        }
+    }
+
+    /// Whether the process exited with exit code 0
+    pub fn success(&self) -> bool {
+        *self == Self::Exited(0)
+    }
+}
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exited(code) => write!(formatter, "exit code {code}"),
+            Self::Signaled(signal) => match Signal::from_raw(*signal) {
+                Some(signal) => write!(formatter, "killed by {signal}"),
+                None => write!(formatter, "killed by signal {signal}"),
+            },
+            Self::Unknown => write!(formatter, "unknown exit status"),
+        }
+    }
+}
+
+/// Makes `sysinitd` a child subreaper
+///
+/// Orphaned descendants of services are then re-parented to `sysinitd`
+/// instead of the initialization process of the system, so that they
+/// are reaped by `sysinitd` even if `sysinitd` is not PID 1.
+pub fn become_subreaper() -> std::io::Result<()> {
+    // SAFETY: `prctl(2)` with `PR_SET_CHILD_SUBREAPER` has no memory safety preconditions
+    if unsafe { ::libc::prctl(::libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A reference to a process that is safe against PID reuse if the
+/// kernel supports [pidfds](pidfd)
+///
+/// Without pidfd support, the process is referred to by its PID only.
+#[derive(Debug)]
+pub struct ProcessHandle {
+    /// The process ID
+    pid: ::libc::pid_t,
+    /// The pidfd referring to the process
+    pidfd: Option<std::os::fd::OwnedFd>,
+}
+
+impl ProcessHandle {
+    /// Creates a handle for `pid`
+    ///
+    /// To be free of races, `pid` must not have been reaped yet.
+    pub fn open(pid: ::libc::pid_t) -> Self {
+        Self {
+            pid,
+            pidfd: pidfd::open(pid).ok(),
+        }
+    }
+
+    /// Creates a handle for `pid`, which was found by scanning `/proc`, if
+    /// `verify` still holds once the pidfd was opened
+    ///
+    /// The process found may have exited and its PID may have been reused
+    /// since the scan. A pidfd opened for a reused PID refers to the wrong
+    /// process, so what made the process match (e.g. its parent) is checked
+    /// again afterwards.
+    pub fn open_verified(pid: ::libc::pid_t, verify: impl FnOnce() -> bool) -> Option<Self> {
+        let handle = Self::open(pid);
+        verify().then_some(handle)
+    }
+
+    /// The process ID
+    pub fn pid(&self) -> ::libc::pid_t {
+        self.pid
+    }
+
+    /// Sends `signal` to the process
+    pub fn signal(&self, signal: Signal) -> std::io::Result<()> {
+        match &self.pidfd {
+            Some(pidfd) => pidfd::send_signal(pidfd, signal.as_raw()),
+            None => kill(self.pid, signal),
+        }
+    }
+
+    /// Waits until the process has terminated
+    ///
+    /// The process does not need to be a child of `sysinitd`. Without
+    /// a pidfd, this polls whether the PID still exists.
+    pub async fn terminated(&self) {
+        use std::os::fd::AsRawFd as _;
+
+        if let Some(pidfd) = &self.pidfd
+            // SAFETY: the pidfd stays open while it is borrowed by `self`,
+            // which outlives the `AsyncFd`
+            && let Ok(pidfd) = unsafe {
+                ::tokio::io::unix::AsyncFd::register_with_interest(
+                    pidfd.as_raw_fd(),
+                    ::tokio::io::Interest::READABLE,
+                )
+            }
+        {
+            // a pidfd becomes readable once the process has terminated
+            let _ = pidfd.readable().await;
+            return;
+        }
+
+        // SAFETY: `kill(2)` with signal 0 has no memory safety preconditions
+        while unsafe { ::libc::kill(self.pid, 0) } == 0 {
+            ::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// Calls `kill(2)`
+fn kill(pid: ::libc::pid_t, signal: Signal) -> std::io::Result<()> {
+    // SAFETY: `kill(2)` has no memory safety preconditions
+    if unsafe { ::libc::kill(pid, signal.as_raw()) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A running process spawned by `sysinitd`
+#[derive(Debug)]
+pub struct Process {
+    /// The handle to the process
+    handle: ProcessHandle,
+    /// The ID of the service the process belongs to
+    service_id: String,
+    /// The cgroup of the service, if `sysinitd` manages cgroups
+    cgroup: Option<cgroup::Cgroup>,
+}
+
+impl Process {
+    /// Spawns the start command of `service`, passing the path of its
+    /// `notify_socket` (if it has one) in `NOTIFY_SOCKET` and its
+    /// `listeners` (if it has any) as file descriptors 3 and following
+    ///
+    /// The returned receiver yields the exit status of the process
+    /// once it has terminated and was reaped.
+    pub fn spawn(
+        service: &Service,
+        notify_socket: Option<&std::path::Path>,
+        listeners: Option<&sockets::Listeners>,
+    ) -> ::anyhow::Result<(Self, ::tokio::sync::oneshot::Receiver<ExitStatus>)> {
+        use std::os::unix::process::CommandExt as _;
+
+        let mut command = std::process::Command::new(service.start().command());
+        command
+            .args(service.start().arguments())
+            .env(descendants::SERVICE_ENVIRONMENT_VARIABLE, service.id());
+        if let Some(notify_socket) = notify_socket {
+            command.env(
+                crate::library::notify::SOCKET_ENVIRONMENT_VARIABLE,
+                notify_socket,
+            );
+        }
+        if let Some(instance) = service.instance() {
+            command
+                .env(
+                    crate::library::service::INSTANCE_ENVIRONMENT_VARIABLE,
+                    instance.name(),
+                )
+                .env(
+                    crate::library::service::INDEX_ENVIRONMENT_VARIABLE,
+                    instance.index().to_string(),
+                );
+        }
+        if let Some(watchdog) = service.watchdog() {
+            command.env(
+                crate::library::notify::WATCHDOG_ENVIRONMENT_VARIABLE,
+                watchdog.interval().as_micros().to_string(),
+            );
+        }
+
+        let cgroup = match cgroup::hierarchy() {
+            Some(hierarchy) => {
+                let cgroup = hierarchy.create(service.id())?;
+                if let Err(error) = cgroup.configure(service.cgroup()) {
+                    let _ = cgroup.remove();
+                    return Err(error.into());
+                }
+                Some(cgroup)
+            }
+            None => None,
+        };
+
+        let sockets = listeners
+            .map(|listeners| sockets::SocketSetup::new(&command, listeners))
+            .transpose()?;
+        let mut setup = setup::ChildSetup::new(service, cgroup.as_ref(), sockets)?;
+        // SAFETY: `ChildSetup::apply` only calls async-signal-safe functions
+        unsafe {
+            command.pre_exec(move || setup.apply());
+        }
+
+        let guard = reaper::begin_spawn();
+        let child = command.spawn().map_err(|error| {
+            if error.kind() == std::io::ErrorKind::PermissionDenied
+                && !service.namespaces().is_empty()
+            {
+                ::anyhow::anyhow!(error).context(format!(
+                    "Could not create the namespaces of service '{}' (sysinitd may lack {} or \
+                     a seccomp filter, e.g. of a container runtime, may forbid unshare(2))",
+                    service.id(),
+                    crate::library::capability::Capability::SYS_ADMIN
+                ))
+            } else {
+                error.into()
+            }
+        })?;
+        // the reaper does not reap the new process while `guard` lives,
+        // so the PID can not have been reused before the pidfd is opened
+        let handle = ProcessHandle::open(child.id() as ::libc::pid_t);
+        let exit_status = guard.watch(handle.pid());
+
+        Ok((
+            Self {
+                handle,
+                service_id: service.id().clone(),
+                cgroup,
+            },
+            exit_status,
+        ))
+    }
+
+    /// Takes over supervision of the daemon `pid` that this process (the
+    /// launcher of a forking service) left behind, or of the main process
+    /// a notify service announced
+    ///
+    /// The daemon must have been re-parented to `sysinitd`, so that its exit
+    /// status can be collected. The returned receiver yields it.
+    pub fn adopt(
+        &mut self,
+        pid: ::libc::pid_t,
+    ) -> ::anyhow::Result<(Self, ::tokio::sync::oneshot::Receiver<ExitStatus>)> {
+        // once the pidfd is open, the daemon is known to be alive (or a
+        // zombie), and if it is reaped before it is watched, the reaper
+        // keeps its exit status
+        let Some(handle) = ProcessHandle::open_verified(pid, || reaper::is_unreaped_child(pid))
+        else {
+            if let Some(status) = reaper::take_reaped(pid) {
+                ::anyhow::bail!("Process {pid} exited before it could be supervised ({status})");
+            }
+            match descendants::parent(pid) {
+                None => ::anyhow::bail!("Process {pid} does not exist"),
+                Some(parent) => ::anyhow::bail!(
+                    "Process {pid} is not a child of sysinitd (its parent is {parent}); \
+                     sysinitd must be PID 1 or a subreaper to supervise processes it did not spawn"
+                ),
+            }
+        };
+        let exit_status = reaper::watch(pid);
+
+        Ok((
+            Self {
+                handle,
+                service_id: self.service_id.clone(),
+                cgroup: self.cgroup.clone(),
+            },
+            exit_status,
+        ))
+    }
+
+    /// Waits until the PID file of a forking service contains the PID of
+    /// a running process and returns it
+    ///
+    /// Daemons may write the file after their launcher exited, so reading
+    /// is retried until `timeout` expires.
+    pub async fn read_pid_file(
+        path: &std::path::Path,
+        timeout: std::time::Duration,
+    ) -> ::anyhow::Result<::libc::pid_t> {
+        let read = || -> ::anyhow::Result<::libc::pid_t> {
+            use ::anyhow::Context as _;
+
+            let content = std::fs::read_to_string(path)
+                .context(format!("Could not read PID file '{}'", path.display()))?;
+            let pid = content
+                .trim()
+                .parse::<::libc::pid_t>()
+                .ok()
+                .filter(|pid| *pid > 0)
+                .context(format!(
+                    "PID file '{}' does not contain a PID",
+                    path.display()
+                ))?;
+            // SAFETY: `kill(2)` with signal 0 has no memory safety preconditions
+            if unsafe { ::libc::kill(pid, 0) } == -1 {
+                ::anyhow::bail!(
+                    "Process {pid} from PID file '{}' does not exist",
+                    path.display()
+                );
+            }
+            Ok(pid)
+        };
+
+        let deadline = ::tokio::time::Instant::now() + timeout;
+        loop {
+            match read() {
+                Ok(pid) => return Ok(pid),
+                Err(error) if ::tokio::time::Instant::now() >= deadline => return Err(error),
+                Err(_) => ::tokio::time::sleep(std::time::Duration::from_millis(100)).await,
+            }
+        }
+    }
+
+    /// The process ID
+    pub fn pid(&self) -> ::libc::pid_t {
+        self.handle.pid()
+    }
+
+    /// Sends `signal` to the process
+    pub fn signal(&self, signal: Signal) -> std::io::Result<()> {
+        self.handle.signal(signal)
+    }
+
+    /// Sends `signal` to the process group of the process
+    ///
+    /// With pidfd support, every member of the group is signaled via a
+    /// pidfd, so that a recycled PID is never signaled.
+    pub fn signal_group(&self, signal: Signal) -> std::io::Result<()> {
+        if !pidfd::supported() {
+            return kill(-self.pid(), signal);
+        }
+
+        let group = self.pid();
+        let mut result = self.signal(signal);
+        for pid in descendants::in_process_group(group) {
+            if pid == group {
+                continue;
+            }
+            if let Some(member) =
+                ProcessHandle::open_verified(pid, || descendants::process_group(pid) == Some(group))
+            {
+                // members may have exited in the meantime
+                if let Err(error) = member.signal(signal)
+                    && error.raw_os_error() != Some(::libc::ESRCH)
+                {
+                    result = Err(error);
+                }
+            }
+        }
+        result
+    }
+
+    /// The current scheduling of the process
+    pub fn scheduling(&self) -> std::io::Result<scheduling::Scheduling> {
+        scheduling::Scheduling::of(self.pid())
+    }
+
+    /// The cgroup of the service of the process
+    pub fn cgroup(&self) -> Option<&cgroup::Cgroup> {
+        self.cgroup.as_ref()
+    }
+
+    /// Sends `signal` to the process, its process group and all
+    /// descendants that left the process group
+    ///
+    /// `SIGKILL` is sent to the whole cgroup of the service if there is one.
+    pub fn signal_all(&self, signal: Signal) -> std::io::Result<()> {
+        if signal == Signal::KILL
+            && let Some(cgroup) = &self.cgroup
+        {
+            return cgroup.kill();
+        }
+
+        let result = self.signal(signal);
+        let _ = self.signal_group(signal);
+        for descendant in self.descendants() {
+            let _ = descendant.signal(signal);
+        }
+        result
+    }
+
+    /// Whether `pid` is the process itself or belongs to the same service,
+    /// i.e. is in the cgroup of the service or a descendant of the process
+    pub fn contains(&self, pid: ::libc::pid_t) -> bool {
+        if pid == self.pid() {
+            return true;
+        }
+        if let Some(cgroup) = &self.cgroup {
+            return cgroup.contains(pid);
+        }
+        let mut current = pid;
+        while let Some(parent) = descendants::parent(current) {
+            if parent == self.pid() {
+                return true;
+            }
+            if parent <= 1 {
+                return false;
+            }
+            current = parent;
+        }
+        false
+    }
+
+    /// Handles to all descendants of the process
+    ///
+    /// These are all other processes in the cgroup of the service if there
+    /// is one. This also works after the process itself has exited.
+    pub fn descendants(&self) -> Vec<ProcessHandle> {
+        match &self.cgroup {
+            Some(cgroup) => cgroup
+                .pids()
+                .into_iter()
+                .filter(|pid| *pid != self.pid())
+                .filter_map(|pid| ProcessHandle::open_verified(pid, || cgroup.contains(pid)))
+                .collect(),
+            None => descendants::of(self.pid(), &self.service_id)
+                .into_iter()
+                .filter_map(|(pid, parent)| {
+                    ProcessHandle::open_verified(pid, || descendants::parent(pid) == Some(parent))
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_status_code() {
+        assert_eq!(ExitStatus::Exited(3).code(), 3);
+        assert_eq!(ExitStatus::Signaled(::libc::SIGKILL).code(), 137);
+        assert_eq!(ExitStatus::Unknown.code(), 255);
+        assert_eq!(
+            ExitStatus::Signaled(::libc::SIGTERM).to_string(),
+            "killed by SIGTERM"
+        );
+    }
+
+    #[::tokio::test]
+    async fn signal_process() {
+        let service = Service::from_command("sleep", &[String::from("60")]);
+        let (process, exit_status) =
+            Process::spawn(&service, None, None).expect("Could not spawn 'sleep'");
+        assert_eq!(process.handle.pidfd.is_some(), pidfd::supported());
+
+        process
+            .signal(Signal::TERM)
+            .expect("Could not signal 'sleep'");
+        assert_eq!(
+            exit_status.await.expect("Reaper dropped the exit status"),
+            ExitStatus::Signaled(::libc::SIGTERM)
+        );
+        assert_eq!(
+            process
+                .signal(Signal::TERM)
+                .expect_err("Signaling a reaped process must fail")
+                .raw_os_error(),
+            Some(::libc::ESRCH)
+        );
+    }
+
+    #[::tokio::test]
+    async fn reaped_process_watched() {
+        let service = Service::from_command("true", &[]);
+        let (process, exit_status) =
+            Process::spawn(&service, None, None).expect("Could not spawn 'true'");
+        assert_eq!(
+            exit_status.await.expect("Reaper dropped the exit status"),
+            ExitStatus::Exited(0)
+        );
+
+        // the exit status is gone, but the watcher is not left waiting
+        assert_eq!(
+            reaper::watch(process.pid())
+                .await
+                .expect("Reaper dropped the exit status"),
+            ExitStatus::Unknown
+        );
+    }
+}
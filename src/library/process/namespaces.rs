@@ -0,0 +1,405 @@
+//! Contains [`NamespaceSetup`], the part of [`super::setup::ChildSetup`]
+//! that moves a new process into new namespaces
+//!
+//! All namespaces are created with `unshare(2)`. A new PID namespace only
+//! applies to children of the calling process, so the new process forks
+//! twice: it stays outside of the namespace and passes signals on, its
+//! child becomes PID 1 of the namespace, which passes signals on as well
+//! and reaps orphans, and the grandchild executes the command. Both
+//! intermediate processes exit with the exit status of the command. As PID 1
+//! of a namespace can not be killed by signals it sends to itself, it
+//! reports the signal that killed the command through memory shared with
+//! the outer process, which then kills itself with that signal.
+//!
+//! Read-only paths are made read-only together with all mounts below them
+//! via `mount_setattr(2)`, which requires Linux 5.12.
+
+use crate::library::{capability::Capability, service::Service};
+
+/// The signals the intermediate processes of a PID namespace pass on
+const PASSED_ON_SIGNALS: [::libc::c_int; 9] = [
+    ::libc::SIGHUP,
+    ::libc::SIGINT,
+    ::libc::SIGQUIT,
+    ::libc::SIGTERM,
+    ::libc::SIGUSR1,
+    ::libc::SIGUSR2,
+    ::libc::SIGWINCH,
+    ::libc::SIGCONT,
+    ::libc::SIGALRM,
+];
+
+/// The process signals are passed on to by [`pass_on_signal`]
+static CHILD: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+
+/// `MOUNT_ATTR_RDONLY` of `mount_setattr(2)`
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+
+/// `struct mount_attr` of `mount_setattr(2)`
+#[repr(C)]
+struct MountAttributes {
+    /// The attributes to set
+    set: u64,
+    /// The attributes to clear
+    clear: u64,
+    /// The mount propagation type
+    propagation: u64,
+    /// The user namespace of ID-mapped mounts
+    user_namespace: u64,
+}
+
+/// How a new process is moved into new namespaces
+#[derive(Debug)]
+pub struct NamespaceSetup {
+    /// The `CLONE_NEW*` flags for `unshare(2)`
+    flags: ::libc::c_int,
+    /// Whether a new mount namespace is created
+    mount: bool,
+    /// Whether a private `tmpfs` is mounted at `/tmp`
+    private_tmp: bool,
+    /// The paths that are bind-mounted read-only onto themselves
+    read_only: Vec<std::ffi::CString>,
+    /// The hostname in the new UTS namespace
+    hostname: Option<std::ffi::CString>,
+    /// Whether a new network namespace is created
+    network: bool,
+    /// Whether a new PID namespace is created
+    pid: bool,
+}
+
+impl NamespaceSetup {
+    /// Resolves the namespace setup of `service`
+    ///
+    /// Returns [`None`] if the service does not request new namespaces.
+    pub fn new(service: &Service) -> ::anyhow::Result<Option<Self>> {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let namespaces = service.namespaces();
+        if namespaces.is_empty() {
+            return Ok(None);
+        }
+        if !super::capabilities::is_effective(Capability::SYS_ADMIN)? {
+            ::anyhow::bail!(
+                "Service '{}' requests new namespaces, but sysinitd lacks {} to create them",
+                service.id(),
+                Capability::SYS_ADMIN
+            );
+        }
+
+        let mut flags = 0;
+        for (requested, flag) in [
+            (namespaces.mount().is_some(), ::libc::CLONE_NEWNS),
+            (namespaces.pid(), ::libc::CLONE_NEWPID),
+            (namespaces.network(), ::libc::CLONE_NEWNET),
+            (namespaces.ipc(), ::libc::CLONE_NEWIPC),
+            (namespaces.uts().is_some(), ::libc::CLONE_NEWUTS),
+        ] {
+            if requested {
+                flags |= flag;
+            }
+        }
+
+        let read_only = namespaces
+            .mount()
+            .map(|mount| mount.read_only())
+            .unwrap_or_default()
+            .iter()
+            .map(|path| std::ffi::CString::new(path.as_os_str().as_bytes()))
+            .collect::<Result<_, _>>()?;
+        let hostname = namespaces
+            .uts()
+            .and_then(|uts| uts.hostname())
+            .map(std::ffi::CString::new)
+            .transpose()?;
+
+        Ok(Some(Self {
+            flags,
+            mount: namespaces.mount().is_some(),
+            private_tmp: namespaces.mount().is_some_and(|mount| mount.private_tmp()),
+            read_only,
+            hostname,
+            network: namespaces.network(),
+            pid: namespaces.pid(),
+        }))
+    }
+
+    /// Creates the namespaces and sets them up
+    ///
+    /// With a new PID namespace, this only returns in the process that
+    /// executes the command.
+    pub fn apply(&self) -> std::io::Result<()> {
+        // SAFETY: `unshare(2)` has no memory safety preconditions
+        if unsafe { ::libc::unshare(self.flags) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        if self.mount {
+            // mounts must not propagate back into the namespace of `sysinitd`
+            mount(None, c"/", None, ::libc::MS_REC | ::libc::MS_PRIVATE, None)?;
+            if self.private_tmp {
+                mount(
+                    Some(c"tmpfs"),
+                    c"/tmp",
+                    Some(c"tmpfs"),
+                    ::libc::MS_NOSUID | ::libc::MS_NODEV,
+                    Some(c"mode=1777"),
+                )?;
+            }
+            for path in &self.read_only {
+                mount(
+                    Some(path),
+                    path,
+                    None,
+                    ::libc::MS_BIND | ::libc::MS_REC,
+                    None,
+                )?;
+                make_read_only(path)?;
+            }
+        }
+
+        if let Some(hostname) = &self.hostname {
+            // SAFETY: `hostname` is valid for the duration of the call
+            if unsafe { ::libc::sethostname(hostname.as_ptr(), hostname.as_bytes().len()) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if self.network {
+            bring_up_loopback()?;
+        }
+
+        if self.pid {
+            let signal = shared_signal()?;
+            // the child becomes PID 1 of the PID namespace; once this process
+            // dies (e.g. because it was sent `SIGKILL`), PID 1 and with it
+            // the whole namespace is killed
+            fork_and_pass_on_signals(true, signal)?;
+            // the child executes the command
+            fork_and_pass_on_signals(false, signal)?;
+            if self.mount {
+                mount(
+                    Some(c"proc"),
+                    c"/proc",
+                    Some(c"proc"),
+                    ::libc::MS_NOSUID | ::libc::MS_NODEV | ::libc::MS_NOEXEC,
+                    None,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Calls `mount(2)`
+fn mount(
+    source: Option<&std::ffi::CStr>,
+    target: &std::ffi::CStr,
+    file_system: Option<&std::ffi::CStr>,
+    flags: ::libc::c_ulong,
+    data: Option<&std::ffi::CStr>,
+) -> std::io::Result<()> {
+    // SAFETY: all pointers are valid C strings or null
+    let result = unsafe {
+        ::libc::mount(
+            source.map_or(std::ptr::null(), std::ffi::CStr::as_ptr),
+            target.as_ptr(),
+            file_system.map_or(std::ptr::null(), std::ffi::CStr::as_ptr),
+            flags,
+            data.map_or(std::ptr::null(), |data| data.as_ptr().cast()),
+        )
+    };
+    if result == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Makes the mount at `path` and all mounts below it read-only
+fn make_read_only(path: &std::ffi::CStr) -> std::io::Result<()> {
+    let attributes = MountAttributes {
+        set: MOUNT_ATTR_RDONLY,
+        clear: 0,
+        propagation: 0,
+        user_namespace: 0,
+    };
+    // SAFETY: `path` and `attributes` are valid for the duration of the call
+    let result = unsafe {
+        ::libc::syscall(
+            ::libc::SYS_mount_setattr,
+            ::libc::AT_FDCWD,
+            path.as_ptr(),
+            ::libc::AT_RECURSIVE,
+            &attributes,
+            std::mem::size_of::<MountAttributes>(),
+        )
+    };
+    if result == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Maps memory that is shared with all children forked afterwards, in which
+/// PID 1 of a PID namespace stores the signal that killed the command
+fn shared_signal() -> std::io::Result<&'static std::sync::atomic::AtomicI32> {
+    // SAFETY: an anonymous mapping has no memory safety preconditions
+    let memory = unsafe {
+        ::libc::mmap(
+            std::ptr::null_mut(),
+            std::mem::size_of::<std::sync::atomic::AtomicI32>(),
+            ::libc::PROT_READ | ::libc::PROT_WRITE,
+            ::libc::MAP_SHARED | ::libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    if memory == ::libc::MAP_FAILED {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the mapping is zeroed, suitably aligned and never unmapped
+    // (it is released when the command is executed)
+    Ok(unsafe { &*memory.cast::<std::sync::atomic::AtomicI32>() })
+}
+
+/// Brings up the loopback interface, the only interface of a new network
+/// namespace
+fn bring_up_loopback() -> std::io::Result<()> {
+    // SAFETY: `socket(2)` has no memory safety preconditions
+    let socket = unsafe {
+        ::libc::socket(
+            ::libc::AF_INET,
+            ::libc::SOCK_DGRAM | ::libc::SOCK_CLOEXEC,
+            0,
+        )
+    };
+    if socket == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: `ifreq` is a plain C struct for which all zeros is valid
+    let mut request: ::libc::ifreq = unsafe { std::mem::zeroed() };
+    for (target, source) in request.ifr_name.iter_mut().zip(c"lo".to_bytes()) {
+        *target = *source as ::libc::c_char;
+    }
+
+    // SAFETY: `request` is valid for the duration of the calls and
+    // `ifru_flags` is the member used by `SIOCGIFFLAGS` and `SIOCSIFFLAGS`
+    let result = unsafe {
+        if ::libc::ioctl(socket, ::libc::SIOCGIFFLAGS as _, &mut request) == -1 {
+            -1
+        } else {
+            request.ifr_ifru.ifru_flags |= ::libc::IFF_UP as ::libc::c_short;
+            ::libc::ioctl(socket, ::libc::SIOCSIFFLAGS as _, &request)
+        }
+    };
+    let error = std::io::Error::last_os_error();
+    // SAFETY: `socket` is a file descriptor we own
+    unsafe { ::libc::close(socket) };
+    if result == -1 {
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Forks; the child returns while the parent passes signals on to the
+/// child, reaps all of its children and exits like the child once it exits
+///
+/// With `kill_with_parent`, the child is killed when the parent dies.
+/// `signal` is shared by all processes in between `sysinitd` and the
+/// command, see [`pass_on_signals_until_exit`].
+fn fork_and_pass_on_signals(
+    kill_with_parent: bool,
+    signal: &std::sync::atomic::AtomicI32,
+) -> std::io::Result<()> {
+    // SAFETY: the child only calls async-signal-safe functions until it
+    // executes the command
+    let child = unsafe { ::libc::fork() };
+    match child {
+        -1 => Err(std::io::Error::last_os_error()),
+        0 => {
+            // SAFETY: `prctl(2)` with `PR_SET_PDEATHSIG` has no memory safety
+            // preconditions
+            if kill_with_parent
+                && unsafe { ::libc::prctl(::libc::PR_SET_PDEATHSIG, ::libc::SIGKILL, 0, 0, 0) }
+                    == -1
+            {
+                // SAFETY: `_exit(2)` has no memory safety preconditions
+                unsafe { ::libc::_exit(1) };
+            }
+            Ok(())
+        }
+        child => pass_on_signals_until_exit(child, signal),
+    }
+}
+
+/// Passes signals on to `child` until it exits and then exits like it
+///
+/// If the command was killed by a signal, it is stored in `signal`, so that
+/// the outer process can kill itself with it even though PID 1 of the PID
+/// namespace can not, and exits with exit code 128 + the signal number.
+fn pass_on_signals_until_exit(child: ::libc::pid_t, signal: &std::sync::atomic::AtomicI32) -> ! {
+    // the command reports whether `exec(2)` failed through a pipe that
+    // must only be held open by the command itself
+    // SAFETY: closing file descriptors has no memory safety preconditions
+    unsafe {
+        if ::libc::syscall(::libc::SYS_close_range, 3, ::libc::c_uint::MAX, 0) == -1 {
+            for fd in 3..1024 {
+                ::libc::close(fd);
+            }
+        }
+    }
+
+    CHILD.store(child, std::sync::atomic::Ordering::SeqCst);
+    for signal in PASSED_ON_SIGNALS {
+        // SAFETY: `sigaction` is a plain C struct for which all zeros is valid,
+        // and `pass_on_signal` is async-signal-safe
+        unsafe {
+            let mut action: ::libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = pass_on_signal as extern "C" fn(::libc::c_int) as usize;
+            action.sa_flags = ::libc::SA_RESTART;
+            ::libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+    // SAFETY: `set` is valid for the duration of the calls
+    unsafe {
+        let mut set: ::libc::sigset_t = std::mem::zeroed();
+        ::libc::sigemptyset(&mut set);
+        ::libc::sigprocmask(::libc::SIG_SETMASK, &set, std::ptr::null_mut());
+    }
+
+    let status = loop {
+        let mut status = 0;
+        // SAFETY: `status` is valid for writes
+        match unsafe { ::libc::waitpid(-1, &mut status, 0) } {
+            pid if pid == child => break status,
+            -1 if std::io::Error::last_os_error().raw_os_error() != Some(::libc::EINTR) => {
+                break 0;
+            }
+            _ => {}
+        }
+    };
+
+    if ::libc::WIFSIGNALED(status) {
+        signal.store(
+            ::libc::WTERMSIG(status),
+            std::sync::atomic::Ordering::SeqCst,
+        );
+    }
+    let killed_by = signal.load(std::sync::atomic::Ordering::SeqCst);
+    // SAFETY: only async-signal-safe functions are called
+    unsafe {
+        if killed_by != 0 {
+            ::libc::signal(killed_by, ::libc::SIG_DFL);
+            ::libc::kill(::libc::getpid(), killed_by);
+            ::libc::_exit(128 + killed_by);
+        }
+        ::libc::_exit(::libc::WEXITSTATUS(status))
+    }
+}
+
+/// Signal handler that passes `signal` on to [`CHILD`]
+extern "C" fn pass_on_signal(signal: ::libc::c_int) {
+    // SAFETY: `kill(2)` is async-signal-safe
+    unsafe { ::libc::kill(CHILD.load(std::sync::atomic::Ordering::SeqCst), signal) };
+}
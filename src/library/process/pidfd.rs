@@ -0,0 +1,57 @@
+//! Contains wrappers around the pidfd system calls of Linux
+//!
+//! A pidfd is a file descriptor that refers to a process. Contrary to
+//! a PID, it can not be recycled: signals sent via a pidfd never reach
+//! another process that happens to reuse the PID after the original
+//! process exited. pidfds are available since Linux 5.3.
+
+use std::os::fd::{AsRawFd as _, FromRawFd as _};
+
+/// Whether the kernel supports `pidfd_open(2)` and `pidfd_send_signal(2)`
+///
+/// The result is determined once by opening a pidfd for `sysinitd` itself.
+pub fn supported() -> bool {
+    static SUPPORTED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        // SAFETY: `getpid(2)` has no memory safety preconditions
+        open_unchecked(unsafe { ::libc::getpid() }).is_ok()
+    })
+}
+
+/// Opens a pidfd for `pid`
+pub fn open(pid: ::libc::pid_t) -> std::io::Result<std::os::fd::OwnedFd> {
+    if !supported() {
+        return Err(std::io::Error::from_raw_os_error(::libc::ENOSYS));
+    }
+    open_unchecked(pid)
+}
+
+/// Calls `pidfd_open(2)`
+fn open_unchecked(pid: ::libc::pid_t) -> std::io::Result<std::os::fd::OwnedFd> {
+    // SAFETY: `pidfd_open(2)` has no memory safety preconditions
+    let fd = unsafe { ::libc::syscall(::libc::SYS_pidfd_open, pid, 0) };
+    if fd == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: `pidfd_open(2)` returned a new file descriptor we now own
+    Ok(unsafe { std::os::fd::OwnedFd::from_raw_fd(fd as std::os::fd::RawFd) })
+}
+
+/// Sends `signal` to the process referred to by `pidfd`
+pub fn send_signal(pidfd: &std::os::fd::OwnedFd, signal: i32) -> std::io::Result<()> {
+    // SAFETY: passing a null pointer as `siginfo_t` is allowed and
+    // makes the kernel fill it in as `kill(2)` would
+    let result = unsafe {
+        ::libc::syscall(
+            ::libc::SYS_pidfd_send_signal,
+            pidfd.as_raw_fd(),
+            signal,
+            std::ptr::null::<::libc::siginfo_t>(),
+            0,
+        )
+    };
+    if result == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,251 @@
+//! Contains the process-wide reaper that collects the exit status
+//! of every child of `sysinitd`
+//!
+//! ## Why?
+//!
+//! As an initialization process, `sysinitd` inherits orphaned
+//! processes and has to reap them so that they do not linger as
+//! zombies. A dedicated thread waits for _any_ child and hands the
+//! exit status of supervised processes to whoever [`watch`]es them.
+//! Other children are reaped silently.
+//!
+//! Exit statuses are only peeked (`WNOWAIT`) first: while a spawn is
+//! in progress, unknown children are left alone because they might
+//! belong to the spawn that is not yet registered. Children that are
+//! registered later on, like the daemons of forking services, may have
+//! been reaped already, so the exit statuses of the most recently reaped
+//! unknown children are kept. Watchers of children whose exit status was
+//! lost nonetheless receive [`ExitStatus::Unknown`].
+
+use super::ExitStatus;
+
+/// The state shared between the reaper thread and spawning code
+#[derive(Default)]
+struct State {
+    /// Processes whose exit status is awaited
+    watchers: std::collections::HashMap<::libc::pid_t, ::tokio::sync::oneshot::Sender<ExitStatus>>,
+    /// Number of spawns currently in progress
+    spawning: usize,
+    /// The most recently reaped unknown children and their exit statuses
+    reaped: std::collections::VecDeque<(::libc::pid_t, ExitStatus)>,
+    /// Number of watchers registered so far, which tells the reaper
+    /// whether new children may exist
+    registrations: u64,
+}
+
+impl State {
+    /// Registers `sender` as the watcher of `pid`
+    fn register(&mut self, pid: ::libc::pid_t, sender: ::tokio::sync::oneshot::Sender<ExitStatus>) {
+        self.watchers.insert(pid, sender);
+        self.registrations += 1;
+    }
+}
+
+/// How many exit statuses of unknown children are kept
+const REAPED_CAPACITY: usize = 64;
+
+/// The reaper's state and the condition variable to wake it
+static REAPER: std::sync::OnceLock<(std::sync::Mutex<State>, std::sync::Condvar)> =
+    std::sync::OnceLock::new();
+
+/// Returns the reaper's state and starts the reaper thread on first use
+fn reaper() -> &'static (std::sync::Mutex<State>, std::sync::Condvar) {
+    REAPER.get_or_init(|| {
+        std::thread::Builder::new()
+            .name(String::from("reaper"))
+            .spawn(reap)
+            .expect("Could not spawn reaper thread");
+        (std::sync::Mutex::default(), std::sync::Condvar::new())
+    })
+}
+
+/// Locks the reaper's state, ignoring poisoning (the state stays consistent)
+fn lock(mutex: &std::sync::Mutex<State>) -> std::sync::MutexGuard<'_, State> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Marks a spawn as in progress for as long as it lives
+#[derive(Debug)]
+pub struct SpawnGuard(());
+
+impl SpawnGuard {
+    /// Registers `pid` so that its exit status is sent to the returned receiver
+    pub fn watch(&self, pid: ::libc::pid_t) -> ::tokio::sync::oneshot::Receiver<ExitStatus> {
+        let (sender, receiver) = ::tokio::sync::oneshot::channel();
+        let (state, condition) = reaper();
+        lock(state).register(pid, sender);
+        condition.notify_all();
+        receiver
+    }
+}
+
+impl Drop for SpawnGuard {
+    fn drop(&mut self) {
+        let (state, condition) = reaper();
+        lock(state).spawning -= 1;
+        condition.notify_all();
+    }
+}
+
+/// Announces that a process is about to be spawned
+///
+/// The returned guard must be kept until the new process was registered
+/// with [`SpawnGuard::watch`] (or spawning failed).
+pub fn begin_spawn() -> SpawnGuard {
+    let (state, _) = reaper();
+    lock(state).spawning += 1;
+    SpawnGuard(())
+}
+
+/// Whether `pid` is a child of `sysinitd` that was not reaped yet
+pub fn is_unreaped_child(pid: ::libc::pid_t) -> bool {
+    // SAFETY: `siginfo_t` is a plain C struct for which all-zero is valid
+    let mut info: ::libc::siginfo_t = unsafe { std::mem::zeroed() };
+    // SAFETY: `info` is a valid pointer for the duration of the call
+    unsafe {
+        ::libc::waitid(
+            ::libc::P_PID,
+            pid as ::libc::id_t,
+            &mut info,
+            ::libc::WEXITED | ::libc::WNOHANG | ::libc::WNOWAIT,
+        ) == 0
+    }
+}
+
+/// Registers the child `pid`, which was not spawned by `sysinitd` itself
+/// (e.g. the daemon of a forking service), so that its exit status is sent
+/// to the returned receiver
+///
+/// The child must have been an unreaped child (see [`is_unreaped_child`])
+/// recently. If it was reaped in the meantime, its kept exit status is sent,
+/// or [`ExitStatus::Unknown`] if it is no longer kept.
+pub fn watch(pid: ::libc::pid_t) -> ::tokio::sync::oneshot::Receiver<ExitStatus> {
+    let (sender, receiver) = ::tokio::sync::oneshot::channel();
+    let (state, condition) = reaper();
+    // the reaper does not reap while the state is locked
+    let mut state = lock(state);
+    let kept = state.reaped.iter().position(|(reaped, _)| *reaped == pid);
+    match kept {
+        // a kept exit status is outdated if the PID was reused by a child
+        Some(index) if !is_unreaped_child(pid) => {
+            let (_, status) = state.reaped.remove(index).expect("bug: invalid index");
+            let _ = sender.send(status);
+        }
+        // the reaper would never report the exit of a reaped child
+        None if !is_unreaped_child(pid) => {
+            let _ = sender.send(ExitStatus::Unknown);
+        }
+        _ => {
+            state.reaped.retain(|(reaped, _)| *reaped != pid);
+            state.register(pid, sender);
+            condition.notify_all();
+        }
+    }
+    receiver
+}
+
+/// Takes the kept exit status of the unknown child `pid` that was reaped
+/// recently, if there is one
+pub fn take_reaped(pid: ::libc::pid_t) -> Option<ExitStatus> {
+    let (state, _) = reaper();
+    let mut state = lock(state);
+    let index = state.reaped.iter().position(|(reaped, _)| *reaped == pid)?;
+    state.reaped.remove(index).map(|(_, status)| status)
+}
+
+/// The reaper thread's main loop
+fn reap() {
+    let (state, condition) = reaper();
+
+    loop {
+        let registrations = lock(state).registrations;
+        // SAFETY: `siginfo_t` is a plain C struct for which all-zero is valid
+        let mut info: ::libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: `info` is a valid pointer for the duration of the call
+        let result = unsafe {
+            ::libc::waitid(
+                ::libc::P_ALL,
+                0,
+                &mut info,
+                ::libc::WEXITED | ::libc::WNOWAIT,
+            )
+        };
+
+        if result == -1 {
+            match std::io::Error::last_os_error().raw_os_error() {
+                Some(::libc::EINTR) => {}
+                Some(::libc::ECHILD) => {
+                    // there are no children: watchers of children that were
+                    // reaped already are told so, and the reaper waits until
+                    // new children are registered
+                    let mut guard = lock(state);
+                    let reaped: Vec<::libc::pid_t> = guard
+                        .watchers
+                        .keys()
+                        .copied()
+                        .filter(|pid| !is_unreaped_child(*pid))
+                        .collect();
+                    for pid in reaped {
+                        if let Some(watcher) = guard.watchers.remove(&pid) {
+                            let _ = watcher.send(ExitStatus::Unknown);
+                        }
+                    }
+                    drop(
+                        condition
+                            .wait_while(guard, |state| state.registrations == registrations)
+                            .unwrap_or_else(std::sync::PoisonError::into_inner),
+                    );
+                }
+                _ => {
+                    ::tracing::error!(
+                        "Reaper could not wait for children: {}",
+                        std::io::Error::last_os_error()
+                    );
+                    std::thread::sleep(std::time::Duration::from_secs(1));
+                }
+            }
+            continue;
+        }
+
+        // SAFETY: `waitid` filled `info` for an exited child
+        let pid = unsafe { info.si_pid() };
+        let mut guard = lock(state);
+
+        if !guard.watchers.contains_key(&pid) && guard.spawning > 0 {
+            // the child might belong to a spawn that has not been registered yet
+            drop(
+                condition
+                    .wait_while(guard, |state| state.spawning > 0)
+                    .unwrap_or_else(std::sync::PoisonError::into_inner),
+            );
+            continue;
+        }
+
+        let mut raw_status = 0;
+        // SAFETY: `raw_status` is a valid pointer for the duration of the call
+        if unsafe { ::libc::waitpid(pid, &mut raw_status, ::libc::WNOHANG) } != pid {
+            // somebody else reaped the child in the meantime, so its exit
+            // status is the one peeked at
+            if let Some(watcher) = guard.watchers.remove(&pid) {
+                let _ = watcher.send(ExitStatus::from_siginfo(&info));
+            }
+            continue;
+        }
+
+        let status = ExitStatus::from_raw(raw_status);
+        match guard.watchers.remove(&pid) {
+            Some(watcher) => {
+                let _ = watcher.send(status);
+            }
+            None => {
+                ::tracing::debug!("Reaped orphaned process {pid} ({status})");
+                if guard.reaped.len() == REAPED_CAPACITY {
+                    guard.reaped.pop_front();
+                }
+                guard.reaped.push_back((pid, status));
+            }
+        }
+    }
+}
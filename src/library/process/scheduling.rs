@@ -0,0 +1,243 @@
+//! Contains [`SchedulingSetup`], the part of [`super::setup::ChildSetup`]
+//! that sets the scheduling priority, CPU affinity, I/O scheduling and OOM
+//! score adjustment of a new process, and [`Scheduling`], the current
+//! values of a running process
+//!
+//! All settings are inherited by children and survive `exec(2)`. They are
+//! applied before credentials are dropped as raising the priority or
+//! lowering the OOM score adjustment requires privileges.
+
+use crate::library::service::{CpuSet, IoClass, Service};
+
+/// `IOPRIO_WHO_PROCESS` of `ioprio_set(2)`
+const IO_PRIORITY_WHO_PROCESS: ::libc::c_int = 1;
+/// `IOPRIO_CLASS_SHIFT` of `ioprio_set(2)`
+const IO_PRIORITY_CLASS_SHIFT: ::libc::c_int = 13;
+
+/// Converts an [`IoClass`] to its `IOPRIO_CLASS_*` constant
+fn raw_io_class(io_class: IoClass) -> ::libc::c_int {
+    match io_class {
+        IoClass::Realtime => 1,
+        IoClass::BestEffort => 2,
+        IoClass::Idle => 3,
+    }
+}
+
+/// How the scheduling of a new process is set up
+#[derive(Debug)]
+pub struct SchedulingSetup {
+    /// The niceness
+    nice: Option<::libc::c_int>,
+    /// The CPUs the process may run on
+    cpu_affinity: Option<::libc::cpu_set_t>,
+    /// The I/O priority as passed to `ioprio_set(2)`
+    io_priority: Option<::libc::c_int>,
+    /// The OOM score adjustment as written to `/proc/self/oom_score_adj`
+    oom_score_adjust: Option<String>,
+}
+
+impl SchedulingSetup {
+    /// Resolves the scheduling setup of `service`
+    ///
+    /// Returns [`None`] if the service keeps the scheduling of `sysinitd`.
+    pub fn new(service: &Service) -> Option<Self> {
+        if !service.has_scheduling() {
+            return None;
+        }
+
+        let cpu_affinity = service.cpu_affinity().map(|cpus| {
+            // SAFETY: `cpu_set_t` is a plain C struct for which all zeros is valid
+            let mut cpu_set: ::libc::cpu_set_t = unsafe { std::mem::zeroed() };
+            for cpu in cpus.cpus() {
+                // SAFETY: `cpu` is below `CPU_SETSIZE`, as checked when parsing
+                unsafe { ::libc::CPU_SET(cpu, &mut cpu_set) };
+            }
+            cpu_set
+        });
+        // the idle class has no priorities, the others default to the middle one
+        let io_priority = service.io_class().map(|io_class| {
+            let default_priority = if io_class == IoClass::Idle { 0 } else { 4 };
+            raw_io_class(io_class) << IO_PRIORITY_CLASS_SHIFT
+                | ::libc::c_int::from(service.io_priority().unwrap_or(default_priority))
+        });
+
+        Some(Self {
+            nice: service.nice(),
+            cpu_affinity,
+            io_priority,
+            oom_score_adjust: service
+                .oom_score_adjust()
+                .map(|adjustment| adjustment.to_string()),
+        })
+    }
+
+    /// Applies the setup in the child process
+    pub fn apply(&self) -> std::io::Result<()> {
+        if let Some(nice) = self.nice {
+            // SAFETY: `setpriority(2)` has no memory safety preconditions
+            if unsafe { ::libc::setpriority(::libc::PRIO_PROCESS, 0, nice) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(cpu_set) = &self.cpu_affinity {
+            // SAFETY: `cpu_set` is valid for the duration of the call
+            if unsafe {
+                ::libc::sched_setaffinity(0, std::mem::size_of::<::libc::cpu_set_t>(), cpu_set)
+            } == -1
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(io_priority) = self.io_priority {
+            // SAFETY: `ioprio_set(2)` has no memory safety preconditions
+            if unsafe {
+                ::libc::syscall(
+                    ::libc::SYS_ioprio_set,
+                    IO_PRIORITY_WHO_PROCESS,
+                    0,
+                    io_priority,
+                )
+            } == -1
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(oom_score_adjust) = &self.oom_score_adjust {
+            // SAFETY: the path and buffer are valid for the duration of the calls
+            unsafe {
+                let file = ::libc::open(
+                    c"/proc/self/oom_score_adj".as_ptr(),
+                    ::libc::O_WRONLY | ::libc::O_CLOEXEC,
+                );
+                if file == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let result = ::libc::write(
+                    file,
+                    oom_score_adjust.as_ptr().cast(),
+                    oom_score_adjust.len(),
+                );
+                let error = std::io::Error::last_os_error();
+                ::libc::close(file);
+                if result == -1 {
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The current scheduling of a running process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheduling {
+    /// The niceness
+    pub nice: i32,
+    /// The CPUs the process may run on
+    pub cpu_affinity: CpuSet,
+    /// The I/O scheduling class ([`None`] if derived from the niceness)
+    pub io_class: Option<IoClass>,
+    /// The I/O priority within [`Scheduling::io_class`]
+    pub io_priority: u8,
+    /// The OOM score adjustment
+    pub oom_score_adjust: i16,
+}
+
+impl Scheduling {
+    /// Reads the scheduling of the process `pid`
+    pub fn of(pid: ::libc::pid_t) -> std::io::Result<Self> {
+        let invalid_data = |what: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Could not parse the {what} of process {pid}"),
+            )
+        };
+
+        // the niceness is the 19th field, the command (2nd field) may contain spaces
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat"))?;
+        let nice = stat
+            .rsplit_once(')')
+            .and_then(|(_, fields)| fields.split_whitespace().nth(16))
+            .and_then(|nice| nice.parse().ok())
+            .ok_or_else(|| invalid_data("niceness"))?;
+
+        // SAFETY: `cpu_set_t` is a plain C struct for which all zeros is valid
+        let mut cpu_set: ::libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        // SAFETY: `cpu_set` is valid for writes for the duration of the call
+        if unsafe {
+            ::libc::sched_getaffinity(pid, std::mem::size_of::<::libc::cpu_set_t>(), &mut cpu_set)
+        } == -1
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        let cpu_affinity = (0..CpuSet::MAXIMUM_CPUS)
+            // SAFETY: `cpu` is below `CPU_SETSIZE`
+            .filter(|cpu| unsafe { ::libc::CPU_ISSET(*cpu, &cpu_set) })
+            .collect();
+
+        // SAFETY: `ioprio_get(2)` has no memory safety preconditions
+        let io_priority =
+            unsafe { ::libc::syscall(::libc::SYS_ioprio_get, IO_PRIORITY_WHO_PROCESS, pid) };
+        if io_priority == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let io_class = match io_priority >> IO_PRIORITY_CLASS_SHIFT {
+            1 => Some(IoClass::Realtime),
+            2 => Some(IoClass::BestEffort),
+            3 => Some(IoClass::Idle),
+            _ => None,
+        };
+
+        let oom_score_adjust = std::fs::read_to_string(format!("/proc/{pid}/oom_score_adj"))?
+            .trim()
+            .parse()
+            .map_err(|_| invalid_data("OOM score adjustment"))?;
+
+        Ok(Self {
+            nice,
+            cpu_affinity,
+            io_class,
+            io_priority: (io_priority & 0x7) as u8,
+            oom_score_adjust,
+        })
+    }
+}
+
+impl std::fmt::Display for Scheduling {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            formatter,
+            "nice={} cpu_affinity={} io=",
+            self.nice, self.cpu_affinity
+        )?;
+        match self.io_class {
+            Some(io_class) => write!(formatter, "{io_class}/{}", self.io_priority)?,
+            None => write!(formatter, "none")?,
+        }
+        write!(formatter, " oom_score_adjust={}", self.oom_score_adjust)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cpu_affinity() {
+        let parse = |yaml: &str| ::serde_yml::from_str::<CpuSet>(yaml).map(|cpus| cpus.to_string());
+        assert_eq!(parse("0-3,6").unwrap(), "0-3,6");
+        assert_eq!(parse("[0, 2-3, 1, 7]").unwrap(), "0-3,7");
+        assert_eq!(parse("5").unwrap(), "5");
+        assert!(parse("3-1").is_err());
+        assert!(parse("[1024]").is_err());
+        assert!(parse("[]").is_err());
+
+        let scheduling = Scheduling::of(std::process::id() as ::libc::pid_t)
+            .expect("Could not read own scheduling");
+        assert!(scheduling.cpu_affinity.cpus().next().is_some());
+    }
+}
@@ -0,0 +1,178 @@
+//! Contains [`SeccompSetup`], the part of [`super::setup::ChildSetup`]
+//! that installs the seccomp filter of a new process
+//!
+//! The filter is a classic BPF program compiled in the parent. It first
+//! kills processes that make system calls of a foreign architecture (whose
+//! numbers differ), then checks the denied system calls, then the allowed
+//! ones. It is installed last, right before `exec(2)`, so that setting up
+//! the process is not restricted.
+
+use crate::library::service::{SeccompAction, Service};
+
+/// `AUDIT_ARCH_*` of the architecture `sysinitd` was built for
+#[cfg(target_arch = "x86_64")]
+const ARCHITECTURE: Option<u32> = Some(0xc000_003e);
+/// `AUDIT_ARCH_*` of the architecture `sysinitd` was built for
+#[cfg(target_arch = "aarch64")]
+const ARCHITECTURE: Option<u32> = Some(0xc000_00b7);
+/// `AUDIT_ARCH_*` of the architecture `sysinitd` was built for
+#[cfg(target_arch = "riscv64")]
+const ARCHITECTURE: Option<u32> = Some(0xc000_00f3);
+/// Seccomp filters are not supported on other architectures
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+const ARCHITECTURE: Option<u32> = None;
+
+/// The offset of `nr` in `struct seccomp_data`
+const SYSCALL_NUMBER_OFFSET: u32 = 0;
+/// The offset of `arch` in `struct seccomp_data`
+const ARCHITECTURE_OFFSET: u32 = 4;
+/// `__X32_SYSCALL_BIT`, set in the numbers of system calls of the x32 ABI
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// `BPF_LD | BPF_W | BPF_ABS`
+const LOAD: u16 = 0x20;
+/// `BPF_JMP | BPF_JEQ | BPF_K`
+const JUMP_IF_EQUAL: u16 = 0x15;
+/// `BPF_JMP | BPF_JGE | BPF_K`
+const JUMP_IF_GREATER_OR_EQUAL: u16 = 0x35;
+/// `BPF_RET | BPF_K`
+const RETURN: u16 = 0x06;
+
+/// Builds a BPF statement
+fn statement(code: u16, k: u32) -> ::libc::sock_filter {
+    ::libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+/// Builds a BPF jump that skips `skip_if_true` or `skip_if_false`
+/// statements
+fn jump(code: u16, k: u32, skip_if_true: u8, skip_if_false: u8) -> ::libc::sock_filter {
+    ::libc::sock_filter {
+        code,
+        jt: skip_if_true,
+        jf: skip_if_false,
+        k,
+    }
+}
+
+/// Converts a [`SeccompAction`] to its `SECCOMP_RET_*` value
+fn raw_action(action: SeccompAction) -> u32 {
+    match action {
+        SeccompAction::Errno => ::libc::SECCOMP_RET_ERRNO | ::libc::EPERM as u32,
+        SeccompAction::Kill => ::libc::SECCOMP_RET_KILL_PROCESS,
+        SeccompAction::Log => ::libc::SECCOMP_RET_LOG,
+    }
+}
+
+/// The seccomp filter of a new process
+#[derive(Debug)]
+pub struct SeccompSetup {
+    /// The compiled BPF program
+    program: Vec<::libc::sock_filter>,
+}
+
+impl SeccompSetup {
+    /// Compiles the seccomp filter of `service`
+    ///
+    /// Returns [`None`] if the service may make all system calls.
+    pub fn new(service: &Service) -> ::anyhow::Result<Option<Self>> {
+        let seccomp = service.seccomp();
+        if seccomp.is_empty() {
+            return Ok(None);
+        }
+        let Some(architecture) = ARCHITECTURE else {
+            ::anyhow::bail!(
+                "Service '{}' has a seccomp filter, which is not supported on this architecture",
+                service.id()
+            );
+        };
+
+        let action = raw_action(seccomp.default_action());
+        let mut program = vec![
+            statement(LOAD, ARCHITECTURE_OFFSET),
+            jump(JUMP_IF_EQUAL, architecture, 1, 0),
+            statement(RETURN, ::libc::SECCOMP_RET_KILL_PROCESS),
+            statement(LOAD, SYSCALL_NUMBER_OFFSET),
+        ];
+        if cfg!(target_arch = "x86_64") {
+            // x32 system calls share the architecture but not the numbers
+            program.push(jump(JUMP_IF_GREATER_OR_EQUAL, X32_SYSCALL_BIT, 0, 1));
+            program.push(statement(RETURN, action));
+        }
+
+        let mut rule = |syscall: ::libc::c_long, result: u32| {
+            program.push(jump(JUMP_IF_EQUAL, syscall as u32, 0, 1));
+            program.push(statement(RETURN, result));
+        };
+        for syscall in seccomp.denied() {
+            rule(syscall.as_raw(), action);
+        }
+        match seccomp.allow() {
+            Some(allowed) => {
+                // executing the command, and reporting that this failed
+                // before exiting, must remain possible
+                for syscall in [
+                    ::libc::SYS_execve,
+                    ::libc::SYS_write,
+                    ::libc::SYS_exit_group,
+                ] {
+                    rule(syscall, ::libc::SECCOMP_RET_ALLOW);
+                }
+                for syscall in allowed {
+                    rule(syscall.as_raw(), ::libc::SECCOMP_RET_ALLOW);
+                }
+                program.push(statement(RETURN, action));
+            }
+            None => program.push(statement(RETURN, ::libc::SECCOMP_RET_ALLOW)),
+        }
+
+        Ok(Some(Self { program }))
+    }
+
+    /// Installs the filter
+    ///
+    /// Without `CAP_SYS_ADMIN` (e.g. after dropping privileges), the kernel
+    /// only accepts filters of processes with `PR_SET_NO_NEW_PRIVS`, which
+    /// is set in that case.
+    pub fn apply(&self) -> std::io::Result<()> {
+        let program = ::libc::sock_fprog {
+            len: self.program.len() as ::libc::c_ushort,
+            filter: self.program.as_ptr().cast_mut(),
+        };
+        let install = || {
+            // SAFETY: `program` points to a valid filter for the duration of the call
+            unsafe {
+                ::libc::prctl(
+                    ::libc::PR_SET_SECCOMP,
+                    ::libc::SECCOMP_MODE_FILTER,
+                    &program as *const ::libc::sock_fprog,
+                    0,
+                    0,
+                )
+            }
+        };
+
+        if install() == 0 {
+            return Ok(());
+        }
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() != Some(::libc::EACCES) {
+            return Err(error);
+        }
+        // SAFETY: `prctl(2)` with `PR_SET_NO_NEW_PRIVS` has no memory safety preconditions
+        if unsafe { ::libc::prctl(::libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } == -1
+            || install() == -1
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
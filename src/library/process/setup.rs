@@ -0,0 +1,223 @@
+//! Contains [`ChildSetup`], everything that is applied to a new process
+//! between `fork(2)` and `exec(2)`
+//!
+//! Everything that can fail for reasons other than the state of the
+//! system (e.g. unknown users) is resolved in [`ChildSetup::new`] in the
+//! parent. [`ChildSetup::apply`] runs in the child and must only call
+//! async-signal-safe functions.
+
+use crate::library::service::{LimitValue, Resource, Service};
+
+use super::{
+    capabilities::CapabilitySetup,
+    cgroup::Cgroup,
+    credentials::{self, Credentials},
+    namespaces::NamespaceSetup,
+    scheduling::SchedulingSetup,
+    seccomp::SeccompSetup,
+    sockets::SocketSetup,
+};
+
+/// The type of the resource argument of `setrlimit(2)`
+#[cfg(target_env = "gnu")]
+type RawResource = ::libc::__rlimit_resource_t;
+/// The type of the resource argument of `setrlimit(2)`
+#[cfg(not(target_env = "gnu"))]
+type RawResource = ::libc::c_int;
+
+/// Converts a [`Resource`] to its `RLIMIT_*` constant
+fn raw_resource(resource: Resource) -> RawResource {
+    match resource {
+        Resource::Nofile => ::libc::RLIMIT_NOFILE,
+        Resource::Nproc => ::libc::RLIMIT_NPROC,
+        Resource::Core => ::libc::RLIMIT_CORE,
+        Resource::Memlock => ::libc::RLIMIT_MEMLOCK,
+        Resource::As => ::libc::RLIMIT_AS,
+        Resource::Stack => ::libc::RLIMIT_STACK,
+        Resource::Cpu => ::libc::RLIMIT_CPU,
+    }
+}
+
+/// Converts a [`LimitValue`] to its `rlim_t` representation
+fn raw_limit(value: LimitValue) -> ::libc::rlim_t {
+    match value {
+        LimitValue::Finite(value) => value as ::libc::rlim_t,
+        LimitValue::Unlimited => ::libc::RLIM_INFINITY,
+    }
+}
+
+/// Everything that is applied to a new process before it executes the
+/// command of its service
+#[derive(Debug)]
+pub struct ChildSetup {
+    /// `cgroup.procs` of the cgroup to move into
+    cgroup_procs: Option<std::os::fd::OwnedFd>,
+    /// The resource limits to set
+    limits: Vec<(RawResource, ::libc::rlimit)>,
+    /// How the process is scheduled
+    scheduling: Option<SchedulingSetup>,
+    /// How the process is moved into new namespaces
+    namespaces: Option<NamespaceSetup>,
+    /// The directory to change the root directory to
+    root_directory: Option<std::ffi::CString>,
+    /// The directory to change into
+    working_directory: Option<std::ffi::CString>,
+    /// The file mode creation mask
+    umask: Option<::libc::mode_t>,
+    /// The credentials to drop to
+    credentials: Option<Credentials>,
+    /// How capabilities are restricted
+    capabilities: Option<CapabilitySetup>,
+    /// Whether `PR_SET_NO_NEW_PRIVS` is set
+    no_new_privileges: bool,
+    /// The seccomp filter to install
+    seccomp: Option<SeccompSetup>,
+    /// The sockets to pass, in which case the command is executed by
+    /// [`ChildSetup::apply`] itself
+    sockets: Option<SocketSetup>,
+}
+
+impl ChildSetup {
+    /// Resolves the setup for `service`, which runs in `cgroup` and gets
+    /// `sockets` passed
+    pub fn new(
+        service: &Service,
+        cgroup: Option<&Cgroup>,
+        sockets: Option<SocketSetup>,
+    ) -> ::anyhow::Result<Self> {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let c_path = |path: &std::path::Path| std::ffi::CString::new(path.as_os_str().as_bytes());
+        let root_directory = service.start().root_directory().map(c_path).transpose()?;
+        // after changing the root directory, the working directory defaults to it
+        let working_directory = match service.start().working_directory() {
+            Some(working_directory) => Some(c_path(working_directory)?),
+            None if root_directory.is_some() => Some(c"/".to_owned()),
+            None => None,
+        };
+
+        let limits = service
+            .limits()
+            .iter()
+            .map(|(resource, limit)| {
+                (
+                    raw_resource(*resource),
+                    ::libc::rlimit {
+                        rlim_cur: raw_limit(limit.soft),
+                        rlim_max: raw_limit(limit.hard),
+                    },
+                )
+            })
+            .collect();
+
+        Ok(Self {
+            cgroup_procs: cgroup.map(Cgroup::open_procs).transpose()?,
+            limits,
+            scheduling: SchedulingSetup::new(service),
+            namespaces: NamespaceSetup::new(service)?,
+            root_directory,
+            working_directory,
+            umask: service.start().umask(),
+            credentials: credentials::resolve(service)?,
+            capabilities: CapabilitySetup::new(service),
+            no_new_privileges: service.no_new_privileges(),
+            seccomp: SeccompSetup::new(service)?,
+            sockets,
+        })
+    }
+
+    /// Applies the setup in the child process
+    ///
+    /// If sockets are passed, the command is executed here and this only
+    /// returns on errors.
+    pub fn apply(&mut self) -> std::io::Result<()> {
+        // every service runs in a session (and process group) of its own:
+        // signals sent to the process group of `sysinitd` (e.g. by pressing
+        // Ctrl+C in a terminal) must not reach services directly
+        // SAFETY: `setsid(2)` has no memory safety preconditions
+        if unsafe { ::libc::setsid() } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        if let Some(cgroup_procs) = &self.cgroup_procs {
+            use std::os::fd::AsRawFd as _;
+
+            // writing `0` moves the calling process
+            // SAFETY: the buffer is valid for the duration of the call
+            if unsafe { ::libc::write(cgroup_procs.as_raw_fd(), c"0".as_ptr().cast(), 1) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        // raising hard limits requires privileges
+        for (resource, limit) in &self.limits {
+            // SAFETY: `limit` is valid for the duration of the call
+            if unsafe { ::libc::setrlimit(*resource, limit) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(scheduling) = &self.scheduling {
+            scheduling.apply()?;
+        }
+
+        // mounts in a new mount namespace refer to the original root
+        // directory, so namespaces are created before changing it
+        if let Some(namespaces) = &self.namespaces {
+            namespaces.apply()?;
+        }
+
+        if let Some(root_directory) = &self.root_directory {
+            // SAFETY: `root_directory` is a valid C string
+            if unsafe { ::libc::chroot(root_directory.as_ptr()) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(working_directory) = &self.working_directory {
+            // SAFETY: `working_directory` is a valid C string
+            if unsafe { ::libc::chdir(working_directory.as_ptr()) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(umask) = self.umask {
+            // SAFETY: `umask(2)` has no memory safety preconditions
+            unsafe { ::libc::umask(umask) };
+        }
+
+        if let Some(capabilities) = &self.capabilities {
+            capabilities.apply_before_credentials()?;
+        }
+
+        // dropping privileges comes last as all other steps may require them
+        if let Some(credentials) = &self.credentials {
+            credentials.apply()?;
+        }
+
+        if let Some(capabilities) = &self.capabilities {
+            capabilities.apply_after_credentials()?;
+        }
+
+        if self.no_new_privileges {
+            // SAFETY: `prctl(2)` with `PR_SET_NO_NEW_PRIVS` has no memory safety preconditions
+            if unsafe { ::libc::prctl(::libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(sockets) = &mut self.sockets {
+            sockets.apply()?;
+        }
+
+        // the filter would otherwise restrict the steps above
+        if let Some(seccomp) = &self.seccomp {
+            seccomp.apply()?;
+        }
+
+        match &self.sockets {
+            Some(sockets) => Err(sockets.exec()),
+            None => Ok(()),
+        }
+    }
+}
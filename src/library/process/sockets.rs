@@ -0,0 +1,311 @@
+//! Contains [`Listeners`], the sockets (and FIFOs) `sysinitd` binds for a
+//! service, and [`SocketSetup`], the part of [`super::setup::ChildSetup`]
+//! that passes them on like systemd's socket activation
+//!
+//! The sockets are passed as file descriptors 3 and following and announced
+//! in `LISTEN_FDS`, `LISTEN_FDNAMES` and `LISTEN_PID`. As `LISTEN_PID` must
+//! be the PID of the process executing the command, which is only known
+//! after `fork(2)`, the environment is built in the parent with a
+//! placeholder that is filled in in the child, which then executes the
+//! command itself.
+
+use crate::library::service::{Service, SocketAddress};
+
+/// The environment variable containing the number of passed sockets
+const FDS_ENVIRONMENT_VARIABLE: &str = "LISTEN_FDS";
+/// The environment variable containing the names of the passed sockets
+const FDNAMES_ENVIRONMENT_VARIABLE: &str = "LISTEN_FDNAMES";
+/// The environment variable containing the PID the sockets are meant for
+const PID_ENVIRONMENT_VARIABLE: &str = "LISTEN_PID";
+/// The first file descriptor sockets are passed as (`SD_LISTEN_FDS_START`)
+const FIRST_FD: ::libc::c_int = 3;
+
+/// The sockets `sysinitd` bound for a service
+///
+/// They stay open for as long as `sysinitd` runs, so that clients can
+/// connect while the service is not (yet) running.
+#[derive(Debug)]
+pub struct Listeners {
+    /// The names and file descriptors of the sockets
+    sockets: Vec<(String, std::os::fd::OwnedFd)>,
+    /// The paths of the Unix sockets and FIFOs that were created
+    paths: Vec<std::path::PathBuf>,
+}
+
+impl Listeners {
+    /// Binds all sockets of `service`
+    ///
+    /// Stale Unix sockets are replaced; FIFOs are created if they do not
+    /// exist yet.
+    pub fn bind(service: &Service) -> ::anyhow::Result<Self> {
+        use ::anyhow::Context as _;
+
+        let mut listeners = Self {
+            sockets: Vec::with_capacity(service.sockets().listen().len()),
+            paths: Vec::new(),
+        };
+        for socket in service.sockets().listen() {
+            let address = socket.address();
+            let fd = Self::bind_one(address).context(format!(
+                "Could not listen on {address} of service '{}'",
+                service.id()
+            ))?;
+            let name = socket.name().unwrap_or(service.id()).to_string();
+            listeners.sockets.push((name, fd));
+            if let Some(path) = address.path() {
+                listeners.paths.push(path.to_path_buf());
+            }
+        }
+        Ok(listeners)
+    }
+
+    /// Binds a single socket (or opens a FIFO)
+    fn bind_one(address: &SocketAddress) -> std::io::Result<std::os::fd::OwnedFd> {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let remove_stale_socket = |path: &std::path::Path| {
+            use std::os::unix::fs::FileTypeExt as _;
+
+            if std::fs::symlink_metadata(path)
+                .is_ok_and(|metadata| metadata.file_type().is_socket())
+            {
+                std::fs::remove_file(path)?;
+            }
+            Ok::<_, std::io::Error>(())
+        };
+
+        Ok(match address {
+            SocketAddress::Tcp(address) => std::net::TcpListener::bind(address)?.into(),
+            SocketAddress::UnixStream(path) => {
+                remove_stale_socket(path)?;
+                std::os::unix::net::UnixListener::bind(path)?.into()
+            }
+            SocketAddress::UnixDatagram(path) => {
+                remove_stale_socket(path)?;
+                std::os::unix::net::UnixDatagram::bind(path)?.into()
+            }
+            SocketAddress::Fifo(path) => {
+                let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+                // SAFETY: `c_path` is a valid C string
+                if unsafe { ::libc::mkfifo(c_path.as_ptr(), 0o666) } == -1 {
+                    let error = std::io::Error::last_os_error();
+                    if error.kind() != std::io::ErrorKind::AlreadyExists {
+                        return Err(error);
+                    }
+                }
+                // opening for reading and writing neither blocks until a
+                // writer appears nor yields end-of-file once it is gone
+                std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(path)?
+                    .into()
+            }
+        })
+    }
+
+    /// Waits until a client connected to (or wrote to) one of the sockets
+    ///
+    /// Nothing is accepted or read: that is left to the service. Must be
+    /// called from within a [`::tokio`] runtime.
+    pub fn activity(&self) -> std::io::Result<impl Future<Output = ()> + Send + 'static> {
+        use std::os::fd::AsRawFd as _;
+
+        let fds = self
+            .sockets
+            .iter()
+            .map(|(_, fd)| {
+                let fd = fd.try_clone()?;
+                // SAFETY: the duplicate stays open for as long as the
+                // `AsyncFd`, which is dropped first as it comes first
+                let async_fd = unsafe {
+                    ::tokio::io::unix::AsyncFd::register_with_interest(
+                        fd.as_raw_fd(),
+                        ::tokio::io::Interest::READABLE,
+                    )?
+                };
+                Ok((async_fd, fd))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(async move {
+            std::future::poll_fn(|context| {
+                if fds
+                    .iter()
+                    .any(|(fd, _)| fd.poll_read_ready(context).is_ready())
+                {
+                    std::task::Poll::Ready(())
+                } else {
+                    std::task::Poll::Pending
+                }
+            })
+            .await;
+        })
+    }
+
+    /// Removes the Unix sockets and FIFOs that were created
+    pub fn remove_files(&self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Raw pointers to C strings as expected by `execvpe(3)`
+#[derive(Debug)]
+struct Pointers(Vec<*const ::libc::c_char>);
+
+// SAFETY: the pointers refer to heap buffers owned by the same
+// `SocketSetup` and are only dereferenced by `execvpe(3)`
+unsafe impl Send for Pointers {}
+// SAFETY: see above
+unsafe impl Sync for Pointers {}
+
+/// How sockets are passed to a new process, which then executes the
+/// command of its service
+#[derive(Debug)]
+pub struct SocketSetup {
+    /// The file descriptors of the sockets, valid in the child as well
+    fds: Vec<::libc::c_int>,
+    /// The command
+    program: std::ffi::CString,
+    /// The command and its arguments
+    _arguments: Vec<std::ffi::CString>,
+    /// Pointers to [`SocketSetup::_arguments`]
+    argument_pointers: Pointers,
+    /// The environment except for `LISTEN_PID`
+    _environment: Vec<std::ffi::CString>,
+    /// `LISTEN_PID=` followed by space for the PID and a terminating NUL
+    listen_pid: Vec<u8>,
+    /// Pointers to [`SocketSetup::_environment`] and
+    /// [`SocketSetup::listen_pid`]
+    environment_pointers: Pointers,
+}
+
+impl SocketSetup {
+    /// Prepares passing `listeners` to the process spawned by `command`,
+    /// whose program, arguments and environment must not change anymore
+    pub fn new(command: &std::process::Command, listeners: &Listeners) -> ::anyhow::Result<Self> {
+        use std::os::{fd::AsRawFd as _, unix::ffi::OsStrExt as _};
+
+        let c_string = |value: &std::ffi::OsStr| std::ffi::CString::new(value.as_bytes());
+
+        let program = c_string(command.get_program())?;
+        let arguments = std::iter::once(Ok(program.clone()))
+            .chain(command.get_args().map(c_string))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut variables: std::collections::BTreeMap<_, _> = std::env::vars_os().collect();
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => variables.insert(key.to_os_string(), value.to_os_string()),
+                None => variables.remove(key),
+            };
+        }
+        variables.remove(std::ffi::OsStr::new(PID_ENVIRONMENT_VARIABLE));
+        let names: Vec<&str> = listeners
+            .sockets
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        variables.insert(
+            FDS_ENVIRONMENT_VARIABLE.into(),
+            listeners.sockets.len().to_string().into(),
+        );
+        variables.insert(FDNAMES_ENVIRONMENT_VARIABLE.into(), names.join(":").into());
+        let environment = variables
+            .into_iter()
+            .map(|(key, value)| {
+                let mut variable = key;
+                variable.push("=");
+                variable.push(value);
+                c_string(&variable)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // a PID has at most 10 digits
+        let mut listen_pid = format!("{PID_ENVIRONMENT_VARIABLE}=").into_bytes();
+        listen_pid.resize(listen_pid.len() + 11, 0);
+
+        let argument_pointers = arguments
+            .iter()
+            .map(|argument| argument.as_ptr())
+            .chain(std::iter::once(std::ptr::null()))
+            .collect();
+        let environment_pointers = environment
+            .iter()
+            .map(|variable| variable.as_ptr())
+            .chain([listen_pid.as_ptr().cast(), std::ptr::null()])
+            .collect();
+
+        Ok(Self {
+            fds: listeners
+                .sockets
+                .iter()
+                .map(|(_, fd)| fd.as_raw_fd())
+                .collect(),
+            program,
+            _arguments: arguments,
+            argument_pointers: Pointers(argument_pointers),
+            _environment: environment,
+            listen_pid,
+            environment_pointers: Pointers(environment_pointers),
+        })
+    }
+
+    /// Moves the sockets to file descriptors 3 and following (without
+    /// `FD_CLOEXEC`) and fills in `LISTEN_PID`
+    ///
+    /// Runs in the child before the seccomp filter is installed, which may
+    /// forbid the system calls involved.
+    pub fn apply(&mut self) -> std::io::Result<()> {
+        let count = self.fds.len() as ::libc::c_int;
+        // moving the sockets out of the way first ensures that no socket is
+        // overwritten before it was moved
+        for fd in &mut self.fds {
+            // SAFETY: `fcntl(2)` with `F_DUPFD_CLOEXEC` has no memory safety preconditions
+            *fd = unsafe { ::libc::fcntl(*fd, ::libc::F_DUPFD_CLOEXEC, FIRST_FD + count) };
+            if *fd == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        for (target, fd) in (FIRST_FD..).zip(&self.fds) {
+            // SAFETY: `dup2(2)` has no memory safety preconditions
+            if unsafe { ::libc::dup2(*fd, target) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        // SAFETY: `getpid(2)` has no memory safety preconditions
+        let mut pid = unsafe { ::libc::getpid() };
+        let digits_start = PID_ENVIRONMENT_VARIABLE.len() + 1;
+        let mut digits = [0; 10];
+        let mut length = 0;
+        while pid > 0 || length == 0 {
+            digits[length] = b'0' + (pid % 10) as u8;
+            pid /= 10;
+            length += 1;
+        }
+        for (index, digit) in digits[..length].iter().rev().enumerate() {
+            self.listen_pid[digits_start + index] = *digit;
+        }
+        self.listen_pid[digits_start + length] = 0;
+        Ok(())
+    }
+
+    /// Executes the command with the prepared environment
+    ///
+    /// Only returns if that failed.
+    pub fn exec(&self) -> std::io::Error {
+        // SAFETY: all pointers refer to NUL-terminated strings owned by
+        // `self`, and both arrays are terminated by a null pointer
+        unsafe {
+            ::libc::execvpe(
+                self.program.as_ptr(),
+                self.argument_pointers.0.as_ptr(),
+                self.environment_pointers.0.as_ptr(),
+            );
+        }
+        std::io::Error::last_os_error()
+    }
+}
@@ -0,0 +1,399 @@
+//! Contains [`Timer`], which describes when a scheduled service is run:
+//! either a cron expression or a fixed interval (e.g. `every 15m`)
+//!
+//! Cron expressions consist of the five fields minute, hour, day of month,
+//! month and day of week. Every field is `*` or a list of values and ranges
+//! (`1,5-7`), optionally with a step (`*/15`); months and days of week can
+//! also be given by their English abbreviations (`jan`, `mon`). The macros
+//! `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are supported as
+//! well. Cron expressions are evaluated in UTC.
+//!
+//! For persistent schedules, the time of the last run of every service is
+//! recorded in the state directory, so that runs missed while `sysinitd`
+//! was not running can be caught up.
+
+/// The directory persistent state is kept in if none is given
+pub const DEFAULT_STATE_DIRECTORY: &str = "/var/lib/sysinitd";
+
+/// The environment variable that overrides [`DEFAULT_STATE_DIRECTORY`]
+pub const STATE_DIRECTORY_ENVIRONMENT_VARIABLE: &str = "SYSINITD_STATE_DIRECTORY";
+
+/// The number of seconds per minute
+const SECONDS_PER_MINUTE: u64 = 60;
+/// The number of minutes per day
+const MINUTES_PER_DAY: u64 = 24 * 60;
+/// How many days are searched for the next match of a cron expression
+/// before giving up (e.g. for `0 0 30 2 *`)
+const MAXIMUM_SEARCHED_DAYS: u64 = 5 * 366;
+
+/// The abbreviations of months, starting with January (1)
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+/// The abbreviations of days of week, starting with Sunday (0)
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// When a scheduled service is run
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum Timer {
+    /// Whenever the cron expression matches
+    Cron(Cron),
+    /// Once per interval
+    Every(std::time::Duration),
+}
+
+impl Timer {
+    /// When the timer elapses next after `after`
+    ///
+    /// Returns [`None`] if it never elapses again.
+    pub fn next(&self, after: std::time::SystemTime) -> Option<std::time::SystemTime> {
+        match self {
+            Self::Cron(cron) => cron.next(after),
+            Self::Every(interval) => after.checked_add(*interval),
+        }
+    }
+}
+
+impl std::str::FromStr for Timer {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if let Some(interval) = value.strip_prefix("every ") {
+            let interval = ::humantime::parse_duration(interval.trim())
+                .map_err(|error| format!("'{value}' is not a valid interval: {error}"))?;
+            if interval.is_zero() {
+                return Err(format!("Interval of '{value}' must be greater than zero"));
+            }
+            return Ok(Self::Every(interval));
+        }
+        value.parse().map(Self::Cron)
+    }
+}
+
+impl TryFrom<String> for Timer {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl std::fmt::Display for Timer {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cron(cron) => write!(formatter, "{}", cron.expression),
+            Self::Every(interval) => {
+                write!(
+                    formatter,
+                    "every {}",
+                    ::humantime::format_duration(*interval)
+                )
+            }
+        }
+    }
+}
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    /// The expression as written
+    expression: String,
+    /// The matching minutes (bit 0 is minute 0)
+    minutes: u64,
+    /// The matching hours
+    hours: u64,
+    /// The matching days of month (bit 1 is the first day)
+    days: u64,
+    /// The matching months (bit 1 is January)
+    months: u64,
+    /// The matching days of week (bit 0 is Sunday)
+    weekdays: u64,
+    /// Whether the day of month is restricted, i.e. does not start with `*`
+    days_restricted: bool,
+    /// Whether the day of week is restricted, i.e. does not start with `*`
+    weekdays_restricted: bool,
+}
+
+impl Cron {
+    /// Parses a single field whose values range from `minimum` to `maximum`
+    /// into a bit set and returns whether it is restricted
+    fn parse_field(
+        field: &str,
+        name: &str,
+        minimum: u64,
+        maximum: u64,
+        names: &[&str],
+    ) -> Result<(u64, bool), String> {
+        let value = |value: &str| -> Result<u64, String> {
+            let lower_case = value.to_ascii_lowercase();
+            let parsed = match names.iter().position(|name| *name == lower_case) {
+                Some(index) => index as u64 + minimum,
+                None => value
+                    .parse()
+                    .map_err(|_| format!("'{value}' is not a valid {name}"))?,
+            };
+            if !(minimum..=maximum).contains(&parsed) {
+                return Err(format!(
+                    "{name} {parsed} is not between {minimum} and {maximum}"
+                ));
+            }
+            Ok(parsed)
+        };
+
+        let mut bits = 0;
+        for item in field.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => match step.parse::<u64>() {
+                    Ok(step) if step > 0 => (range, step),
+                    _ => return Err(format!("'{step}' is not a valid step of the {name}")),
+                },
+                None => (item, 1),
+            };
+            let (start, end) = match range {
+                "*" => (minimum, maximum),
+                range => match range.split_once('-') {
+                    Some((start, end)) => (value(start)?, value(end)?),
+                    // `5/10` means from 5 to the maximum in steps of 10
+                    None if step > 1 => (value(range)?, maximum),
+                    None => {
+                        let value = value(range)?;
+                        (value, value)
+                    }
+                },
+            };
+            if start > end {
+                return Err(format!("Range '{range}' of the {name} is empty"));
+            }
+            for value in (start..=end).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+        // like in other crons, `*/2` counts as unrestricted as well
+        Ok((bits, !field.starts_with('*')))
+    }
+
+    /// Whether the day `days` (since 1970-01-01) matches
+    fn matches_day(&self, days: u64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        // 1970-01-01 was a Thursday
+        let weekday = (days + 4) % 7;
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        let day_matches = self.days & (1 << day) != 0;
+        let weekday_matches = self.weekdays & (1 << weekday) != 0;
+        // like in other crons, restricting both matches either of them
+        if self.days_restricted && self.weekdays_restricted {
+            day_matches || weekday_matches
+        } else {
+            day_matches && weekday_matches
+        }
+    }
+
+    /// The first matching minute after `after`
+    fn next(&self, after: std::time::SystemTime) -> Option<std::time::SystemTime> {
+        let seconds = after
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let mut minutes = seconds / SECONDS_PER_MINUTE + 1;
+        let last_day = minutes / MINUTES_PER_DAY + MAXIMUM_SEARCHED_DAYS;
+
+        loop {
+            let days = minutes / MINUTES_PER_DAY;
+            if days > last_day {
+                return None;
+            }
+            if !self.matches_day(days) {
+                minutes = (days + 1) * MINUTES_PER_DAY;
+                continue;
+            }
+            let hour = minutes % MINUTES_PER_DAY / 60;
+            if self.hours & (1 << hour) == 0 {
+                minutes = days * MINUTES_PER_DAY + (hour + 1) * 60;
+                continue;
+            }
+            if self.minutes & (1 << (minutes % 60)) == 0 {
+                minutes += 1;
+                continue;
+            }
+            return Some(
+                std::time::UNIX_EPOCH
+                    + std::time::Duration::from_secs(minutes * SECONDS_PER_MINUTE),
+            );
+        }
+    }
+}
+
+impl std::str::FromStr for Cron {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let fields = match value {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            fields => fields,
+        };
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "'{value}' is neither an interval ('every 15m') nor a cron expression with five fields"
+            ));
+        };
+
+        let (minutes, _) = Self::parse_field(minute, "minute", 0, 59, &[])?;
+        let (hours, _) = Self::parse_field(hour, "hour", 0, 23, &[])?;
+        let (days, days_restricted) = Self::parse_field(day, "day of month", 1, 31, &[])?;
+        let (months, _) = Self::parse_field(month, "month", 1, 12, &MONTHS)?;
+        let (mut weekdays, weekdays_restricted) =
+            Self::parse_field(weekday, "day of week", 0, 7, &WEEKDAYS)?;
+        // both 0 and 7 are Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(Self {
+            expression: value.to_string(),
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            days_restricted,
+            weekdays_restricted,
+        })
+    }
+}
+
+/// The file the last run of the service `id` is recorded in
+fn stamp_path(state_directory: &std::path::Path, id: &str) -> std::path::PathBuf {
+    state_directory.join("timers").join(id)
+}
+
+/// Reads when the service `id` was last run, if that was recorded
+pub fn last_run(state_directory: &std::path::Path, id: &str) -> Option<std::time::SystemTime> {
+    let seconds = std::fs::read_to_string(stamp_path(state_directory, id))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds))
+}
+
+/// Records that the service `id` was run at `time`
+pub fn record_run(
+    state_directory: &std::path::Path,
+    id: &str,
+    time: std::time::SystemTime,
+) -> std::io::Result<()> {
+    let path = stamp_path(state_directory, id);
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let seconds = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    std::fs::write(path, format!("{seconds}\n"))
+}
+
+/// A random duration below `maximum`, or no delay if no random numbers
+/// are available
+pub fn random_delay(maximum: std::time::Duration) -> std::time::Duration {
+    if maximum.is_zero() {
+        return maximum;
+    }
+    let mut random = [0; 8];
+    // SAFETY: `random` is valid for writes of its length for the duration of the call
+    if unsafe { ::libc::getrandom(random.as_mut_ptr().cast(), random.len(), 0) } == -1 {
+        ::tracing::warn!(
+            "Could not determine a random delay: {}",
+            std::io::Error::last_os_error()
+        );
+        return std::time::Duration::ZERO;
+    }
+    let nanoseconds = u64::from_ne_bytes(random) as u128 % maximum.as_nanos();
+    std::time::Duration::from_nanos(nanoseconds as u64)
+}
+
+/// Converts days since 1970-01-01 to year, month (1 to 12) and day (1 to
+/// 31) of the proleptic Gregorian calendar
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_elapse() {
+        let time = |text: &str| ::humantime::parse_rfc3339(text).expect("Invalid time");
+        let next = |timer: &str, after: &str| {
+            timer
+                .parse::<Timer>()
+                .expect("Invalid timer")
+                .next(time(after))
+                .map(|next| ::humantime::format_rfc3339_seconds(next).to_string())
+        };
+
+        assert_eq!(
+            next("*/15 * * * *", "2026-10-19T04:50:10Z").as_deref(),
+            Some("2026-10-19T05:00:00Z")
+        );
+        assert_eq!(
+            next("30 2 * * mon-fri", "2026-10-17T12:00:00Z").as_deref(),
+            Some("2026-10-19T02:30:00Z")
+        );
+        assert_eq!(
+            next("0 0 29 feb *", "2026-01-01T00:00:00Z").as_deref(),
+            Some("2028-02-29T00:00:00Z")
+        );
+        assert_eq!(
+            next("@monthly", "2026-12-31T23:59:00Z").as_deref(),
+            Some("2027-01-01T00:00:00Z")
+        );
+        assert_eq!(
+            next("every 15m", "2026-10-19T04:50:10Z").as_deref(),
+            Some("2026-10-19T05:05:10Z")
+        );
+        assert_eq!(next("0 0 30 2 *", "2026-01-01T00:00:00Z"), None);
+        // a step over `*` does not make the day of month restricted, so
+        // both days have to match
+        assert_eq!(
+            next("0 0 */2 * mon", "2026-10-19T00:00:10Z").as_deref(),
+            Some("2026-11-09T00:00:00Z")
+        );
+
+        assert!("* * * *".parse::<Timer>().is_err());
+        assert!("60 * * * *".parse::<Timer>().is_err());
+        assert!("5-1 * * * *".parse::<Timer>().is_err());
+        assert!("*/0 * * * *".parse::<Timer>().is_err());
+        assert!("every 0s".parse::<Timer>().is_err());
+        assert_eq!(
+            "every 1h 30m"
+                .parse::<Timer>()
+                .map(|timer| timer.to_string()),
+            Ok(String::from("every 1h 30m"))
+        );
+    }
+}
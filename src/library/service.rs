@@ -1,11 +1,120 @@
 //! Contains the definition of a service in [`Service`] as well
 //! as all data and functions associated with processes.
 
+/// The target services belong to if they do not list any
+pub const DEFAULT_TARGET: &str = "default";
+
+/// The environment variable that selects the target to bring up
+pub const TARGET_ENVIRONMENT_VARIABLE: &str = "SYSINITD_TARGET";
+
+/// The environment variable that contains the instance name of a
+/// templated service
+pub const INSTANCE_ENVIRONMENT_VARIABLE: &str = "SYSINITD_INSTANCE";
+
+/// The environment variable that contains the index of the instance of a
+/// templated service
+pub const INDEX_ENVIRONMENT_VARIABLE: &str = "SYSINITD_INSTANCE_INDEX";
+
+/// The maximum number of replicas of a template, which keeps a typo from
+/// creating more services than `sysinitd` can handle
+pub const MAX_REPLICAS: usize = 1024;
+
+/// The placeholder replaced by the instance name in templates
+const INSTANCE_PLACEHOLDER: &str = "{{instance}}";
+
+/// The placeholder replaced by the instance index in templates
+const INDEX_PLACEHOLDER: &str = "{{index}}";
+
 #[derive(Debug, ::serde::Deserialize)]
 pub struct Service {
     meta: Meta,
     id: String,
+    /// The names of the instances if the definition is a template
+    instances: Option<Vec<String>>,
+    /// The number of instances if the definition is a template
+    replicas: Option<usize>,
+    /// The instance of a template this service is
+    #[serde(skip)]
+    instance: Option<Instance>,
+    /// Whether `sysinitd` exits together with this service
+    #[serde(default)]
+    main: bool,
+    /// Whether the service is started together with `sysinitd`
+    #[serde(default = "Service::default_true")]
+    autostart: bool,
+    /// Whether the service may be started at all
+    #[serde(default = "Service::default_true")]
+    enabled: bool,
+    /// The targets the service belongs to
+    #[serde(default = "Service::default_targets")]
+    targets: Vec<String>,
+    /// What must hold for the service to be started instead of skipped
+    #[serde(default)]
+    conditions: Vec<crate::library::condition::Condition>,
+    /// What must hold for the service definitions to be valid
+    #[serde(default)]
+    assertions: Vec<crate::library::condition::Condition>,
+    /// Whether the service keeps running or runs to completion
+    #[serde(default, rename = "type")]
+    service_type: ServiceType,
+    /// Whether a oneshot service stays active after it completed
+    #[serde(default)]
+    remain_active: bool,
+    /// The file a forking service writes the PID of its daemon to
+    pid_file: Option<std::path::PathBuf>,
+    /// How often a notify service has to ping its watchdog
+    watchdog: Option<Watchdog>,
+    /// The sockets `sysinitd` binds for the service
+    #[serde(default)]
+    sockets: Sockets,
+    /// When the service is run periodically
+    schedule: Option<Schedule>,
+    /// What the service is started on
+    #[serde(default)]
+    trigger: Trigger,
     start: Start,
+    #[serde(default)]
+    termination: Termination,
+    #[serde(default)]
+    signals: Signals,
+    /// The user the service runs as
+    user: Option<NameOrId>,
+    /// The primary group the service runs as
+    group: Option<NameOrId>,
+    /// Additional groups the service is a member of
+    supplementary_groups: Option<Vec<NameOrId>>,
+    /// The resource limits of the service
+    #[serde(default)]
+    limits: std::collections::BTreeMap<Resource, Limit>,
+    /// The settings of the cgroup of the service
+    #[serde(default)]
+    cgroup: CgroupSettings,
+    /// The capabilities of the service
+    #[serde(default)]
+    capabilities: Capabilities,
+    /// Whether the service can never gain privileges (e.g. via
+    /// set-user-ID programs or file capabilities)
+    #[serde(default)]
+    no_new_privileges: bool,
+    /// The namespaces the service is moved into
+    #[serde(default)]
+    namespaces: Namespaces,
+    /// The system calls the service may make
+    #[serde(default)]
+    seccomp: Seccomp,
+    /// The niceness of the service
+    #[serde(default, deserialize_with = "deserialize::option_nice")]
+    nice: Option<i32>,
+    /// The CPUs the service may run on
+    cpu_affinity: Option<CpuSet>,
+    /// The I/O scheduling class of the service
+    io_class: Option<IoClass>,
+    /// The I/O priority of the service within its class
+    #[serde(default, deserialize_with = "deserialize::option_io_priority")]
+    io_priority: Option<u8>,
+    /// How much more or less likely the OOM killer picks the service
+    #[serde(default, deserialize_with = "deserialize::option_oom_score_adjust")]
+    oom_score_adjust: Option<i16>,
 }
 
 impl PartialEq for Service {
@@ -23,25 +132,526 @@ fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
 }
 
 impl Service {
+    /// The default of flags that are usually set
+    fn default_true() -> bool {
+        true
+    }
+
+    /// The targets of services that do not list any
+    fn default_targets() -> Vec<String> {
+        vec![String::from(DEFAULT_TARGET)]
+    }
+
     /// TODO
     pub fn id(&self) -> &String {
         &self.id
     }
 
+    /// The instance of a template this service is, if it is one
+    pub fn instance(&self) -> Option<&Instance> {
+        self.instance.as_ref()
+    }
+
+    /// Whether this service is the main service
+    ///
+    /// When the main service exits, `sysinitd` shuts down all other
+    /// services and exits with the exit code of the main service.
+    pub fn main(&self) -> bool {
+        self.main
+    }
+
+    /// Whether the service is started together with `sysinitd`
+    ///
+    /// Otherwise, it is only started on request (`sysinitctl start`) or
+    /// when a service depending on it is started.
+    pub fn autostart(&self) -> bool {
+        self.autostart
+    }
+
+    /// Whether the service may be started at all
+    ///
+    /// Disabled services are parsed and validated, but never started, and
+    /// dependencies on them are ignored.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The targets (e.g. `default`, `debug` or `migration-only`) the
+    /// service belongs to
+    ///
+    /// Only the services of the selected target and their dependencies are
+    /// started. Services that do not list any targets belong to
+    /// [`DEFAULT_TARGET`].
+    pub fn targets(&self) -> &[String] {
+        &self.targets
+    }
+
+    /// What must hold whenever the service is about to start; otherwise, it
+    /// is skipped (and services depending on it are started nonetheless)
+    pub fn conditions(&self) -> &[crate::library::condition::Condition] {
+        &self.conditions
+    }
+
+    /// What must hold when the service definitions are checked; otherwise,
+    /// the checks fail
+    pub fn assertions(&self) -> &[crate::library::condition::Condition] {
+        &self.assertions
+    }
+
+    /// Whether the service keeps running or runs to completion
+    pub fn service_type(&self) -> ServiceType {
+        self.service_type
+    }
+
+    /// Whether a oneshot service is reported as active instead of exited
+    /// after it completed successfully
+    pub fn remain_active(&self) -> bool {
+        self.remain_active
+    }
+
+    /// The file a forking service writes the PID of its daemon to
+    pub fn pid_file(&self) -> Option<&std::path::Path> {
+        self.pid_file.as_deref()
+    }
+
+    /// How often a notify service has to send `WATCHDOG=1` before it is
+    /// considered hung
+    pub fn watchdog(&self) -> Option<&Watchdog> {
+        self.watchdog.as_ref()
+    }
+
+    /// The sockets `sysinitd` binds for the service and passes to it
+    pub fn sockets(&self) -> &Sockets {
+        &self.sockets
+    }
+
+    /// When the service is run, if it is not started together with
+    /// `sysinitd` but periodically
+    pub fn schedule(&self) -> Option<&Schedule> {
+        self.schedule.as_ref()
+    }
+
+    /// What the service is started on, if it is not started together with
+    /// `sysinitd`
+    pub fn trigger(&self) -> &Trigger {
+        &self.trigger
+    }
+
     /// TODO
     pub fn start(&self) -> &Start {
         &self.start
     }
 
+    /// How the service is terminated
+    pub fn termination(&self) -> &Termination {
+        &self.termination
+    }
+
+    /// How signals received by `sysinitd` are passed on to the service
+    pub fn signals(&self) -> &Signals {
+        &self.signals
+    }
+
+    /// The user the service runs as
+    pub fn user(&self) -> Option<&NameOrId> {
+        self.user.as_ref()
+    }
+
+    /// The primary group the service runs as
+    pub fn group(&self) -> Option<&NameOrId> {
+        self.group.as_ref()
+    }
+
+    /// Additional groups the service is a member of
+    pub fn supplementary_groups(&self) -> &[NameOrId] {
+        self.supplementary_groups.as_deref().unwrap_or_default()
+    }
+
+    /// The resource limits of the service
+    pub fn limits(&self) -> &std::collections::BTreeMap<Resource, Limit> {
+        &self.limits
+    }
+
+    /// The settings of the cgroup of the service
+    pub fn cgroup(&self) -> &CgroupSettings {
+        &self.cgroup
+    }
+
+    /// The capabilities of the service
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Whether `PR_SET_NO_NEW_PRIVS` is set for the service
+    pub fn no_new_privileges(&self) -> bool {
+        self.no_new_privileges
+    }
+
+    /// The namespaces the service is moved into
+    pub fn namespaces(&self) -> &Namespaces {
+        &self.namespaces
+    }
+
+    /// The system calls the service may make
+    pub fn seccomp(&self) -> &Seccomp {
+        &self.seccomp
+    }
+
+    /// The niceness of the service, from -20 (highest priority) to 19
+    /// (lowest priority)
+    pub fn nice(&self) -> Option<i32> {
+        self.nice
+    }
+
+    /// The CPUs the service may run on
+    pub fn cpu_affinity(&self) -> Option<&CpuSet> {
+        self.cpu_affinity.as_ref()
+    }
+
+    /// The I/O scheduling class of the service
+    ///
+    /// Defaults to [`IoClass::BestEffort`] if only an I/O priority is given.
+    pub fn io_class(&self) -> Option<IoClass> {
+        self.io_class
+            .or(self.io_priority.map(|_| IoClass::BestEffort))
+    }
+
+    /// The I/O priority of the service within its class, from 0 (highest
+    /// priority) to 7 (lowest priority)
+    pub fn io_priority(&self) -> Option<u8> {
+        self.io_priority
+    }
+
+    /// How much more (up to 1000) or less (down to -1000, which disables
+    /// OOM kills) likely the OOM killer picks the service
+    pub fn oom_score_adjust(&self) -> Option<i16> {
+        self.oom_score_adjust
+    }
+
+    /// Whether the service sets its scheduling priority, CPU affinity,
+    /// I/O scheduling or OOM score adjustment
+    pub fn has_scheduling(&self) -> bool {
+        self.nice.is_some()
+            || self.cpu_affinity.is_some()
+            || self.io_class().is_some()
+            || self.oom_score_adjust.is_some()
+    }
+
+    /// What happens to the service when `sysinitd` receives `signal`
+    ///
+    /// Without an explicit mapping, the main service receives all signals
+    /// as-is while all other services are not signaled.
+    pub fn signal_action(&self, signal: crate::library::signal::Signal) -> SignalAction {
+        match self.signals.mapping.get(&signal) {
+            Some(action) => *action,
+            None if self.main => SignalAction::Forward,
+            None => SignalAction::Ignore,
+        }
+    }
+
+    /// Creates the implicit main service for a command given on the
+    /// command line
+    ///
+    /// The ID of the service is the file name of the command.
+    pub fn from_command(command: &str, arguments: &[String]) -> Self {
+        let id = std::path::Path::new(command)
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| command.to_string());
+
+        Self {
+            meta: Meta {
+                version: ::semver::Version::new(0, 1, 0),
+            },
+            id,
+            instances: None,
+            replicas: None,
+            instance: None,
+            main: true,
+            autostart: true,
+            enabled: true,
+            targets: Self::default_targets(),
+            conditions: vec![],
+            assertions: vec![],
+            service_type: ServiceType::default(),
+            remain_active: false,
+            pid_file: None,
+            watchdog: None,
+            sockets: Sockets::default(),
+            schedule: None,
+            trigger: Trigger::default(),
+            start: Start {
+                command_and_arguments: BasicCommand {
+                    command: command.to_string(),
+                    arguments: Some(arguments.to_vec()),
+                    working_directory: None,
+                    umask: None,
+                    root_directory: None,
+                },
+                dependencies: None,
+            },
+            termination: Termination::default(),
+            signals: Signals::default(),
+            user: None,
+            group: None,
+            supplementary_groups: None,
+            limits: std::collections::BTreeMap::new(),
+            cgroup: CgroupSettings::default(),
+            capabilities: Capabilities::default(),
+            no_new_privileges: false,
+            namespaces: Namespaces::default(),
+            seccomp: Seccomp::default(),
+            nice: None,
+            cpu_affinity: None,
+            io_class: None,
+            io_priority: None,
+            oom_score_adjust: None,
+        }
+    }
+
     /// TODO
-    pub fn serde_from_slice(slice: &Vec<u8>, path: &std::path::Path) -> ::anyhow::Result<Self> {
+    pub fn serde_from_slice(slice: &[u8], path: &std::path::Path) -> ::anyhow::Result<Self> {
         use ::anyhow::Context as _;
 
-        ::serde_yml::from_slice(&slice).context(format!(
+        ::serde_yml::from_slice(slice).context(format!(
             "Could not parse service definition in '{}'",
             path.display()
         ))
     }
+
+    /// Parses a service definition that may be a template
+    ///
+    /// A template (with `instances` or `replicas`, read from a file such as
+    /// `worker@.yaml` or with an ID such as `worker@`) yields one service
+    /// per instance, with the ID `<ID>@<INSTANCE>`; `{{instance}}` and
+    /// `{{index}}` are replaced by the instance name and its index (starting
+    /// at 0) in the arguments of its command. For `replicas`, the instance
+    /// names are the indices, and a number of replicas recorded in
+    /// `state_directory` by scaling the service at runtime takes precedence
+    /// as long as the definition did not change since. Other definitions
+    /// yield just one service.
+    ///
+    /// The template is returned as well if it has `replicas`, so that it
+    /// can be scaled at runtime even while it has no instances.
+    pub fn serde_from_template_slice(
+        slice: &[u8],
+        path: &std::path::Path,
+        state_directory: &std::path::Path,
+    ) -> ::anyhow::Result<(Vec<Self>, Option<std::sync::Arc<Template>>)> {
+        use ::anyhow::Context as _;
+
+        let definition = std::str::from_utf8(slice).context(format!(
+            "Service definition in '{}' is not valid UTF-8",
+            path.display()
+        ))?;
+        let mut template = Template {
+            id: String::new(),
+            definition: definition.to_string(),
+            path: path.to_path_buf(),
+        };
+
+        let service = template.parse().map_err(|error| {
+            if definition.contains(INSTANCE_PLACEHOLDER) || definition.contains(INDEX_PLACEHOLDER) {
+                error.context(format!(
+                    "Placeholders on their own such as '{INDEX_PLACEHOLDER}' must be quoted in '{}'",
+                    path.display()
+                ))
+            } else {
+                error
+            }
+        })?;
+        template.id = service.id.trim_end_matches('@').to_string();
+
+        // the file of a template is named after it, e.g. `worker@.yaml`
+        let file_stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let templated_file = file_stem.ends_with('@');
+        if templated_file && file_stem.trim_end_matches('@') != template.id {
+            ::anyhow::bail!(
+                "Template '{}' must define the service '{}@', not '{}'",
+                path.display(),
+                file_stem.trim_end_matches('@'),
+                service.id
+            );
+        }
+
+        let instances = match (&service.instances, service.replicas) {
+            (None, None) if service.id.ends_with('@') || templated_file => ::anyhow::bail!(
+                "Service '{}' in '{}' is a template, but sets neither instances nor replicas",
+                service.id,
+                path.display()
+            ),
+            (None, None) => return Ok((vec![service], None)),
+            (Some(instances), None) => instances.clone(),
+            (None, Some(replicas)) => {
+                let replicas = match template.recorded_replicas(state_directory) {
+                    Some(recorded) if recorded != replicas => {
+                        ::tracing::info!(
+                            "Running {recorded} instead of {replicas} replicas of service '{}' as it was scaled",
+                            template.id
+                        );
+                        recorded
+                    }
+                    _ => replicas,
+                };
+                if replicas > MAX_REPLICAS {
+                    ::anyhow::bail!(
+                        "Service '{}' in '{}' has {replicas} replicas, but at most {MAX_REPLICAS} are supported",
+                        service.id,
+                        path.display()
+                    );
+                }
+                (0..replicas).map(|index| index.to_string()).collect()
+            }
+            (Some(_), Some(_)) => ::anyhow::bail!(
+                "Service '{}' in '{}' sets both instances and replicas",
+                service.id,
+                path.display()
+            ),
+        };
+        let scalable = service.replicas.is_some();
+        let template = std::sync::Arc::new(template);
+
+        let services = instances
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                // names become part of service IDs, cgroup names and paths
+                if name.is_empty()
+                    || !name.chars().all(|character| {
+                        character.is_ascii_alphanumeric() || matches!(character, '_' | '.' | '-')
+                    })
+                {
+                    ::anyhow::bail!(
+                        "Instance '{name}' of service '{}' must not be empty and only contain letters, digits, '_', '.' and '-'",
+                        template.id
+                    );
+                }
+                template.instantiate(name, index)
+            })
+            .collect::<::anyhow::Result<_>>()?;
+        Ok((services, scalable.then_some(template)))
+    }
+}
+
+/// The definition of a templated service, from which its instances are
+/// created
+#[derive(Debug)]
+pub struct Template {
+    /// The ID of the template, without the trailing `@`
+    id: String,
+    /// The definition with the placeholders
+    definition: String,
+    /// The file the definition was read from
+    path: std::path::PathBuf,
+}
+
+impl Template {
+    /// The ID of the template, e.g. `worker` for the service `worker@0`
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Creates the instance `name` with the position `index` among all
+    /// instances
+    pub fn instantiate(
+        self: &std::sync::Arc<Self>,
+        name: &str,
+        index: usize,
+    ) -> ::anyhow::Result<Service> {
+        let mut service = self.parse()?;
+        // only strings of the parsed definition are filled in, so that the
+        // placeholders can not change the structure or types of the YAML
+        if let Some(arguments) = &mut service.start.command_and_arguments.arguments {
+            for argument in arguments {
+                *argument = argument
+                    .replace(INSTANCE_PLACEHOLDER, name)
+                    .replace(INDEX_PLACEHOLDER, &index.to_string());
+            }
+        }
+        service.id = format!("{}@{name}", self.id);
+        service.instance = Some(Instance {
+            name: name.to_string(),
+            index,
+            template: service.replicas.is_some().then(|| self.clone()),
+        });
+        Ok(service)
+    }
+
+    /// Parses the definition with the placeholders left in place
+    fn parse(&self) -> ::anyhow::Result<Service> {
+        Service::serde_from_slice(self.definition.as_bytes(), &self.path)
+    }
+
+    /// The number of replicas recorded in `state_directory`, unless the
+    /// definition changed since
+    pub fn recorded_replicas(&self, state_directory: &std::path::Path) -> Option<usize> {
+        let record = std::fs::read_to_string(self.replicas_path(state_directory)).ok()?;
+        let (replicas, fingerprint) = record.trim().split_once(' ')?;
+        if fingerprint != format!("{:016x}", self.fingerprint()) {
+            return None;
+        }
+        replicas.parse().ok()
+    }
+
+    /// Records `replicas` as the number of replicas in `state_directory`
+    pub fn record_replicas(
+        &self,
+        state_directory: &std::path::Path,
+        replicas: usize,
+    ) -> std::io::Result<()> {
+        let path = self.replicas_path(state_directory);
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        std::fs::write(path, format!("{replicas} {:016x}\n", self.fingerprint()))
+    }
+
+    /// The file the number of replicas is recorded in
+    fn replicas_path(&self, state_directory: &std::path::Path) -> std::path::PathBuf {
+        state_directory.join("replicas").join(&self.id)
+    }
+
+    /// A fingerprint (FNV-1a) of the definition, which tells whether it
+    /// changed since the number of replicas was recorded
+    fn fingerprint(&self) -> u64 {
+        self.definition
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+            })
+    }
+}
+
+/// The instance of a templated service
+#[derive(Debug, Clone)]
+pub struct Instance {
+    /// The name of the instance
+    name: String,
+    /// The position of the instance among all instances, starting at 0
+    index: usize,
+    /// The template of the instance if it has `replicas`, and can thus be
+    /// scaled at runtime
+    template: Option<std::sync::Arc<Template>>,
+}
+
+impl Instance {
+    /// The name of the instance, e.g. `a` for the service `worker@a`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The position of the instance among all instances, starting at 0
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The template of the instance if it has `replicas`
+    pub fn template(&self) -> Option<&std::sync::Arc<Template>> {
+        self.template.as_ref()
+    }
 }
 
 /// TODO
@@ -57,11 +667,372 @@ struct Meta {
 pub struct Start {
     /// TODO
     #[serde(flatten)]
-    _command_and_arguments: BasicCommand,
+    command_and_arguments: BasicCommand,
     /// TODO
     pub dependencies: Option<Vec<String>>,
 }
 
+impl Start {
+    /// The command to execute
+    pub fn command(&self) -> &str {
+        &self.command_and_arguments.command
+    }
+
+    /// The arguments to the command
+    pub fn arguments(&self) -> &[String] {
+        self.command_and_arguments
+            .arguments
+            .as_deref()
+            .unwrap_or_default()
+    }
+
+    /// The directory the command is executed in
+    ///
+    /// If [`Start::root_directory`] is set, the path is relative to it.
+    pub fn working_directory(&self) -> Option<&std::path::Path> {
+        self.command_and_arguments.working_directory.as_deref()
+    }
+
+    /// The file mode creation mask of the command
+    pub fn umask(&self) -> Option<::libc::mode_t> {
+        self.command_and_arguments.umask
+    }
+
+    /// The directory that becomes the root directory of the command
+    pub fn root_directory(&self) -> Option<&std::path::Path> {
+        self.command_and_arguments.root_directory.as_deref()
+    }
+
+    /// The IDs of the services this service depends on
+    pub fn dependencies(&self) -> &[String] {
+        self.dependencies.as_deref().unwrap_or_default()
+    }
+}
+
+/// Describes how a service is terminated
+#[derive(Debug, ::serde::Deserialize)]
+pub struct Termination {
+    /// The signal that asks the service to terminate
+    #[serde(default = "Termination::default_signal")]
+    signal: crate::library::signal::Signal,
+    /// How long to wait after sending [`Termination::signal`]
+    /// before the service is killed with `SIGKILL`
+    #[serde(default, deserialize_with = "deserialize::option_humantime_duration")]
+    delay: Option<std::time::Duration>,
+    /// Whether all processes of the service are terminated, not only its
+    /// main process
+    #[serde(default)]
+    kill_descendants: bool,
+}
+
+impl Default for Termination {
+    fn default() -> Self {
+        Self {
+            signal: Self::default_signal(),
+            delay: None,
+            kill_descendants: false,
+        }
+    }
+}
+
+impl Termination {
+    /// The delay used when none is configured
+    const DEFAULT_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+
+    /// The signal used when none is configured
+    fn default_signal() -> crate::library::signal::Signal {
+        crate::library::signal::Signal::TERM
+    }
+
+    /// The signal that asks the service to terminate
+    pub fn signal(&self) -> crate::library::signal::Signal {
+        self.signal
+    }
+
+    /// How long to wait for the service to terminate before it is killed
+    pub fn delay(&self) -> std::time::Duration {
+        self.delay.unwrap_or(Self::DEFAULT_DELAY)
+    }
+
+    /// Whether the process group and all descendants of the service are
+    /// terminated together with its main process
+    pub fn kill_descendants(&self) -> bool {
+        self.kill_descendants
+    }
+}
+
+/// Describes the software watchdog of a notify service
+///
+/// Either just the interval (e.g. `30s`) or an `interval` together with
+/// the `signal` a hung service is killed with (`SIGKILL` by default;
+/// `SIGABRT` makes it dump core).
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(try_from = "deserialize::Watchdog")]
+pub struct Watchdog {
+    /// The time within which the service has to ping the watchdog
+    interval: std::time::Duration,
+    /// The signal a hung service is killed with
+    signal: crate::library::signal::Signal,
+}
+
+impl Watchdog {
+    /// The time within which the service has to ping the watchdog
+    pub fn interval(&self) -> std::time::Duration {
+        self.interval
+    }
+
+    /// The signal a hung service is killed with
+    pub fn signal(&self) -> crate::library::signal::Signal {
+        self.signal
+    }
+}
+
+/// When and how a service is run periodically
+///
+/// Either just the [timer](crate::library::schedule::Timer) (e.g.
+/// `every 15m` or `*/15 * * * *`) or the timer as `when` together with
+/// further settings:
+///
+/// ```yaml
+/// schedule:
+///   when: 0 3 * * *
+///   random_delay: 10m
+///   persistent: true
+///   overlap: skip
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(try_from = "deserialize::Schedule")]
+pub struct Schedule {
+    /// When the service is run
+    timer: crate::library::schedule::Timer,
+    /// The maximum random delay added to every run
+    random_delay: std::time::Duration,
+    /// Whether a run missed while `sysinitd` was not running is caught up
+    persistent: bool,
+    /// What happens if the previous run is still active
+    overlap: Overlap,
+}
+
+impl Schedule {
+    /// When the service is run
+    pub fn timer(&self) -> &crate::library::schedule::Timer {
+        &self.timer
+    }
+
+    /// The maximum random delay added to every run, so that services
+    /// scheduled at the same time do not all start at once
+    pub fn random_delay(&self) -> std::time::Duration {
+        self.random_delay
+    }
+
+    /// Whether a run that was missed while `sysinitd` was not running is
+    /// caught up right after startup
+    pub fn persistent(&self) -> bool {
+        self.persistent
+    }
+
+    /// What happens if the previous run is still active when the service
+    /// is to be run again
+    pub fn overlap(&self) -> Overlap {
+        self.overlap
+    }
+}
+
+/// What happens if the previous run of a scheduled service is still active
+/// when it is to be run again
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overlap {
+    /// The new run is skipped
+    #[default]
+    Skip,
+    /// The new run starts once the previous one exited
+    Queue,
+    /// The previous run is stopped and the new run starts once it exited
+    Kill,
+}
+
+/// The events that start a service, which is then not started together
+/// with `sysinitd`
+///
+/// ```yaml
+/// trigger:
+///   debounce: 1s
+///   path:
+///     - exists: /run/app/ready
+///     - changed: /etc/app/config.yaml
+///     - directory_not_empty: /var/spool/app
+/// ```
+#[derive(Debug, Default, ::serde::Deserialize)]
+pub struct Trigger {
+    /// The paths the service is started on
+    #[serde(default)]
+    path: Vec<PathTrigger>,
+    /// How long no further events may arrive before the service is started
+    #[serde(default, deserialize_with = "deserialize::option_humantime_duration")]
+    debounce: Option<std::time::Duration>,
+}
+
+impl Trigger {
+    /// The debounce delay used when none is configured
+    const DEFAULT_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+
+    /// The paths the service is started on
+    pub fn path(&self) -> &[PathTrigger] {
+        &self.path
+    }
+
+    /// How long no further events may arrive after a path triggered before
+    /// the service is started, so that e.g. a file being written does not
+    /// start the service more than once
+    pub fn debounce(&self) -> std::time::Duration {
+        self.debounce.unwrap_or(Self::DEFAULT_DEBOUNCE)
+    }
+}
+
+/// A path whose changes start a service
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(try_from = "deserialize::PathTrigger")]
+pub enum PathTrigger {
+    /// The path appears
+    Exists(std::path::PathBuf),
+    /// The file at the path is written, created or replaced
+    Changed(std::path::PathBuf),
+    /// The directory at the path becomes non-empty
+    DirectoryNotEmpty(std::path::PathBuf),
+}
+
+impl PathTrigger {
+    /// The path
+    pub fn path(&self) -> &std::path::Path {
+        match self {
+            Self::Exists(path) | Self::Changed(path) | Self::DirectoryNotEmpty(path) => path,
+        }
+    }
+
+    /// Whether the path is in a state that starts the service, i.e. it
+    /// exists or the directory is not empty
+    ///
+    /// Changes are events rather than states, so this is always false for
+    /// [`PathTrigger::Changed`].
+    pub fn holds(&self) -> bool {
+        match self {
+            Self::Exists(path) => path.exists(),
+            Self::Changed(_) => false,
+            Self::DirectoryNotEmpty(path) => {
+                std::fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_some())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for PathTrigger {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exists(path) => write!(formatter, "'{}' exists", path.display()),
+            Self::Changed(path) => write!(formatter, "'{}' changed", path.display()),
+            Self::DirectoryNotEmpty(path) => {
+                write!(formatter, "directory '{}' is not empty", path.display())
+            }
+        }
+    }
+}
+
+/// The sockets `sysinitd` binds for a service and passes to it like
+/// systemd's socket activation
+///
+/// ```yaml
+/// sockets:
+///   lazy: true
+///   listen:
+///     - tcp: 127.0.0.1:8080
+///       name: http
+///     - unix_stream: /run/app.sock
+///     - unix_datagram: /run/app-log.sock
+///     - fifo: /run/app.fifo
+/// ```
+#[derive(Debug, Default, ::serde::Deserialize)]
+pub struct Sockets {
+    /// Whether the service is only started once a client connects
+    #[serde(default)]
+    lazy: bool,
+    /// The sockets, passed in this order
+    #[serde(default)]
+    listen: Vec<ListenSocket>,
+}
+
+impl Sockets {
+    /// Whether the service is only started once a client connects to (or
+    /// writes to) one of its sockets
+    pub fn lazy(&self) -> bool {
+        self.lazy
+    }
+
+    /// The sockets in the order they are passed in
+    pub fn listen(&self) -> &[ListenSocket] {
+        &self.listen
+    }
+}
+
+/// A single socket (or FIFO) `sysinitd` listens on for a service
+#[derive(Debug, ::serde::Deserialize)]
+pub struct ListenSocket {
+    /// What is listened on
+    #[serde(flatten)]
+    address: SocketAddress,
+    /// The name passed in `LISTEN_FDNAMES` (the service ID by default)
+    name: Option<String>,
+}
+
+impl ListenSocket {
+    /// What is listened on
+    pub fn address(&self) -> &SocketAddress {
+        &self.address
+    }
+
+    /// The name passed in `LISTEN_FDNAMES`, if one is set
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+/// What a [`ListenSocket`] listens on
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SocketAddress {
+    /// A TCP socket bound to the address
+    Tcp(std::net::SocketAddr),
+    /// A Unix stream socket at the path
+    UnixStream(std::path::PathBuf),
+    /// A Unix datagram socket at the path
+    UnixDatagram(std::path::PathBuf),
+    /// A FIFO at the path
+    Fifo(std::path::PathBuf),
+}
+
+impl SocketAddress {
+    /// The path of a Unix socket or FIFO
+    pub fn path(&self) -> Option<&std::path::Path> {
+        match self {
+            Self::Tcp(_) => None,
+            Self::UnixStream(path) | Self::UnixDatagram(path) | Self::Fifo(path) => Some(path),
+        }
+    }
+}
+
+impl std::fmt::Display for SocketAddress {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(address) => write!(formatter, "TCP socket {address}"),
+            Self::UnixStream(path) => write!(formatter, "Unix stream socket '{}'", path.display()),
+            Self::UnixDatagram(path) => {
+                write!(formatter, "Unix datagram socket '{}'", path.display())
+            }
+            Self::Fifo(path) => write!(formatter, "FIFO '{}'", path.display()),
+        }
+    }
+}
+
 /// TODO
 #[derive(Debug, ::serde::Deserialize)]
 struct BasicCommand {
@@ -69,24 +1040,1012 @@ struct BasicCommand {
     command: String,
     /// TODO
     arguments: Option<Vec<String>>,
+    /// The directory the command is executed in (inside
+    /// [`BasicCommand::root_directory`] if that is set)
+    working_directory: Option<std::path::PathBuf>,
+    /// The file mode creation mask of the command
+    #[serde(default, deserialize_with = "deserialize::option_umask")]
+    umask: Option<::libc::mode_t>,
+    /// The directory that becomes the root directory (`chroot(2)`)
+    /// of the command
+    root_directory: Option<std::path::PathBuf>,
+}
+
+/// A resource whose consumption can be limited with `setrlimit(2)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ::serde::Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Resource {
+    /// `RLIMIT_NOFILE`: the number of open file descriptors
+    Nofile,
+    /// `RLIMIT_NPROC`: the number of processes of the user
+    Nproc,
+    /// `RLIMIT_CORE`: the size of core dumps in bytes
+    Core,
+    /// `RLIMIT_MEMLOCK`: the amount of memory locked into RAM in bytes
+    Memlock,
+    /// `RLIMIT_AS`: the size of the virtual memory in bytes
+    As,
+    /// `RLIMIT_STACK`: the size of the stack in bytes
+    Stack,
+    /// `RLIMIT_CPU`: the CPU time in seconds
+    Cpu,
+}
+
+/// A soft and a hard limit for a [`Resource`]
+///
+/// Given either as a single value for both limits (e.g. `65536` or
+/// `unlimited`), or as a mapping with `soft` and `hard`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(try_from = "deserialize::Limit")]
+pub struct Limit {
+    /// The soft limit, i.e. the limit that is enforced
+    pub soft: LimitValue,
+    /// The hard limit, i.e. the ceiling for the soft limit
+    pub hard: LimitValue,
+}
+
+/// The value of a [`Limit`] or a cgroup setting
+///
+/// Numbers may be given as sizes with a binary suffix (e.g. `512M`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ::serde::Deserialize)]
+#[serde(try_from = "deserialize::LimitValue")]
+pub enum LimitValue {
+    /// A finite limit
+    Finite(u64),
+    /// No limit
+    Unlimited,
+}
+
+impl std::fmt::Display for LimitValue {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Finite(value) => write!(formatter, "{value}"),
+            Self::Unlimited => write!(formatter, "unlimited"),
+        }
+    }
+}
+
+/// The settings of the cgroup v2 of a service
+///
+/// The keys are the names of the cgroup interface files they are
+/// written to:
+///
+/// ```yaml
+/// cgroup:
+///   memory.max: 512M
+///   memory.high: 384M
+///   cpu.max: 50000 100000
+///   cpu.weight: 100
+///   pids.max: 64
+/// ```
+#[derive(Debug, Default, ::serde::Deserialize)]
+pub struct CgroupSettings {
+    /// The hard memory limit in bytes
+    #[serde(rename = "memory.max")]
+    memory_max: Option<LimitValue>,
+    /// The memory limit in bytes above which the service is throttled
+    #[serde(rename = "memory.high")]
+    memory_high: Option<LimitValue>,
+    /// The CPU bandwidth limit
+    #[serde(rename = "cpu.max")]
+    cpu_max: Option<CpuMax>,
+    /// The relative CPU weight (1 to 10000)
+    #[serde(
+        rename = "cpu.weight",
+        default,
+        deserialize_with = "deserialize::option_cpu_weight"
+    )]
+    cpu_weight: Option<u16>,
+    /// The maximum number of processes
+    #[serde(rename = "pids.max")]
+    pids_max: Option<LimitValue>,
+}
+
+impl CgroupSettings {
+    /// Whether no setting is given
+    pub fn is_empty(&self) -> bool {
+        self.files().is_empty()
+    }
+
+    /// The cgroup interface files and the values to write to them
+    pub fn files(&self) -> Vec<(&'static str, String)> {
+        let value = |value: &LimitValue| match value {
+            LimitValue::Finite(value) => value.to_string(),
+            LimitValue::Unlimited => String::from("max"),
+        };
+
+        let mut files = vec![];
+        if let Some(memory_max) = &self.memory_max {
+            files.push(("memory.max", value(memory_max)));
+        }
+        if let Some(memory_high) = &self.memory_high {
+            files.push(("memory.high", value(memory_high)));
+        }
+        if let Some(cpu_max) = &self.cpu_max {
+            let quota = value(&cpu_max.quota);
+            files.push((
+                "cpu.max",
+                match cpu_max.period {
+                    Some(period) => format!("{quota} {period}"),
+                    None => quota,
+                },
+            ));
+        }
+        if let Some(cpu_weight) = self.cpu_weight {
+            files.push(("cpu.weight", cpu_weight.to_string()));
+        }
+        if let Some(pids_max) = &self.pids_max {
+            files.push(("pids.max", value(pids_max)));
+        }
+        files
+    }
+}
+
+/// The CPU bandwidth limit of a cgroup: the service may use `quota`
+/// microseconds of CPU time every `period` microseconds
+///
+/// Given as in `cpu.max`, e.g. `50000 100000` or `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(try_from = "deserialize::CpuMax")]
+pub struct CpuMax {
+    /// The CPU time per period in microseconds
+    quota: LimitValue,
+    /// The length of a period in microseconds (the kernel default is
+    /// 100000 microseconds)
+    period: Option<u64>,
+}
+
+/// A set of CPUs, given as a list of CPUs and ranges (e.g. `[0, 2-3]`), as
+/// a string (e.g. `0,2-3`) or as a single CPU
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(try_from = "deserialize::CpuSet")]
+pub struct CpuSet(std::collections::BTreeSet<usize>);
+
+impl CpuSet {
+    /// The number of CPUs a `cpu_set_t` can hold (`CPU_SETSIZE`)
+    pub const MAXIMUM_CPUS: usize = 1024;
+
+    /// The CPUs in the set
+    pub fn cpus(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl FromIterator<usize> for CpuSet {
+    fn from_iter<T: IntoIterator<Item = usize>>(iterator: T) -> Self {
+        Self(iterator.into_iter().collect())
+    }
+}
+
+impl std::fmt::Display for CpuSet {
+    /// Formats the set as a list of ranges, e.g. `0-3,6`
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut cpus = self.cpus().peekable();
+        let mut separator = "";
+        while let Some(first) = cpus.next() {
+            let mut last = first;
+            while cpus.next_if_eq(&(last + 1)).is_some() {
+                last += 1;
+            }
+            if first == last {
+                write!(formatter, "{separator}{first}")?;
+            } else {
+                write!(formatter, "{separator}{first}-{last}")?;
+            }
+            separator = ",";
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for CpuSet {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut cpus = std::collections::BTreeSet::new();
+        for range in value.split(',').map(str::trim) {
+            let cpu = |cpu: &str| {
+                cpu.trim()
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|cpu| *cpu < CpuSet::MAXIMUM_CPUS)
+                    .ok_or_else(|| {
+                        format!("'{cpu}' is not a CPU number below {}", CpuSet::MAXIMUM_CPUS)
+                    })
+            };
+            match range.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (cpu(first)?, cpu(last)?);
+                    if first > last {
+                        return Err(format!("'{range}' is not a valid range of CPUs"));
+                    }
+                    cpus.extend(first..=last);
+                }
+                None => {
+                    cpus.insert(cpu(range)?);
+                }
+            }
+        }
+        Ok(Self(cpus))
+    }
+}
+
+/// The I/O scheduling class of a service (see `ioprio_set(2)`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    /// Served before all other classes
+    Realtime,
+    /// The default class
+    BestEffort,
+    /// Only served when no other process needs the disk
+    Idle,
+}
+
+impl std::fmt::Display for IoClass {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Realtime => write!(formatter, "realtime"),
+            Self::BestEffort => write!(formatter, "best-effort"),
+            Self::Idle => write!(formatter, "idle"),
+        }
+    }
+}
+
+/// The capabilities of a service
+///
+/// ```yaml
+/// capabilities:
+///   ambient: [NET_BIND_SERVICE]
+///   bounding: [NET_BIND_SERVICE, CHOWN]
+///   drop_all: true
+/// ```
+#[derive(Debug, Default, ::serde::Deserialize)]
+pub struct Capabilities {
+    /// The capabilities the service's command is executed with even if
+    /// it does not run as `root`
+    #[serde(default)]
+    ambient: std::collections::BTreeSet<crate::library::capability::Capability>,
+    /// The capabilities the service and its children can ever have
+    bounding: Option<std::collections::BTreeSet<crate::library::capability::Capability>>,
+    /// Whether all capabilities except the ambient ones are dropped
+    #[serde(default)]
+    drop_all: bool,
+}
+
+impl Capabilities {
+    /// Whether the capabilities of the service are left as they are
+    pub fn is_empty(&self) -> bool {
+        self.ambient.is_empty() && self.bounding.is_none() && !self.drop_all
+    }
+
+    /// The capabilities raised in the ambient set
+    pub fn ambient(&self) -> &std::collections::BTreeSet<crate::library::capability::Capability> {
+        &self.ambient
+    }
+
+    /// The capabilities kept in the bounding set, if it is restricted
+    ///
+    /// With [`Capabilities::drop_all`], the bounding set is restricted to
+    /// the ambient capabilities unless given explicitly.
+    pub fn bounding(
+        &self,
+    ) -> Option<&std::collections::BTreeSet<crate::library::capability::Capability>> {
+        self.bounding.as_ref()
+    }
+
+    /// Whether all capabilities except the ambient ones are dropped, even
+    /// if the service runs as `root`
+    pub fn drop_all(&self) -> bool {
+        self.drop_all
+    }
+}
+
+/// The new namespaces a service is moved into
+///
+/// ```yaml
+/// namespaces:
+///   mount:
+///     private_tmp: true
+///     read_only: [/etc, /usr]
+///   pid: true
+///   network: true
+///   ipc: true
+///   uts:
+///     hostname: sandbox
+/// ```
+#[derive(Debug, Default, ::serde::Deserialize)]
+pub struct Namespaces {
+    /// A new mount namespace
+    mount: Option<MountNamespace>,
+    /// Whether the service gets a new PID namespace
+    #[serde(default)]
+    pid: bool,
+    /// Whether the service gets a new network namespace
+    #[serde(default)]
+    network: bool,
+    /// Whether the service gets a new IPC namespace
+    #[serde(default)]
+    ipc: bool,
+    /// A new UTS namespace
+    uts: Option<UtsNamespace>,
+}
+
+impl Namespaces {
+    /// Whether the service stays in the namespaces of `sysinitd`
+    pub fn is_empty(&self) -> bool {
+        self.mount.is_none() && !self.pid && !self.network && !self.ipc && self.uts.is_none()
+    }
+
+    /// The new mount namespace, if requested
+    pub fn mount(&self) -> Option<&MountNamespace> {
+        self.mount.as_ref()
+    }
+
+    /// Whether the service gets a new PID namespace in which it is PID 1
+    /// (with a new mount namespace, `/proc` is remounted to match it)
+    pub fn pid(&self) -> bool {
+        self.pid
+    }
+
+    /// Whether the service gets a new network namespace in which only the
+    /// loopback interface exists
+    pub fn network(&self) -> bool {
+        self.network
+    }
+
+    /// Whether the service gets a new IPC namespace
+    pub fn ipc(&self) -> bool {
+        self.ipc
+    }
+
+    /// The new UTS namespace, if requested
+    pub fn uts(&self) -> Option<&UtsNamespace> {
+        self.uts.as_ref()
+    }
+}
+
+/// The settings of a new mount namespace
+#[derive(Debug, Default, ::serde::Deserialize)]
+pub struct MountNamespace {
+    /// Whether an empty `tmpfs` is mounted at `/tmp`
+    #[serde(default)]
+    private_tmp: bool,
+    /// Paths that are mounted read-only
+    #[serde(default)]
+    read_only: Vec<std::path::PathBuf>,
+}
+
+impl MountNamespace {
+    /// Whether an empty `tmpfs` is mounted at `/tmp`
+    pub fn private_tmp(&self) -> bool {
+        self.private_tmp
+    }
+
+    /// Paths that are mounted read-only
+    pub fn read_only(&self) -> &[std::path::PathBuf] {
+        &self.read_only
+    }
+}
+
+/// The settings of a new UTS namespace
+#[derive(Debug, Default, ::serde::Deserialize)]
+pub struct UtsNamespace {
+    /// The hostname in the namespace (the hostname of `sysinitd` is kept
+    /// otherwise)
+    hostname: Option<String>,
+}
+
+impl UtsNamespace {
+    /// The hostname in the namespace
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+}
+
+/// The seccomp filter of a service
+///
+/// ```yaml
+/// seccomp:
+///   default_action: errno
+///   presets: [no-network, no-ptrace]
+///   deny: [personality]
+///   # allow: [read, write, exit_group]
+/// ```
+#[derive(Debug, Default, ::serde::Deserialize)]
+pub struct Seccomp {
+    /// What happens when the service makes a denied system call
+    #[serde(default)]
+    default_action: SeccompAction,
+    /// The only system calls the service may make (besides `execve`),
+    /// if restricted
+    allow: Option<std::collections::BTreeSet<crate::library::syscall::Syscall>>,
+    /// The system calls the service must not make
+    #[serde(default)]
+    deny: std::collections::BTreeSet<crate::library::syscall::Syscall>,
+    /// Curated groups of system calls the service must not make
+    #[serde(default)]
+    presets: std::collections::BTreeSet<crate::library::syscall::SyscallPreset>,
+}
+
+impl Seccomp {
+    /// Whether the service may make all system calls
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_none() && self.deny.is_empty() && self.presets.is_empty()
+    }
+
+    /// What happens when the service makes a denied system call
+    pub fn default_action(&self) -> SeccompAction {
+        self.default_action
+    }
+
+    /// The only system calls the service may make, if restricted
+    ///
+    /// `execve` is always allowed as the command could not be executed
+    /// otherwise.
+    pub fn allow(&self) -> Option<&std::collections::BTreeSet<crate::library::syscall::Syscall>> {
+        self.allow.as_ref()
+    }
+
+    /// The system calls the service must not make, including those of its
+    /// presets; denying takes precedence over allowing
+    pub fn denied(&self) -> std::collections::BTreeSet<crate::library::syscall::Syscall> {
+        self.presets
+            .iter()
+            .flat_map(crate::library::syscall::SyscallPreset::syscalls)
+            .chain(self.deny.iter().copied())
+            .collect()
+    }
+}
+
+/// What happens when a service makes a denied system call
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeccompAction {
+    /// The system call fails with `EPERM`
+    #[default]
+    Errno,
+    /// The whole process is killed with `SIGSYS`
+    Kill,
+    /// The system call is allowed but logged by the kernel
+    Log,
+}
+
+/// Whether a service keeps running or runs to completion
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceType {
+    /// The service keeps running; it is ready once it was started
+    #[default]
+    Simple,
+    /// The service is expected to exit (e.g. a setup task); it is ready
+    /// once it exited successfully
+    Oneshot,
+    /// The service starts a daemon that forks into the background and
+    /// writes its PID to [`Service::pid_file`]; it is ready once the
+    /// daemon is supervised
+    Forking,
+    /// The service reports its readiness via the `sd_notify(3)` protocol;
+    /// it is ready once it sent `READY=1`
+    Notify,
+}
+
+impl std::fmt::Display for ServiceType {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Simple => write!(formatter, "simple"),
+            Self::Oneshot => write!(formatter, "oneshot"),
+            Self::Forking => write!(formatter, "forking"),
+            Self::Notify => write!(formatter, "notify"),
+        }
+    }
+}
+
+/// A user or group, given either by name or by numeric ID
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(untagged)]
+pub enum NameOrId {
+    /// A numeric ID
+    Id(u32),
+    /// A name (which may still be a numeric ID written as a string)
+    Name(String),
+}
+
+impl NameOrId {
+    /// The numeric ID, if this is not a name
+    pub fn as_id(&self) -> Option<u32> {
+        match self {
+            Self::Id(id) => Some(*id),
+            Self::Name(name) => name.parse().ok(),
+        }
+    }
+}
+
+impl std::fmt::Display for NameOrId {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Id(id) => write!(formatter, "{id}"),
+            Self::Name(name) => write!(formatter, "{name}"),
+        }
+    }
+}
+
+/// Describes how signals received by `sysinitd` are passed on to a service
+///
+/// ```yaml
+/// signals:
+///   process_group: true
+///   TERM: QUIT
+///   HUP: forward
+///   WINCH: ignore
+/// ```
+#[derive(Debug, Default, ::serde::Deserialize)]
+pub struct Signals {
+    /// Whether signals are sent to the whole process group of the
+    /// service instead of its main process only
+    #[serde(default)]
+    process_group: bool,
+    /// What happens to the service for each signal received by `sysinitd`
+    #[serde(flatten)]
+    mapping: std::collections::HashMap<crate::library::signal::Signal, SignalAction>,
+}
+
+impl Signals {
+    /// Whether signals are sent to the whole process group
+    pub fn process_group(&self) -> bool {
+        self.process_group
+    }
+
+    /// The signals for which an action was configured explicitly
+    pub fn mapped_signals(&self) -> impl Iterator<Item = &crate::library::signal::Signal> {
+        self.mapping.keys()
+    }
+}
+
+/// What happens to a service when `sysinitd` receives a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ::serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum SignalAction {
+    /// The signal is sent to the service as-is
+    Forward,
+    /// The contained signal is sent to the service instead
+    Translate(crate::library::signal::Signal),
+    /// The service is not signaled
+    Ignore,
+}
+
+impl SignalAction {
+    /// The signal to send to the service when `sysinitd` received `signal`
+    pub fn resolve(
+        &self,
+        signal: crate::library::signal::Signal,
+    ) -> Option<crate::library::signal::Signal> {
+        match self {
+            Self::Forward => Some(signal),
+            Self::Translate(translated) => Some(*translated),
+            Self::Ignore => None,
+        }
+    }
+}
+
+impl TryFrom<String> for SignalAction {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "forward" => Ok(Self::Forward),
+            "ignore" => Ok(Self::Ignore),
+            _ => value.parse().map(Self::Translate).map_err(|_| {
+                format!("'{value}' is neither 'forward', 'ignore' nor a known signal")
+            }),
+        }
+    }
 }
 
 mod deserialize {
     //! Contains deserializers for non-standard types
 
-    // /// Parse a [`std::time::Duration`] via [`::humantime`] from a [`String`]
-    // pub fn option_humantime_duration<'de, D>(
-    //     deserializer: D,
-    // ) -> Result<Option<std::time::Duration>, D::Error>
-    // where
-    //     D: ::serde::Deserializer<'de>,
-    // {
-    //     let deserialized_string = <String as ::serde::Deserialize>::deserialize(deserializer)?;
-    //     match ::humantime::parse_duration(&deserialized_string) {
-    //         Ok(duration) => Ok(Some(duration)),
-    //         Err(error) => Err(::serde::de::Error::custom(error)),
-    //     }
-    // }
+    /// Parse a [`std::time::Duration`] via [`::humantime`] from a [`String`]
+    pub fn option_humantime_duration<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<std::time::Duration>, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        let deserialized_string = <String as ::serde::Deserialize>::deserialize(deserializer)?;
+        match ::humantime::parse_duration(&deserialized_string) {
+            Ok(duration) => Ok(Some(duration)),
+            Err(error) => Err(::serde::de::Error::custom(error)),
+        }
+    }
+
+    /// The representation of a [`super::Schedule`] in service definitions
+    #[derive(::serde::Deserialize)]
+    #[serde(untagged)]
+    pub enum Schedule {
+        /// Only the timer
+        Timer(String),
+        /// The timer and further settings
+        Full {
+            /// The timer
+            when: String,
+            /// The maximum random delay
+            random_delay: Option<String>,
+            /// Whether missed runs are caught up
+            #[serde(default)]
+            persistent: bool,
+            /// What happens if the previous run is still active
+            #[serde(default)]
+            overlap: super::Overlap,
+        },
+    }
+
+    impl TryFrom<Schedule> for super::Schedule {
+        type Error = String;
+
+        fn try_from(value: Schedule) -> Result<Self, Self::Error> {
+            let (when, random_delay, persistent, overlap) = match value {
+                Schedule::Timer(when) => (when, None, false, super::Overlap::default()),
+                Schedule::Full {
+                    when,
+                    random_delay,
+                    persistent,
+                    overlap,
+                } => (when, random_delay, persistent, overlap),
+            };
+            let random_delay = random_delay
+                .map(|random_delay| {
+                    ::humantime::parse_duration(&random_delay).map_err(|error| {
+                        format!("random delay '{random_delay}' is invalid: {error}")
+                    })
+                })
+                .transpose()?
+                .unwrap_or_default();
+            Ok(Self {
+                timer: when.parse()?,
+                random_delay,
+                persistent,
+                overlap,
+            })
+        }
+    }
+
+    /// The representation of a [`super::PathTrigger`] in service
+    /// definitions, which sets exactly one of its fields
+    #[derive(::serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct PathTrigger {
+        /// The path that is to appear
+        exists: Option<std::path::PathBuf>,
+        /// The path that is to change
+        changed: Option<std::path::PathBuf>,
+        /// The directory that is to become non-empty
+        directory_not_empty: Option<std::path::PathBuf>,
+    }
+
+    impl TryFrom<PathTrigger> for super::PathTrigger {
+        type Error = String;
+
+        fn try_from(value: PathTrigger) -> Result<Self, Self::Error> {
+            match (value.exists, value.changed, value.directory_not_empty) {
+                (Some(path), None, None) => Ok(Self::Exists(path)),
+                (None, Some(path), None) => Ok(Self::Changed(path)),
+                (None, None, Some(path)) => Ok(Self::DirectoryNotEmpty(path)),
+                _ => Err(String::from(
+                    "a path trigger needs exactly one of exists, changed and directory_not_empty",
+                )),
+            }
+        }
+    }
+
+    /// The representation of a [`super::Watchdog`] in service definitions
+    #[derive(::serde::Deserialize)]
+    #[serde(untagged)]
+    pub enum Watchdog {
+        /// Only the interval
+        Interval(String),
+        /// The interval and the signal a hung service is killed with
+        IntervalAndSignal {
+            /// The interval
+            interval: String,
+            /// The signal
+            signal: Option<crate::library::signal::Signal>,
+        },
+    }
+
+    impl TryFrom<Watchdog> for super::Watchdog {
+        type Error = String;
+
+        fn try_from(value: Watchdog) -> Result<Self, Self::Error> {
+            let (interval, signal) = match value {
+                Watchdog::Interval(interval) => (interval, None),
+                Watchdog::IntervalAndSignal { interval, signal } => (interval, signal),
+            };
+            let interval = ::humantime::parse_duration(&interval)
+                .map_err(|error| format!("watchdog interval '{interval}' is invalid: {error}"))?;
+            if interval.is_zero() {
+                return Err(String::from("watchdog interval must be greater than zero"));
+            }
+            Ok(Self {
+                interval,
+                signal: signal.unwrap_or(crate::library::signal::Signal::KILL),
+            })
+        }
+    }
+
+    /// The representation of a [`super::Limit`] in service definitions
+    #[derive(::serde::Deserialize)]
+    #[serde(untagged)]
+    pub enum Limit {
+        /// The same value for the soft and the hard limit
+        Both(super::LimitValue),
+        /// Different values for the soft and the hard limit
+        SoftAndHard {
+            /// The soft limit
+            soft: super::LimitValue,
+            /// The hard limit
+            hard: super::LimitValue,
+        },
+    }
+
+    impl TryFrom<Limit> for super::Limit {
+        type Error = String;
+
+        fn try_from(value: Limit) -> Result<Self, Self::Error> {
+            let (soft, hard) = match value {
+                Limit::Both(both) => (both, both),
+                Limit::SoftAndHard { soft, hard } => (soft, hard),
+            };
+            if soft > hard {
+                return Err(format!(
+                    "the soft limit ({soft}) must not be larger than the hard limit ({hard})"
+                ));
+            }
+            Ok(Self { soft, hard })
+        }
+    }
+
+    /// The representation of a [`super::LimitValue`] in service definitions
+    #[derive(::serde::Deserialize)]
+    #[serde(untagged)]
+    pub enum LimitValue {
+        /// A finite limit
+        Finite(u64),
+        /// `unlimited`, `infinity`, `max` or a size such as `512M`
+        Keyword(String),
+    }
+
+    impl TryFrom<LimitValue> for super::LimitValue {
+        type Error = String;
+
+        fn try_from(value: LimitValue) -> Result<Self, Self::Error> {
+            match value {
+                LimitValue::Finite(value) => Ok(Self::Finite(value)),
+                LimitValue::Keyword(keyword) => keyword.parse(),
+            }
+        }
+    }
+
+    impl std::str::FromStr for super::LimitValue {
+        type Err = String;
+
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            if matches!(value, "unlimited" | "infinity" | "max") {
+                return Ok(Self::Unlimited);
+            }
+
+            let (digits, shift) = match value.char_indices().last() {
+                Some((index, 'K' | 'k')) => (&value[..index], 10),
+                Some((index, 'M' | 'm')) => (&value[..index], 20),
+                Some((index, 'G' | 'g')) => (&value[..index], 30),
+                Some((index, 'T' | 't')) => (&value[..index], 40),
+                _ => (value, 0),
+            };
+            digits
+                .parse::<u64>()
+                .ok()
+                .and_then(|number| number.checked_mul(1 << shift))
+                .map(Self::Finite)
+                .ok_or_else(|| {
+                    format!("'{value}' is neither a number, a size (e.g. 512M) nor 'unlimited'")
+                })
+        }
+    }
+
+    /// The representation of a [`super::CpuMax`] in service definitions
+    #[derive(::serde::Deserialize)]
+    #[serde(untagged)]
+    pub enum CpuMax {
+        /// Only the quota
+        Quota(u64),
+        /// The quota and optionally the period, separated by whitespace
+        QuotaAndPeriod(String),
+    }
+
+    impl TryFrom<CpuMax> for super::CpuMax {
+        type Error = String;
+
+        fn try_from(value: CpuMax) -> Result<Self, Self::Error> {
+            let value = match value {
+                CpuMax::Quota(quota) => {
+                    return Ok(Self {
+                        quota: super::LimitValue::Finite(quota),
+                        period: None,
+                    });
+                }
+                CpuMax::QuotaAndPeriod(value) => value,
+            };
+
+            let mut fields = value.split_whitespace();
+            let quota = match fields.next() {
+                Some("max") => super::LimitValue::Unlimited,
+                Some(quota) => super::LimitValue::Finite(
+                    quota
+                        .parse()
+                        .map_err(|_| format!("'{quota}' is neither a quota nor 'max'"))?,
+                ),
+                None => return Err(String::from("cpu.max must not be empty")),
+            };
+            let period = fields
+                .next()
+                .map(|period| {
+                    period
+                        .parse()
+                        .map_err(|_| format!("'{period}' is not a period in microseconds"))
+                })
+                .transpose()?;
+            if fields.next().is_some() {
+                return Err(format!("'{value}' is not of the form 'QUOTA [PERIOD]'"));
+            }
+            Ok(Self { quota, period })
+        }
+    }
+
+    /// The representation of a [`super::CpuSet`] in service definitions
+    #[derive(::serde::Deserialize)]
+    #[serde(untagged)]
+    pub enum CpuSet {
+        /// A single CPU
+        Cpu(usize),
+        /// A list of CPUs and ranges
+        List(Vec<CpuRange>),
+        /// CPUs and ranges separated by commas
+        String(String),
+    }
+
+    /// A CPU or a range of CPUs in a [`CpuSet`]
+    #[derive(::serde::Deserialize)]
+    #[serde(untagged)]
+    pub enum CpuRange {
+        /// A single CPU
+        Cpu(usize),
+        /// A range of CPUs, e.g. `2-3`
+        Range(String),
+    }
+
+    impl TryFrom<CpuSet> for super::CpuSet {
+        type Error = String;
+
+        fn try_from(value: CpuSet) -> Result<Self, Self::Error> {
+            let cpus: Self = match value {
+                CpuSet::Cpu(cpu) => std::iter::once(cpu).collect(),
+                CpuSet::String(value) => value.parse()?,
+                CpuSet::List(ranges) => {
+                    let mut cpus = std::collections::BTreeSet::new();
+                    for range in ranges {
+                        match range {
+                            CpuRange::Cpu(cpu) => {
+                                cpus.insert(cpu);
+                            }
+                            CpuRange::Range(range) => cpus.extend(range.parse::<Self>()?.cpus()),
+                        }
+                    }
+                    cpus.into_iter().collect()
+                }
+            };
+
+            if cpus.cpus().next().is_none() {
+                return Err(String::from("cpu_affinity must not be empty"));
+            }
+            if let Some(cpu) = cpus.cpus().find(|cpu| *cpu >= Self::MAXIMUM_CPUS) {
+                return Err(format!(
+                    "'{cpu}' is not a CPU number below {}",
+                    Self::MAXIMUM_CPUS
+                ));
+            }
+            Ok(cpus)
+        }
+    }
+
+    /// Parse a value that must be within `range`
+    fn option_in_range<'de, D, T>(
+        deserializer: D,
+        name: &str,
+        range: std::ops::RangeInclusive<T>,
+    ) -> Result<Option<T>, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+        T: ::serde::Deserialize<'de> + PartialOrd + std::fmt::Display,
+    {
+        let value = T::deserialize(deserializer)?;
+        if !range.contains(&value) {
+            return Err(::serde::de::Error::custom(format!(
+                "{name} {value} is not between {} and {}",
+                range.start(),
+                range.end()
+            )));
+        }
+        Ok(Some(value))
+    }
+
+    /// Parse a niceness, which must be between -20 and 19
+    pub fn option_nice<'de, D>(deserializer: D) -> Result<Option<i32>, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        option_in_range(deserializer, "nice", -20..=19)
+    }
+
+    /// Parse an I/O priority, which must be between 0 and 7
+    pub fn option_io_priority<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        option_in_range(deserializer, "io_priority", 0..=7)
+    }
+
+    /// Parse an OOM score adjustment, which must be between -1000 and 1000
+    pub fn option_oom_score_adjust<'de, D>(deserializer: D) -> Result<Option<i16>, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        option_in_range(deserializer, "oom_score_adjust", -1000..=1000)
+    }
+
+    /// Parse a CPU weight, which must be between 1 and 10000
+    pub fn option_cpu_weight<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        let weight = <u16 as ::serde::Deserialize>::deserialize(deserializer)?;
+        if !(1..=10000).contains(&weight) {
+            return Err(::serde::de::Error::custom(format!(
+                "cpu.weight {weight} is not between 1 and 10000"
+            )));
+        }
+        Ok(Some(weight))
+    }
+
+    /// Parse a file mode creation mask from an octal [`String`] (e.g.
+    /// `0022`) or an integer (e.g. `0o022`)
+    pub fn option_umask<'de, D>(deserializer: D) -> Result<Option<::libc::mode_t>, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        #[derive(::serde::Deserialize)]
+        #[serde(untagged)]
+        enum Umask {
+            Integer(::libc::mode_t),
+            Octal(String),
+        }
+
+        let umask = match <Umask as ::serde::Deserialize>::deserialize(deserializer)? {
+            Umask::Integer(umask) => umask,
+            Umask::Octal(octal) => {
+                let digits = octal.strip_prefix("0o").unwrap_or(&octal);
+                ::libc::mode_t::from_str_radix(digits, 8).map_err(|error| {
+                    ::serde::de::Error::custom(format!("'{octal}' is not an octal umask: {error}"))
+                })?
+            }
+        };
+
+        if umask > 0o777 {
+            return Err(::serde::de::Error::custom(format!(
+                "umask {umask:#o} is larger than 0o777"
+            )));
+        }
+        Ok(Some(umask))
+    }
 
     /// Parse a [`::semver::Version`] from a [`String`]
     pub fn semver_version<'de, D>(deserializer: D) -> Result<::semver::Version, D::Error>
@@ -0,0 +1,121 @@
+//! Contains [`Signal`], a POSIX signal that can be parsed from
+//! its name (e.g. `TERM` or `SIGTERM`) in service definitions.
+
+/// A POSIX signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ::serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Signal(i32);
+
+/// All signals that can be named in service definitions
+const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", ::libc::SIGHUP),
+    ("INT", ::libc::SIGINT),
+    ("QUIT", ::libc::SIGQUIT),
+    ("ILL", ::libc::SIGILL),
+    ("TRAP", ::libc::SIGTRAP),
+    ("ABRT", ::libc::SIGABRT),
+    ("BUS", ::libc::SIGBUS),
+    ("FPE", ::libc::SIGFPE),
+    ("KILL", ::libc::SIGKILL),
+    ("USR1", ::libc::SIGUSR1),
+    ("SEGV", ::libc::SIGSEGV),
+    ("USR2", ::libc::SIGUSR2),
+    ("PIPE", ::libc::SIGPIPE),
+    ("ALRM", ::libc::SIGALRM),
+    ("TERM", ::libc::SIGTERM),
+    ("CHLD", ::libc::SIGCHLD),
+    ("CONT", ::libc::SIGCONT),
+    ("STOP", ::libc::SIGSTOP),
+    ("TSTP", ::libc::SIGTSTP),
+    ("TTIN", ::libc::SIGTTIN),
+    ("TTOU", ::libc::SIGTTOU),
+    ("URG", ::libc::SIGURG),
+    ("XCPU", ::libc::SIGXCPU),
+    ("XFSZ", ::libc::SIGXFSZ),
+    ("VTALRM", ::libc::SIGVTALRM),
+    ("PROF", ::libc::SIGPROF),
+    ("WINCH", ::libc::SIGWINCH),
+    ("IO", ::libc::SIGIO),
+    ("PWR", ::libc::SIGPWR),
+    ("SYS", ::libc::SIGSYS),
+];
+
+impl Signal {
+    /// `SIGHUP`
+    pub const HUP: Self = Self(::libc::SIGHUP);
+    /// `SIGINT`
+    pub const INT: Self = Self(::libc::SIGINT);
+    /// `SIGTERM`
+    pub const TERM: Self = Self(::libc::SIGTERM);
+    /// `SIGKILL`
+    pub const KILL: Self = Self(::libc::SIGKILL);
+    /// `SIGUSR1`
+    pub const USR1: Self = Self(::libc::SIGUSR1);
+    /// `SIGUSR2`
+    pub const USR2: Self = Self(::libc::SIGUSR2);
+    /// `SIGWINCH`
+    pub const WINCH: Self = Self(::libc::SIGWINCH);
+
+    /// Creates a [`Signal`] from its raw number, if the number is
+    /// a signal known to `sysinitd`
+    pub fn from_raw(number: i32) -> Option<Self> {
+        SIGNALS
+            .iter()
+            .any(|(_, known)| *known == number)
+            .then_some(Self(number))
+    }
+
+    /// The raw signal number
+    pub fn as_raw(&self) -> i32 {
+        self.0
+    }
+
+    /// The name of the signal without the `SIG` prefix
+    pub fn name(&self) -> &'static str {
+        SIGNALS
+            .iter()
+            .find_map(|(name, number)| (*number == self.0).then_some(*name))
+            .unwrap_or("UNKNOWN")
+    }
+}
+
+impl std::fmt::Display for Signal {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "SIG{}", self.name())
+    }
+}
+
+impl std::str::FromStr for Signal {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let upper_case = value.trim().to_ascii_uppercase();
+        let name = upper_case.strip_prefix("SIG").unwrap_or(&upper_case);
+        SIGNALS
+            .iter()
+            .find_map(|(known, number)| (*known == name).then_some(Self(*number)))
+            .ok_or_else(|| format!("'{value}' is not a known signal"))
+    }
+}
+
+impl TryFrom<String> for Signal {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_signal_names() {
+        assert_eq!("TERM".parse::<Signal>(), Ok(Signal::TERM));
+        assert_eq!("SIGTERM".parse::<Signal>(), Ok(Signal::TERM));
+        assert_eq!("sigkill".parse::<Signal>(), Ok(Signal::KILL));
+        assert!("SIGNOPE".parse::<Signal>().is_err());
+        assert_eq!(Signal::TERM.to_string(), "SIGTERM");
+    }
+}
@@ -0,0 +1,2963 @@
+//! Contains the [`Supervisor`], which starts services, watches over
+//! them, and shuts them down again.
+//!
+//! The supervisor is event-driven: exits of processes, signals received
+//! by `sysinitd`, requests on the control socket and expired timeouts
+//! are sent as [`Event`]s through a channel and handled one after another.
+
+use crate::library::{
+    condition::Condition,
+    control::{Request, Response},
+    notify::{self, Notification},
+    process::{self, ExitStatus, Process, cgroup::Cgroup, sockets::Listeners},
+    schedule,
+    service::{self, Overlap, PathTrigger, Service, ServiceType, Template},
+    signal::Signal,
+    trigger::PathWatcher,
+};
+
+/// Checks services before they are added at runtime, like the checks
+/// `sysinitd` runs on all service definitions when it starts
+pub type InstanceCheck = fn(&std::collections::HashMap<String, Service>) -> ::anyhow::Result<()>;
+
+/// Something the supervisor has to react to
+#[derive(Debug)]
+enum Event {
+    /// A process of a service exited
+    Exited {
+        /// The ID of the service
+        id: String,
+        /// The process ID of the exited process
+        pid: ::libc::pid_t,
+        /// How the process exited
+        status: ExitStatus,
+    },
+    /// A service that was asked to terminate did not do so in time
+    TerminationTimeout {
+        /// The ID of the service
+        id: String,
+        /// The process ID of the process asked to terminate
+        pid: ::libc::pid_t,
+    },
+    /// All leftover processes of an exited service terminated
+    LeftoversTerminated {
+        /// The ID of the service
+        id: String,
+    },
+    /// Leftover processes of an exited service did not terminate in time
+    LeftoversTimeout {
+        /// The ID of the service
+        id: String,
+        /// The leftover processes
+        leftovers: Vec<process::ProcessHandle>,
+    },
+    /// The PID file of a forking service was read after its launcher exited
+    PidFileRead {
+        /// The ID of the service
+        id: String,
+        /// The PID of the daemon or why it could not be read
+        result: ::anyhow::Result<::libc::pid_t>,
+    },
+    /// A notify service sent notifications via its notify socket
+    Notified {
+        /// The ID of the service
+        id: String,
+        /// The PID of the process that sent the datagram, if it is known
+        sender: Option<::libc::pid_t>,
+        /// The notifications of a single datagram
+        notifications: Vec<Notification>,
+    },
+    /// The watchdog of a notify service may have expired
+    WatchdogTimeout {
+        /// The ID of the service
+        id: String,
+    },
+    /// A client connected to a socket of a lazily started service
+    SocketActivity {
+        /// The ID of the service
+        id: String,
+    },
+    /// The timer of a scheduled service elapsed
+    ScheduleElapsed {
+        /// The ID of the service
+        id: String,
+        /// When the timer was due to elapse
+        at: std::time::SystemTime,
+    },
+    /// `memory.events` of the cgroup of a service changed
+    MemoryEvents {
+        /// The ID of the service
+        id: String,
+    },
+    /// A path that starts a service triggered
+    PathTriggered {
+        /// The ID of the service
+        id: String,
+        /// The trigger that fired
+        trigger: PathTrigger,
+    },
+    /// `sysinitd` received a signal
+    Signal(Signal),
+    /// A client sent a request via the control socket
+    Control {
+        /// The request
+        request: Request,
+        /// Receives the response to the request
+        response_sender: ::tokio::sync::oneshot::Sender<Response>,
+    },
+}
+
+/// The state of a single service
+#[derive(Debug)]
+enum State {
+    /// The service has not been started
+    Inactive,
+    /// The launcher of a forking service runs or exited, and the PID of
+    /// its daemon is not known yet
+    Starting {
+        /// The launcher process
+        launcher: Process,
+        /// Whether the launcher exited successfully
+        launcher_exited: bool,
+    },
+    /// The service is running
+    Running(Process),
+    /// The service was asked to terminate and is still running
+    Stopping(Process),
+    /// The service exited
+    Exited(ExitStatus),
+    /// The oneshot service completed successfully and remains active
+    Active,
+    /// The service could not be started
+    Failed,
+    /// The service was not started because the contained condition is not
+    /// met
+    Skipped(Condition),
+}
+
+impl State {
+    /// Whether a process of the service is alive
+    fn is_alive(&self) -> bool {
+        matches!(
+            self,
+            Self::Starting { .. } | Self::Running(_) | Self::Stopping(_)
+        )
+    }
+
+    /// The process of the service if it is alive
+    fn process(&self) -> Option<&Process> {
+        match self {
+            Self::Running(process)
+            | Self::Stopping(process)
+            | Self::Starting {
+                launcher: process,
+                launcher_exited: false,
+            } => Some(process),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Inactive => write!(formatter, "inactive"),
+            Self::Starting { .. } => write!(formatter, "starting"),
+            Self::Running(_) => write!(formatter, "running"),
+            Self::Stopping(_) => write!(formatter, "stopping"),
+            Self::Exited(status) => write!(formatter, "exited ({status})"),
+            Self::Active => write!(formatter, "active"),
+            Self::Failed => write!(formatter, "failed"),
+            Self::Skipped(condition) => write!(formatter, "condition not met ({condition})"),
+        }
+    }
+}
+
+/// What a notify service reported about itself since it was started
+#[derive(Debug, Default)]
+struct Notified {
+    /// Whether the service sent `READY=1`
+    ready: bool,
+    /// Whether the service is reloading, i.e. sent `RELOADING=1` and not
+    /// `READY=1` afterwards
+    reloading: bool,
+    /// The last `STATUS=` text
+    status: Option<String>,
+    /// The main PID announced via `MAINPID=` that could not be adopted yet
+    main_pid: Option<::libc::pid_t>,
+    /// When the watchdog expires unless the service sends `WATCHDOG=1`
+    watchdog_deadline: Option<::tokio::time::Instant>,
+}
+
+/// A service together with its state
+#[derive(Debug)]
+struct Unit {
+    /// The definition of the service
+    service: Service,
+    /// The current state of the service
+    state: State,
+    /// The cgroup of the service once it was started in one
+    cgroup: Option<Cgroup>,
+    /// The number of OOM kills in [`Unit::cgroup`] already reported
+    oom_kills: u64,
+    /// The path of the notify socket of a notify service once it was bound
+    notify_socket: Option<std::path::PathBuf>,
+    /// What a notify service reported since it was last started
+    notified: Notified,
+    /// The sockets bound for the service
+    listeners: Option<Listeners>,
+    /// Whether a client connected to a socket of a lazily started service
+    activated: bool,
+    /// When the timer of a scheduled service elapses next, without its
+    /// random delay
+    next_elapse: Option<std::time::SystemTime>,
+    /// When a scheduled service is run next, i.e. [`Unit::next_elapse`]
+    /// plus the random delay
+    next_run: Option<std::time::SystemTime>,
+    /// Whether a scheduled or triggered service is to be run once its
+    /// dependencies are ready
+    due: bool,
+    /// Whether a scheduled or triggered service is to be run again once
+    /// its current run exited
+    queued: bool,
+    /// Whether the service is part of the current target or a dependency
+    /// of such a service
+    wanted: bool,
+    /// Whether the service is an instance that was removed by scaling its
+    /// template down; it is kept so that its termination can be supervised
+    removed: bool,
+}
+
+impl Unit {
+    /// Whether services depending on this one may be started
+    ///
+    /// Simple services are ready while they run, forking services while
+    /// their daemon runs, notify services once they sent `READY=1`, and
+    /// oneshot services once they completed successfully. Services whose
+    /// conditions are not met are skipped and therefore ready as well, and
+    /// so are lazily started services while they wait for a client, as
+    /// their sockets already accept connections.
+    fn is_ready(&self) -> bool {
+        if matches!(self.state, State::Skipped(_))
+            || matches!(self.state, State::Inactive)
+                && self.service.sockets().lazy()
+                && self.listeners.is_some()
+        {
+            return true;
+        }
+        match self.service.service_type() {
+            ServiceType::Simple | ServiceType::Forking => matches!(self.state, State::Running(_)),
+            ServiceType::Notify => matches!(self.state, State::Running(_)) && self.notified.ready,
+            ServiceType::Oneshot => match &self.state {
+                State::Exited(status) => status.success(),
+                state => matches!(state, State::Active),
+            },
+        }
+    }
+
+    /// Whether the service may still become ready
+    fn is_pending(&self) -> bool {
+        match self.service.service_type() {
+            ServiceType::Simple => matches!(self.state, State::Inactive),
+            ServiceType::Forking => matches!(self.state, State::Inactive | State::Starting { .. }),
+            ServiceType::Notify => match self.state {
+                State::Inactive => true,
+                State::Running(_) => !self.notified.ready,
+                _ => false,
+            },
+            ServiceType::Oneshot => matches!(self.state, State::Inactive | State::Running(_)),
+        }
+    }
+
+    /// Whether the service may be started once its dependencies are ready,
+    /// i.e. it is enabled, it is not started lazily or a client connected
+    /// already, and it is due to run or wanted and does not run on demand
+    fn is_startable(&self) -> bool {
+        matches!(self.state, State::Inactive)
+            && self.service.enabled()
+            && !self.removed
+            && (!self.service.sockets().lazy() || self.activated)
+            && (self.due || self.wanted && !self.runs_on_demand())
+    }
+
+    /// Whether the service is only run on request, as a dependency, when
+    /// its schedule elapses or when one of its paths triggers, rather than
+    /// together with `sysinitd`
+    fn runs_on_demand(&self) -> bool {
+        !self.service.autostart()
+            || self.service.schedule().is_some()
+            || !self.service.trigger().path().is_empty()
+    }
+
+    /// Describes the state of the service, which for running notify
+    /// services is what they reported about themselves
+    fn describe_state(&self) -> String {
+        if !self.service.enabled() {
+            return String::from("disabled");
+        }
+        if matches!(self.state, State::Inactive) && self.listeners.is_some() && !self.activated {
+            return String::from("listening");
+        }
+        if matches!(self.state, State::Inactive)
+            && !self.service.trigger().path().is_empty()
+            && !self.due
+        {
+            return String::from("watching");
+        }
+        if self.service.service_type() != ServiceType::Notify
+            || !matches!(self.state, State::Running(_))
+        {
+            return self.state.to_string();
+        }
+        let state = if self.notified.reloading {
+            "reloading"
+        } else if self.notified.ready {
+            "running"
+        } else {
+            "starting"
+        };
+        state.to_string()
+    }
+}
+
+/// Starts, supervises and shuts down services
+#[derive(Debug)]
+pub struct Supervisor {
+    /// All services, keyed by their ID
+    units: std::collections::BTreeMap<String, Unit>,
+    /// The templates with `replicas`, keyed by their ID, which can be
+    /// scaled even while they have no instances
+    templates: std::collections::BTreeMap<String, std::sync::Arc<Template>>,
+    /// Checks instances created by scaling before they are added, set with
+    /// [`Supervisor::set_instance_check`]
+    instance_check: InstanceCheck,
+    /// The ID of the main service, if there is one
+    main_service: Option<String>,
+    /// Services with leftover processes that are being terminated
+    services_with_leftovers: std::collections::BTreeSet<String>,
+    /// The path of the control socket once [`Supervisor::listen`] bound it
+    control_socket: Option<std::path::PathBuf>,
+    /// The directory notify sockets are created in
+    notify_directory: std::path::PathBuf,
+    /// The directory persistent state is kept in
+    state_directory: std::path::PathBuf,
+    /// Whether the timers of scheduled services were armed
+    schedules_armed: bool,
+    /// The target whose services are started
+    target: String,
+    /// Whether [`Supervisor::shut_down`] was called, after which no
+    /// services are started anymore
+    shutting_down: bool,
+    /// Sends events to [`Supervisor::events_receiver`]
+    events_sender: ::tokio::sync::mpsc::UnboundedSender<Event>,
+    /// Receives all events the supervisor has to handle
+    events_receiver: ::tokio::sync::mpsc::UnboundedReceiver<Event>,
+}
+
+impl Supervisor {
+    /// The signals that make `sysinitd` shut down if the main service
+    /// is not running
+    const SHUTDOWN_SIGNALS: [Signal; 2] = [Signal::TERM, Signal::INT];
+
+    /// How long the daemon of a forking service has to write its PID file
+    /// after its launcher exited
+    const PID_FILE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    /// The signals `sysinitd` handles and passes on to services
+    pub const HANDLED_SIGNALS: [Signal; 6] = [
+        Signal::TERM,
+        Signal::INT,
+        Signal::HUP,
+        Signal::USR1,
+        Signal::USR2,
+        Signal::WINCH,
+    ];
+
+    /// Creates a new supervisor for `services` and starts listening
+    /// for signals
+    ///
+    /// Must be called from within a [`::tokio`] runtime.
+    pub fn new(services: std::collections::HashMap<String, Service>) -> ::anyhow::Result<Self> {
+        use ::anyhow::Context as _;
+
+        let (events_sender, events_receiver) = ::tokio::sync::mpsc::unbounded_channel();
+
+        for signal in Self::HANDLED_SIGNALS {
+            let mut signals = ::tokio::signal::unix::signal(
+                ::tokio::signal::unix::SignalKind::from_raw(signal.as_raw()),
+            )
+            .context(format!("Could not listen for {signal}"))?;
+            let events_sender = events_sender.clone();
+            ::tokio::spawn(async move {
+                while signals.recv().await.is_some() {
+                    if events_sender.send(Event::Signal(signal)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let main_service = services
+            .values()
+            .find(|service| service.main())
+            .map(|service| service.id().clone());
+
+        let units = services
+            .into_iter()
+            .map(|(id, service)| {
+                let unit = Self::create_unit(&id, service, &events_sender);
+                (id, unit)
+            })
+            .collect();
+
+        let mut supervisor = Self {
+            units,
+            templates: std::collections::BTreeMap::new(),
+            instance_check: |_| Ok(()),
+            main_service,
+            services_with_leftovers: std::collections::BTreeSet::new(),
+            control_socket: None,
+            notify_directory: std::path::PathBuf::from(notify::DEFAULT_DIRECTORY),
+            state_directory: std::path::PathBuf::from(schedule::DEFAULT_STATE_DIRECTORY),
+            schedules_armed: false,
+            target: String::from(service::DEFAULT_TARGET),
+            shutting_down: false,
+            events_sender,
+            events_receiver,
+        };
+        supervisor.set_target(service::DEFAULT_TARGET)?;
+        Ok(supervisor)
+    }
+
+    /// Creates the unit supervising `service`, binding its sockets and
+    /// watching its paths
+    fn create_unit(
+        id: &str,
+        service: Service,
+        events_sender: &::tokio::sync::mpsc::UnboundedSender<Event>,
+    ) -> Unit {
+        let mut unit = Unit {
+            service,
+            state: State::Inactive,
+            cgroup: None,
+            oom_kills: 0,
+            notify_socket: None,
+            notified: Notified::default(),
+            listeners: None,
+            activated: false,
+            next_elapse: None,
+            next_run: None,
+            due: false,
+            queued: false,
+            wanted: false,
+            removed: false,
+        };
+        if unit.service.enabled() && !unit.service.sockets().listen().is_empty() {
+            match Self::listen_for(id, &unit.service, events_sender) {
+                Ok(listeners) => unit.listeners = Some(listeners),
+                Err(error) => {
+                    ::tracing::error!("{error:#}");
+                    unit.state = State::Failed;
+                }
+            }
+        }
+        if unit.service.enabled() && !unit.service.trigger().path().is_empty() {
+            match Self::watch_paths(id, &unit.service, events_sender) {
+                Ok(()) => {}
+                Err(error) => {
+                    ::tracing::error!("Could not watch the paths of service '{id}': {error}");
+                    unit.state = State::Failed;
+                }
+            }
+        }
+        unit
+    }
+
+    /// Binds the sockets of a service and, if it is started lazily, sends
+    /// [`Event::SocketActivity`] once a client connects
+    fn listen_for(
+        id: &str,
+        service: &Service,
+        events_sender: &::tokio::sync::mpsc::UnboundedSender<Event>,
+    ) -> ::anyhow::Result<Listeners> {
+        let listeners = Listeners::bind(service)?;
+        if service.sockets().lazy() {
+            Self::watch_activity(id, &listeners, events_sender)?;
+        }
+        Ok(listeners)
+    }
+
+    /// Sends [`Event::SocketActivity`] once a client connects to one of
+    /// `listeners`
+    fn watch_activity(
+        id: &str,
+        listeners: &Listeners,
+        events_sender: &::tokio::sync::mpsc::UnboundedSender<Event>,
+    ) -> std::io::Result<()> {
+        let activity = listeners.activity()?;
+        let events_sender = events_sender.clone();
+        let id = id.to_string();
+        ::tokio::spawn(async move {
+            activity.await;
+            let _ = events_sender.send(Event::SocketActivity { id });
+        });
+        Ok(())
+    }
+
+    /// Makes a lazily started service that exited wait for the next client
+    /// again, unless it is the main service, whose exit ends supervision
+    fn listen_again(&mut self, id: &str) {
+        if self.shutting_down || self.main_service.as_deref() == Some(id) {
+            return;
+        }
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        let Some(listeners) = &unit.listeners else {
+            return;
+        };
+        if !unit.service.sockets().lazy() || unit.removed {
+            return;
+        }
+
+        match Self::watch_activity(id, listeners, &self.events_sender) {
+            Ok(()) => {
+                ::tracing::info!("Service '{id}' is listening for clients again");
+                unit.state = State::Inactive;
+                unit.activated = false;
+            }
+            Err(error) => {
+                ::tracing::error!("Could not listen for clients of service '{id}' again: {error}")
+            }
+        }
+    }
+
+    /// Watches the paths that trigger a service, sending
+    /// [`Event::PathTriggered`] whenever one of them triggers, and right
+    /// away if one of them already holds
+    fn watch_paths(
+        id: &str,
+        service: &Service,
+        events_sender: &::tokio::sync::mpsc::UnboundedSender<Event>,
+    ) -> std::io::Result<()> {
+        let watcher = PathWatcher::new(service.trigger().path())?;
+        if let Some(trigger) = watcher.holding() {
+            let _ = events_sender.send(Event::PathTriggered {
+                id: id.to_string(),
+                trigger: trigger.clone(),
+            });
+        }
+        let debounce = service.trigger().debounce();
+        let events_sender = events_sender.clone();
+        let id = id.to_string();
+        ::tokio::spawn(async move {
+            loop {
+                let trigger = match watcher.triggered(debounce).await {
+                    Ok(trigger) => trigger.clone(),
+                    Err(error) => {
+                        ::tracing::error!("Could not watch the paths of service '{id}': {error}");
+                        break;
+                    }
+                };
+                let event = Event::PathTriggered {
+                    id: id.clone(),
+                    trigger,
+                };
+                if events_sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Selects the target whose services (and their dependencies) are
+    /// started
+    ///
+    /// Must be called before [`Supervisor::start_services`] to take effect;
+    /// at runtime, [`Request::Target`] switches targets.
+    pub fn set_target(&mut self, target: &str) -> ::anyhow::Result<()> {
+        let wanted = self.wanted_by(target)?;
+        for (id, unit) in &mut self.units {
+            unit.wanted = wanted.contains(id);
+        }
+        self.target = target.to_string();
+        Ok(())
+    }
+
+    /// The IDs of the services that are part of `target`, their transitive
+    /// dependencies and the main service, which always runs as `sysinitd`
+    /// exits with it
+    ///
+    /// Disabled services and instances removed by scaling are never
+    /// wanted.
+    fn wanted_by(&self, target: &str) -> ::anyhow::Result<std::collections::BTreeSet<String>> {
+        let mut pending: Vec<&String> = self
+            .units
+            .iter()
+            .filter(|(_, unit)| unit.service.targets().iter().any(|name| name == target))
+            .map(|(id, _)| id)
+            .collect();
+        if pending.is_empty() && target != service::DEFAULT_TARGET {
+            ::anyhow::bail!("No service is part of target '{target}'");
+        }
+        pending.extend(self.main_service.as_ref());
+
+        let mut wanted = std::collections::BTreeSet::new();
+        while let Some(id) = pending.pop() {
+            let Some(unit) = self.units.get(id) else {
+                continue;
+            };
+            if !unit.service.enabled() || unit.removed || !wanted.insert(id.clone()) {
+                continue;
+            }
+            pending.extend(unit.service.start().dependencies());
+        }
+        Ok(wanted)
+    }
+
+    /// Switches to another target: services that are no longer wanted are
+    /// stopped, and newly wanted ones are started
+    fn switch_target(&mut self, target: &str) -> Response {
+        if self.shutting_down {
+            return Response::Error(String::from("sysinitd is shutting down"));
+        }
+        let wanted = match self.wanted_by(target) {
+            Ok(wanted) => wanted,
+            Err(error) => return Response::Error(error.to_string()),
+        };
+        ::tracing::info!("Switching from target '{}' to '{target}'", self.target);
+
+        let ids: Vec<String> = self.units.keys().cloned().collect();
+        for id in ids {
+            let unit = self.units.get_mut(&id).expect("bug: unknown service ID");
+            let was_wanted = std::mem::replace(&mut unit.wanted, wanted.contains(&id));
+            if was_wanted && !unit.wanted {
+                unit.due = false;
+                unit.queued = false;
+                self.stop(&id);
+            } else if !was_wanted
+                && unit.wanted
+                && matches!(
+                    unit.state,
+                    State::Exited(_) | State::Failed | State::Skipped(_)
+                )
+            {
+                unit.state = State::Inactive;
+            }
+        }
+        self.target = target.to_string();
+        self.start_services();
+        Response::Ok(self.status())
+    }
+
+    /// Sets the directory the notify sockets of notify services are
+    /// created in
+    ///
+    /// Must be called before [`Supervisor::start_services`] to take effect.
+    pub fn set_notify_directory(&mut self, directory: &std::path::Path) {
+        self.notify_directory = directory.to_path_buf();
+    }
+
+    /// Sets the templates with `replicas` that can be scaled with
+    /// [`Request::Scale`]
+    pub fn set_templates(&mut self, templates: impl IntoIterator<Item = std::sync::Arc<Template>>) {
+        self.templates = templates
+            .into_iter()
+            .map(|template| (template.id().to_string(), template))
+            .collect();
+    }
+
+    /// Sets the check that instances created by [`Request::Scale`] have to
+    /// pass before they are added
+    ///
+    /// Dependencies and cycles are checked by the supervisor itself.
+    pub fn set_instance_check(&mut self, check: InstanceCheck) {
+        self.instance_check = check;
+    }
+
+    /// Sets the directory persistent state is kept in
+    ///
+    /// Must be called before [`Supervisor::start_services`] to take effect.
+    pub fn set_state_directory(&mut self, directory: &std::path::Path) {
+        self.state_directory = directory.to_path_buf();
+    }
+
+    /// Starts all services whose dependencies are ready
+    ///
+    /// Services whose dependencies failed or exited (without completing
+    /// successfully, in case of oneshot services) are marked as failed.
+    /// Scheduled services are not started, but their timers are armed.
+    pub fn start_services(&mut self) {
+        if self.shutting_down {
+            return;
+        }
+
+        if !self.schedules_armed {
+            self.schedules_armed = true;
+            let scheduled_ids: Vec<String> = self
+                .units
+                .iter()
+                .filter(|(_, unit)| unit.service.enabled() && unit.service.schedule().is_some())
+                .map(|(id, _)| id.clone())
+                .collect();
+            for id in scheduled_ids {
+                self.arm_schedule(&id, true);
+            }
+        }
+
+        loop {
+            let mut progress = false;
+            let inactive_ids: Vec<String> = self
+                .units
+                .iter()
+                .filter(|(_, unit)| unit.is_startable())
+                .map(|(id, _)| id.clone())
+                .collect();
+
+            for id in inactive_ids {
+                let mut dependencies_ready = true;
+                let mut dependency_failed = None;
+                let mut pulled_in = Vec::new();
+                for dependency in self.units[&id].service.start().dependencies() {
+                    match self.units.get(dependency) {
+                        // dependencies on disabled services and removed
+                        // instances are ignored
+                        Some(unit)
+                            if !unit.service.enabled() || unit.removed || unit.is_ready() => {}
+                        Some(unit) if unit.is_pending() => {
+                            dependencies_ready = false;
+                            // scheduled and triggered dependencies are left
+                            // to their timers and paths
+                            if matches!(unit.state, State::Inactive)
+                                && !unit.due
+                                && !unit.is_startable()
+                                && unit.service.schedule().is_none()
+                                && unit.service.trigger().path().is_empty()
+                            {
+                                pulled_in.push(dependency.clone());
+                            }
+                        }
+                        _ => dependency_failed = Some(dependency.clone()),
+                    }
+                }
+                for dependency in pulled_in {
+                    ::tracing::info!(
+                        "Starting service '{dependency}' because service '{id}' depends on it"
+                    );
+                    if let Some(unit) = self.units.get_mut(&dependency) {
+                        unit.due = true;
+                    }
+                    progress = true;
+                }
+
+                if let Some(dependency) = dependency_failed {
+                    ::tracing::error!(
+                        "Not starting service '{id}' because its dependency '{dependency}' is not ready"
+                    );
+                    self.set_state(&id, State::Failed);
+                    progress = true;
+                } else if dependencies_ready {
+                    self.start(&id);
+                    progress = true;
+                }
+            }
+
+            if !progress {
+                break;
+            }
+        }
+    }
+
+    /// Supervises all services until `sysinitd` is asked to shut down
+    /// or the main service exited
+    pub async fn supervise(&mut self) {
+        while !self.main_service_terminated() {
+            let Some(event) = self.events_receiver.recv().await else {
+                break;
+            };
+
+            if let Event::Signal(signal) = event {
+                ::tracing::info!("Received {signal}");
+                if self.handle_signal(signal) {
+                    break;
+                }
+                continue;
+            }
+            self.handle(event);
+        }
+    }
+
+    /// Terminates all services that are still running and returns the
+    /// exit code `sysinitd` should exit with
+    ///
+    /// The exit code is the exit code of the main service, or 128 plus
+    /// the signal that killed it. Without a main service, it is 0.
+    pub async fn shut_down(&mut self) -> i32 {
+        self.shutting_down = true;
+        let alive_ids: Vec<String> = self
+            .units
+            .iter()
+            .filter(|(_, unit)| matches!(unit.state, State::Running(_) | State::Starting { .. }))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in alive_ids {
+            self.stop(&id);
+        }
+
+        while self.units.values().any(|unit| unit.state.is_alive())
+            || !self.services_with_leftovers.is_empty()
+        {
+            match self.events_receiver.recv().await {
+                Some(Event::Signal(signal)) => {
+                    ::tracing::debug!(
+                        "Ignoring {signal} because sysinitd is already shutting down"
+                    );
+                }
+                Some(event) => self.handle(event),
+                None => break,
+            }
+        }
+
+        if let Some(control_socket) = &self.control_socket {
+            let _ = std::fs::remove_file(control_socket);
+        }
+        for unit in self.units.values() {
+            if let Some(notify_socket) = &unit.notify_socket {
+                let _ = std::fs::remove_file(notify_socket);
+            }
+            if let Some(listeners) = &unit.listeners {
+                listeners.remove_files();
+            }
+            if let Some(cgroup) = &unit.cgroup
+                && let Err(error) = cgroup.remove()
+            {
+                ::tracing::debug!(
+                    "Could not remove cgroup '{}': {error}",
+                    cgroup.path().display()
+                );
+            }
+        }
+
+        match self.main_service.as_ref().map(|id| &self.units[id].state) {
+            None => 0,
+            Some(State::Exited(status)) => status.code(),
+            Some(State::Active | State::Skipped(_)) => 0,
+            Some(_) => 1,
+        }
+    }
+
+    /// Reports the state of every service, one line per service
+    ///
+    /// For services in a cgroup, the accounting data of the cgroup is
+    /// reported as well, and for notify services the last status they
+    /// sent.
+    pub fn status(&self) -> String {
+        let mut status = String::new();
+        for (id, unit) in &self.units {
+            if unit.removed && !unit.state.is_alive() {
+                continue;
+            }
+            status.push_str(&format!("{id}: {}", unit.describe_state()));
+            if let Some(process) = unit.state.process() {
+                status.push_str(&format!(", PID {}", process.pid()));
+                if unit.service.has_scheduling()
+                    && let Ok(scheduling) = process.scheduling()
+                {
+                    status.push_str(&format!(" ({scheduling})"));
+                }
+            }
+            if let Some(cgroup) = &unit.cgroup {
+                status.push_str(&format!(" [{}]", cgroup.statistics()));
+            }
+            if unit.state.is_alive()
+                && let Some(text) = &unit.notified.status
+            {
+                status.push_str(&format!(", status '{text}'"));
+            }
+            if let Some(next_run) = unit.next_run {
+                status.push_str(&format!(
+                    ", next run {}",
+                    ::humantime::format_rfc3339_seconds(next_run)
+                ));
+            }
+            status.push('\n');
+        }
+        status
+    }
+
+    /// Listens for requests on the control socket at `socket_path`
+    ///
+    /// A stale socket file left behind by a previous `sysinitd` is replaced,
+    /// and the socket is removed again by [`Supervisor::shut_down`]. Must be
+    /// called from within a [`::tokio`] runtime.
+    pub fn listen(&mut self, socket_path: &std::path::Path) -> ::anyhow::Result<()> {
+        use ::anyhow::Context as _;
+        use std::os::unix::fs::FileTypeExt as _;
+
+        if std::fs::symlink_metadata(socket_path)
+            .is_ok_and(|metadata| metadata.file_type().is_socket())
+        {
+            if std::os::unix::net::UnixStream::connect(socket_path).is_ok() {
+                ::anyhow::bail!(
+                    "Control socket '{}' is in use by another process",
+                    socket_path.display()
+                );
+            }
+            std::fs::remove_file(socket_path).context(format!(
+                "Could not remove stale control socket '{}'",
+                socket_path.display()
+            ))?;
+        }
+        let listener = ::tokio::net::UnixListener::bind(socket_path).context(format!(
+            "Could not bind control socket '{}'",
+            socket_path.display()
+        ))?;
+
+        let events_sender = self.events_sender.clone();
+        ::tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(error) => {
+                        ::tracing::warn!("Could not accept connection on control socket: {error}");
+                        continue;
+                    }
+                };
+                let events_sender = events_sender.clone();
+                ::tokio::spawn(Self::serve(stream, events_sender));
+            }
+        });
+        self.control_socket = Some(socket_path.to_path_buf());
+        Ok(())
+    }
+
+    /// Answers a single request of a client of the control socket
+    async fn serve(
+        stream: ::tokio::net::UnixStream,
+        events_sender: ::tokio::sync::mpsc::UnboundedSender<Event>,
+    ) {
+        use ::tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _};
+
+        let (reader, mut writer) = stream.into_split();
+        let mut line = String::new();
+        if ::tokio::io::BufReader::new(reader)
+            .read_line(&mut line)
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        let response = match line.parse() {
+            Ok(request) => {
+                ::tracing::debug!("Received request '{request}' on control socket");
+                let (response_sender, response_receiver) = ::tokio::sync::oneshot::channel();
+                let _ = events_sender.send(Event::Control {
+                    request,
+                    response_sender,
+                });
+                response_receiver
+                    .await
+                    .unwrap_or_else(|_| Response::Error(String::from("sysinitd is shutting down")))
+            }
+            Err(message) => Response::Error(message),
+        };
+        let _ = writer.write_all(response.to_string().as_bytes()).await;
+    }
+
+    /// Answers a request received on the control socket
+    fn respond(&mut self, request: Request) -> Response {
+        match request {
+            Request::Status => Response::Ok(self.status()),
+            Request::Start(id) => self.start_on_request(&id),
+            Request::Target(target) => self.switch_target(&target),
+            Request::Scale(id, replicas) => self.scale(&id, replicas),
+        }
+    }
+
+    /// Checks the `instances` about to be added by scaling: they pass the
+    /// check set with [`Supervisor::set_instance_check`], and their
+    /// dependencies are among the
+    /// `remaining` services and do not depend on them in turn
+    fn check_instances(
+        &self,
+        instances: &std::collections::HashMap<String, Service>,
+        remaining: &std::collections::BTreeSet<&String>,
+    ) -> ::anyhow::Result<()> {
+        (self.instance_check)(instances)?;
+
+        let service = |id: &String| {
+            instances
+                .get(id)
+                .or_else(|| self.units.get(id).map(|unit| &unit.service))
+        };
+        for (id, instance) in instances {
+            if let Some(dependency) = instance
+                .start()
+                .dependencies()
+                .iter()
+                .find(|dependency| !remaining.contains(dependency))
+            {
+                ::anyhow::bail!("Dependency '{dependency}' of service '{id}' does not exist");
+            }
+
+            let mut pending: Vec<&String> = instance.start().dependencies().iter().collect();
+            let mut visited = std::collections::BTreeSet::new();
+            while let Some(dependency) = pending.pop() {
+                if dependency == id {
+                    ::anyhow::bail!("Service '{id}' depends on itself through its dependencies");
+                }
+                if visited.insert(dependency) {
+                    pending.extend(
+                        service(dependency)
+                            .map_or(&[][..], |service| service.start().dependencies()),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Scales the service `id` declared with replicas to `replicas`
+    /// instances and reports the state of all services afterwards
+    ///
+    /// Missing instances are checked before anything changes, then created
+    /// and started together once their dependencies are ready; surplus
+    /// instances are stopped, the highest
+    /// indices first. The number of replicas is recorded, so that it
+    /// survives a restart of `sysinitd` unless the definition changes.
+    fn scale(&mut self, id: &str, replicas: usize) -> Response {
+        if self.shutting_down {
+            return Response::Error(String::from("sysinitd is shutting down"));
+        }
+        let template_id = id.trim_end_matches('@');
+        let Some(template) = self.templates.get(template_id).cloned() else {
+            return Response::Error(format!("Service '{template_id}' has no replicas"));
+        };
+        if replicas > service::MAX_REPLICAS {
+            return Response::Error(format!(
+                "Service '{template_id}' can have at most {} replicas",
+                service::MAX_REPLICAS
+            ));
+        }
+
+        let mut surplus: Vec<(usize, String)> = self
+            .units
+            .iter()
+            .filter(|(_, unit)| !unit.removed)
+            .filter_map(|(id, unit)| {
+                let instance = unit.service.instance()?;
+                let belongs = instance
+                    .template()
+                    .is_some_and(|instance_template| instance_template.id() == template_id);
+                (belongs && instance.index() >= replicas).then(|| (instance.index(), id.clone()))
+            })
+            .collect();
+        surplus.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut missing = std::collections::HashMap::new();
+        for index in 0..replicas {
+            let instance_id = format!("{template_id}@{index}");
+            if self.units.contains_key(&instance_id) {
+                continue;
+            }
+            match template.instantiate(&index.to_string(), index) {
+                Ok(service) => missing.insert(instance_id, service),
+                Err(error) => return Response::Error(format!("{error:#}")),
+            };
+        }
+        // the services that are left once scaling is done
+        let remaining: std::collections::BTreeSet<&String> = self
+            .units
+            .iter()
+            .filter(|(_, unit)| {
+                match unit.service.instance().filter(|instance| {
+                    instance
+                        .template()
+                        .is_some_and(|instance_template| instance_template.id() == template_id)
+                }) {
+                    Some(instance) => instance.index() < replicas,
+                    None => !unit.removed,
+                }
+            })
+            .map(|(id, _)| id)
+            .chain(missing.keys())
+            .collect();
+        if let Err(error) = self.check_instances(&missing, &remaining) {
+            return Response::Error(format!("{error:#}"));
+        }
+        ::tracing::info!("Scaling service '{template_id}' to {replicas} replicas");
+
+        for (_, instance_id) in surplus {
+            let unit = self
+                .units
+                .get_mut(&instance_id)
+                .expect("bug: unknown service ID");
+            unit.removed = true;
+            unit.wanted = false;
+            unit.due = false;
+            unit.queued = false;
+            self.stop(&instance_id);
+        }
+        for index in 0..replicas {
+            let instance_id = format!("{template_id}@{index}");
+            let Some(unit) = self.units.get_mut(&instance_id) else {
+                continue;
+            };
+            if !std::mem::take(&mut unit.removed) {
+                continue;
+            }
+            if unit.state.is_alive() {
+                // the instance is still stopping and starts again once it
+                // exited
+                unit.queued = true;
+            } else {
+                unit.state = State::Inactive;
+            }
+        }
+        for (instance_id, service) in missing {
+            let schedule = service.enabled() && service.schedule().is_some();
+            let unit = Self::create_unit(&instance_id, service, &self.events_sender);
+            self.units.insert(instance_id.clone(), unit);
+            if schedule && self.schedules_armed {
+                self.arm_schedule(&instance_id, true);
+            }
+        }
+
+        if let Err(error) = template.record_replicas(&self.state_directory, replicas) {
+            ::tracing::warn!(
+                "Could not record the number of replicas of service '{template_id}': {error}"
+            );
+        }
+        let wanted = match self.wanted_by(&self.target) {
+            Ok(wanted) => wanted,
+            Err(error) => return Response::Error(error.to_string()),
+        };
+        for (id, unit) in &mut self.units {
+            unit.wanted = wanted.contains(id);
+        }
+        self.start_services();
+        Response::Ok(self.status())
+    }
+
+    /// Starts a service on request (once its dependencies are ready) and
+    /// reports its state afterwards
+    fn start_on_request(&mut self, id: &str) -> Response {
+        let Some(unit) = self.units.get(id) else {
+            return Response::Error(format!("Unknown service '{id}'"));
+        };
+        if self.shutting_down {
+            return Response::Error(String::from("sysinitd is shutting down"));
+        }
+        if !unit.service.enabled() {
+            return Response::Error(format!("Service '{id}' is disabled"));
+        }
+        if unit.state.is_alive() || matches!(unit.state, State::Active) {
+            return Response::Error(format!(
+                "Service '{id}' is already {}",
+                unit.describe_state()
+            ));
+        }
+
+        ::tracing::info!("Starting service '{id}' on request");
+        self.run_on_demand(id);
+        Response::Ok(format!("{id}: {}\n", self.units[id].describe_state()))
+    }
+
+    /// Whether the main service has exited or failed to start
+    fn main_service_terminated(&self) -> bool {
+        self.main_service.as_ref().is_some_and(|id| {
+            matches!(
+                self.units[id].state,
+                State::Exited(_) | State::Active | State::Failed | State::Skipped(_)
+            )
+        })
+    }
+
+    /// Passes a signal received by `sysinitd` on to all running services
+    /// and returns whether `sysinitd` shuts down because of it
+    ///
+    /// Each service receives what its [signal mapping](Service::signal_action)
+    /// says. If the main service is running, `sysinitd` shuts down once it
+    /// exits. Otherwise, `SIGTERM` and `SIGINT` shut `sysinitd` down, and
+    /// services that were just signaled are given their termination delay
+    /// to exit instead of being sent their termination signal as well.
+    fn handle_signal(&mut self, signal: Signal) -> bool {
+        let main_service_running = self
+            .main_service
+            .as_ref()
+            .is_some_and(|id| matches!(self.units[id].state, State::Running(_)));
+        let shut_down = !main_service_running && Self::SHUTDOWN_SIGNALS.contains(&signal);
+
+        let running_ids: Vec<String> = self
+            .units
+            .iter()
+            .filter(|(_, unit)| matches!(unit.state, State::Running(_)))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in running_ids {
+            let unit = &self.units[&id];
+            let State::Running(process) = &unit.state else {
+                continue;
+            };
+            let Some(signal_to_send) = unit.service.signal_action(signal).resolve(signal) else {
+                continue;
+            };
+
+            ::tracing::debug!("Sending {signal_to_send} to service '{id}'");
+            let result = if unit.service.signals().process_group() {
+                process.signal_group(signal_to_send)
+            } else {
+                process.signal(signal_to_send)
+            };
+            if let Err(error) = result {
+                ::tracing::warn!("Could not send {signal_to_send} to service '{id}': {error}");
+            }
+
+            if shut_down {
+                self.await_termination(&id);
+            }
+        }
+
+        shut_down
+    }
+
+    /// Handles a single event
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Exited { id, pid, status } => {
+                let unit = &self.units[&id];
+                if let State::Starting {
+                    launcher,
+                    launcher_exited: false,
+                } = &unit.state
+                    && launcher.pid() == pid
+                {
+                    self.launcher_exited(&id, status);
+                    return;
+                }
+                let stopping = match &unit.state {
+                    State::Running(process) if process.pid() == pid => false,
+                    State::Stopping(process) if process.pid() == pid => true,
+                    _ => return,
+                };
+                if !stopping && self.adopt_main_pid(&id) {
+                    return;
+                }
+                let unit = &self.units[&id];
+
+                let oneshot = unit.service.service_type() == ServiceType::Oneshot;
+                if oneshot && !stopping && status.success() {
+                    ::tracing::info!("Service '{id}' completed successfully");
+                } else if stopping || status.success() {
+                    ::tracing::info!("Service '{id}' terminated ({status})");
+                } else {
+                    ::tracing::warn!("Service '{id}' exited unexpectedly ({status})");
+                }
+                self.report_oom_kills(&id);
+                let unit = &self.units[&id];
+
+                if unit.service.termination().kill_descendants()
+                    && let State::Running(process) | State::Stopping(process) = &unit.state
+                {
+                    let leftovers = process.descendants();
+                    self.terminate_leftovers(&id, leftovers);
+                }
+                if oneshot && status.success() && self.units[&id].service.remain_active() {
+                    self.set_state(&id, State::Active);
+                } else {
+                    self.set_state(&id, State::Exited(status));
+                }
+                self.remove_cgroup(&id);
+                self.listen_again(&id);
+                if self.units[&id].queued && !self.shutting_down {
+                    ::tracing::info!("Running service '{id}' again as a run was queued");
+                    self.run_on_demand(&id);
+                }
+                // dependents of completed oneshot services can start now, and
+                // dependents of exited services can never start
+                self.start_services();
+            }
+            Event::LeftoversTerminated { id } => {
+                self.services_with_leftovers.remove(&id);
+                ::tracing::debug!("Leftover processes of service '{id}' terminated");
+                self.remove_cgroup(&id);
+            }
+            Event::MemoryEvents { id } => self.report_oom_kills(&id),
+            Event::LeftoversTimeout { id, leftovers } => {
+                self.services_with_leftovers.remove(&id);
+                let remaining = leftovers
+                    .iter()
+                    .filter(|leftover| leftover.signal(Signal::KILL).is_ok())
+                    .count();
+                if remaining > 0 {
+                    ::tracing::warn!(
+                        "{remaining} leftover process(es) of service '{id}' did not terminate in time - sent {}",
+                        Signal::KILL
+                    );
+                }
+            }
+            Event::TerminationTimeout { id, pid } => {
+                if let State::Stopping(process) = &self.units[&id].state
+                    && process.pid() == pid
+                {
+                    ::tracing::warn!(
+                        "Service '{id}' did not terminate in time - sending {}",
+                        Signal::KILL
+                    );
+                    let result = if self.units[&id].service.termination().kill_descendants() {
+                        process.signal_all(Signal::KILL)
+                    } else {
+                        process.signal(Signal::KILL)
+                    };
+                    if let Err(error) = result {
+                        ::tracing::error!("Could not kill service '{id}': {error}");
+                    }
+                }
+            }
+            Event::PidFileRead { id, result } => self.adopt_daemon(&id, result),
+            Event::Notified {
+                id,
+                sender,
+                notifications,
+            } => self.notified(&id, sender, notifications),
+            Event::WatchdogTimeout { id } => self.check_watchdog(&id),
+            Event::SocketActivity { id } => {
+                ::tracing::info!("Activating service '{id}' as a client connected to its sockets");
+                if let Some(unit) = self.units.get_mut(&id) {
+                    unit.activated = true;
+                }
+                self.start_services();
+            }
+            Event::ScheduleElapsed { id, at } => self.schedule_elapsed(&id, at),
+            Event::PathTriggered { id, trigger } => self.path_triggered(&id, &trigger),
+            Event::Signal(signal) => ::tracing::debug!("Ignoring {signal}"),
+            Event::Control {
+                request,
+                response_sender,
+            } => {
+                let _ = response_sender.send(self.respond(request));
+            }
+        }
+    }
+
+    /// Spawns the process of a service, unless one of its conditions is
+    /// not met
+    fn start(&mut self, id: &str) {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        if let Some(condition) = unit
+            .service
+            .conditions()
+            .iter()
+            .find(|condition| !condition.holds())
+        {
+            ::tracing::info!(
+                "Skipping service '{id}' because its condition is not met: {condition}"
+            );
+            unit.due = false;
+            unit.state = State::Skipped(condition.clone());
+            return;
+        }
+
+        ::tracing::info!("Starting service '{id}'");
+        let notify_socket = match self.open_notify_socket(id) {
+            Ok(notify_socket) => notify_socket,
+            Err(error) => {
+                ::tracing::error!("Could not start service '{id}': {error:#}");
+                self.set_state(id, State::Failed);
+                return;
+            }
+        };
+
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        unit.notified = Notified::default();
+        unit.due = false;
+        let service = &unit.service;
+        match Process::spawn(service, notify_socket.as_deref(), unit.listeners.as_ref()) {
+            Ok((process, exit_status)) => {
+                ::tracing::debug!("Service '{id}' runs with PID {}", process.pid());
+                self.watch_exit(id, process.pid(), exit_status);
+
+                let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+                if let Some(cgroup) = process.cgroup() {
+                    unit.oom_kills = cgroup.oom_kills();
+                    unit.cgroup = Some(cgroup.clone());
+                    self.watch_memory_events(id, cgroup);
+                }
+                let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+                unit.state = if unit.service.service_type() == ServiceType::Forking {
+                    State::Starting {
+                        launcher: process,
+                        launcher_exited: false,
+                    }
+                } else {
+                    State::Running(process)
+                };
+                if let Some(watchdog) = unit.service.watchdog() {
+                    let deadline = ::tokio::time::Instant::now() + watchdog.interval();
+                    unit.notified.watchdog_deadline = Some(deadline);
+                    self.arm_watchdog(id, deadline);
+                }
+            }
+            Err(error) => {
+                ::tracing::error!(
+                    "Could not start service '{id}' (command '{}'): {error:#}",
+                    service.start().command()
+                );
+                self.set_state(id, State::Failed);
+            }
+        }
+    }
+
+    /// Arms the timer of a scheduled service, which sends
+    /// [`Event::ScheduleElapsed`] when the service is to be run next
+    ///
+    /// With `catch_up`, a persistent service whose last recorded run is
+    /// older than its previous elapse is run right away instead.
+    fn arm_schedule(&mut self, id: &str, catch_up: bool) {
+        let now = std::time::SystemTime::now();
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        let Some(service_schedule) = unit.service.schedule() else {
+            return;
+        };
+
+        let missed = catch_up
+            && service_schedule.persistent()
+            && schedule::last_run(&self.state_directory, id)
+                .and_then(|last_run| service_schedule.timer().next(last_run))
+                .is_some_and(|elapse| elapse <= now);
+        if missed {
+            ::tracing::info!("Catching up on a missed run of service '{id}'");
+            unit.next_elapse = Some(now);
+            unit.next_run = Some(now);
+        } else {
+            // the timer continues from where it elapsed nominally, so that
+            // random delays do not accumulate, unless that is overdue (e.g.
+            // after a suspend)
+            let timer = service_schedule.timer();
+            unit.next_elapse = unit
+                .next_elapse
+                .and_then(|elapse| timer.next(elapse))
+                .filter(|elapse| *elapse > now)
+                .or_else(|| timer.next(now));
+            unit.next_run = unit
+                .next_elapse
+                .map(|elapse| elapse + schedule::random_delay(service_schedule.random_delay()));
+        }
+        let Some(next_run) = unit.next_run else {
+            ::tracing::warn!(
+                "Schedule '{}' of service '{id}' never elapses again",
+                service_schedule.timer()
+            );
+            return;
+        };
+        ::tracing::debug!(
+            "Service '{id}' runs next at {}",
+            ::humantime::format_rfc3339_seconds(next_run)
+        );
+
+        let delay = next_run.duration_since(now).unwrap_or_default();
+        let events_sender = self.events_sender.clone();
+        let event = Event::ScheduleElapsed {
+            id: id.to_string(),
+            at: next_run,
+        };
+        ::tokio::spawn(async move {
+            ::tokio::time::sleep(delay).await;
+            let _ = events_sender.send(event);
+        });
+    }
+
+    /// Runs a scheduled service whose timer elapsed, unless its previous
+    /// run is still active, and arms its timer again
+    fn schedule_elapsed(&mut self, id: &str, at: std::time::SystemTime) {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        if self.shutting_down || unit.next_run != Some(at) {
+            return;
+        }
+        let Some(service_schedule) = unit.service.schedule() else {
+            return;
+        };
+
+        if !unit.wanted {
+            ::tracing::debug!("Not running service '{id}' as it is not part of the current target");
+        } else if unit.state.is_alive() {
+            match service_schedule.overlap() {
+                Overlap::Skip => ::tracing::info!(
+                    "Skipping a run of service '{id}' because its previous run is still active"
+                ),
+                Overlap::Queue => {
+                    ::tracing::info!(
+                        "Queueing a run of service '{id}' until its previous run exited"
+                    );
+                    unit.queued = true;
+                }
+                Overlap::Kill => {
+                    unit.queued = true;
+                    self.stop(id);
+                }
+            }
+        } else {
+            ::tracing::info!("Running scheduled service '{id}'");
+            self.run_on_demand(id);
+        }
+        self.arm_schedule(id, false);
+    }
+
+    /// Runs a service one of whose paths triggered, or queues the run if
+    /// the previous one is still active
+    fn path_triggered(&mut self, id: &str, trigger: &PathTrigger) {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        if self.shutting_down || !unit.wanted {
+            return;
+        }
+
+        if unit.state.is_alive() {
+            ::tracing::info!(
+                "Queueing a run of service '{id}' because {trigger} while it is still running"
+            );
+            unit.queued = true;
+        } else {
+            ::tracing::info!("Running service '{id}' because {trigger}");
+            self.run_on_demand(id);
+        }
+    }
+
+    /// Starts a service (again) once its dependencies are ready, e.g. on
+    /// request or because its schedule elapsed, and records the run if its
+    /// schedule is persistent
+    fn run_on_demand(&mut self, id: &str) {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        unit.state = State::Inactive;
+        unit.due = true;
+        unit.queued = false;
+        if unit
+            .service
+            .schedule()
+            .is_some_and(|service_schedule| service_schedule.persistent())
+            && let Err(error) =
+                schedule::record_run(&self.state_directory, id, std::time::SystemTime::now())
+        {
+            ::tracing::warn!("Could not record the run of service '{id}': {error}");
+        }
+        self.start_services();
+    }
+
+    /// Returns the path of the notify socket of a notify service, binding
+    /// it on first use
+    ///
+    /// Notifications received on the socket are sent as
+    /// [`Event::Notified`]. Other services have no notify socket.
+    fn open_notify_socket(&mut self, id: &str) -> ::anyhow::Result<Option<std::path::PathBuf>> {
+        use ::anyhow::Context as _;
+
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        if unit.service.service_type() != ServiceType::Notify {
+            return Ok(None);
+        }
+        if let Some(notify_socket) = &unit.notify_socket {
+            return Ok(Some(notify_socket.clone()));
+        }
+
+        let path = self.notify_directory.join(format!("{id}.sock"));
+        let credentials = process::credentials::resolve(&unit.service)?;
+        let socket = notify::bind(
+            &path,
+            credentials.as_ref().and_then(|credentials| credentials.uid),
+            credentials.as_ref().and_then(|credentials| credentials.gid),
+        )
+        .context(format!("Could not bind notify socket '{}'", path.display()))?;
+
+        let events_sender = self.events_sender.clone();
+        let event_id = id.to_string();
+        ::tokio::spawn(async move {
+            loop {
+                let (sender, notifications) = match notify::receive(&socket).await {
+                    Ok((_, notifications)) if notifications.is_empty() => continue,
+                    Ok(received) => received,
+                    Err(error) => {
+                        ::tracing::warn!(
+                            "Could not receive on notify socket of service '{event_id}': {error}"
+                        );
+                        // errors that persist must not make this a busy loop
+                        ::tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                let event = Event::Notified {
+                    id: event_id.clone(),
+                    sender,
+                    notifications,
+                };
+                if events_sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        unit.notify_socket = Some(path.clone());
+        Ok(Some(path))
+    }
+
+    /// Feeds notifications a notify service sent into its state
+    ///
+    /// Only notifications sent by the main process of the service or by
+    /// processes that belong to it are accepted. `STOPPING=1` makes the
+    /// service stopping, i.e. it is killed if it does not terminate within
+    /// its termination delay.
+    fn notified(
+        &mut self,
+        id: &str,
+        sender: Option<::libc::pid_t>,
+        notifications: Vec<Notification>,
+    ) {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        let State::Running(process) = &unit.state else {
+            ::tracing::debug!("Ignoring notifications of service '{id}' as it is not running");
+            return;
+        };
+        let Some(sender) = sender.filter(|sender| process.contains(*sender)) else {
+            ::tracing::warn!(
+                "Ignoring notifications on the notify socket of service '{id}' sent by a process \
+                 that does not belong to it"
+            );
+            return;
+        };
+        ::tracing::trace!("Received notifications of service '{id}' from process {sender}");
+
+        let was_ready = unit.notified.ready;
+        let mut main_pid = None;
+        let mut stopping = false;
+        for notification in notifications {
+            match notification {
+                Notification::Ready => {
+                    if unit.notified.reloading {
+                        ::tracing::info!("Service '{id}' finished reloading");
+                    }
+                    unit.notified.ready = true;
+                    unit.notified.reloading = false;
+                }
+                Notification::Reloading => {
+                    ::tracing::info!("Service '{id}' is reloading");
+                    unit.notified.reloading = true;
+                }
+                Notification::Stopping => {
+                    ::tracing::info!("Service '{id}' is stopping");
+                    stopping = true;
+                }
+                Notification::Status(text) => {
+                    ::tracing::debug!("Service '{id}' reports status '{text}'");
+                    unit.notified.status = Some(text);
+                }
+                Notification::MainPid(pid) if pid != process.pid() => main_pid = Some(pid),
+                Notification::MainPid(_) => {}
+                Notification::Watchdog => {
+                    ::tracing::trace!("Received watchdog ping from service '{id}'");
+                    if let Some(watchdog) = unit.service.watchdog() {
+                        unit.notified.watchdog_deadline =
+                            Some(::tokio::time::Instant::now() + watchdog.interval());
+                    }
+                }
+            }
+        }
+
+        if let Some(pid) = main_pid {
+            unit.notified.main_pid = Some(pid);
+            self.adopt_main_pid(id);
+        }
+        if stopping {
+            self.await_termination(id);
+        }
+        if !was_ready && self.units[id].notified.ready {
+            ::tracing::info!("Service '{id}' is ready");
+            self.start_services();
+        }
+    }
+
+    /// Supervises the main PID a running notify service announced instead
+    /// of its current process and returns whether that succeeded
+    ///
+    /// The announced process can only be supervised once it is a child of
+    /// `sysinitd`, e.g. after the process that spawned it exited. Until
+    /// then, the main PID is kept and adopting it is retried when the
+    /// current process exits.
+    fn adopt_main_pid(&mut self, id: &str) -> bool {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        let State::Running(process) = &mut unit.state else {
+            return false;
+        };
+        let Some(pid) = unit.notified.main_pid else {
+            return false;
+        };
+
+        match process.adopt(pid) {
+            Ok((main_process, exit_status)) => {
+                ::tracing::info!("Service '{id}' continues with main PID {pid}");
+                unit.notified.main_pid = None;
+                unit.state = State::Running(main_process);
+                self.watch_exit(id, pid, exit_status);
+                true
+            }
+            Err(error) => {
+                ::tracing::debug!(
+                    "Main PID of service '{id}' can not be supervised yet: {error:#}"
+                );
+                false
+            }
+        }
+    }
+
+    /// Sends [`Event::WatchdogTimeout`] at `deadline`
+    fn arm_watchdog(&self, id: &str, deadline: ::tokio::time::Instant) {
+        let events_sender = self.events_sender.clone();
+        let id = id.to_string();
+        ::tokio::spawn(async move {
+            ::tokio::time::sleep_until(deadline).await;
+            let _ = events_sender.send(Event::WatchdogTimeout { id });
+        });
+    }
+
+    /// Kills a running notify service with the signal of its watchdog if
+    /// it did not ping the watchdog in time, or waits for the current
+    /// deadline otherwise
+    ///
+    /// The service is then treated like any service that was asked to
+    /// terminate: if it does not exit within its termination delay, it is
+    /// killed with `SIGKILL`.
+    fn check_watchdog(&mut self, id: &str) {
+        let unit = &self.units[id];
+        let (Some(watchdog), State::Running(process), Some(deadline)) = (
+            unit.service.watchdog(),
+            &unit.state,
+            unit.notified.watchdog_deadline,
+        ) else {
+            return;
+        };
+        if ::tokio::time::Instant::now() < deadline {
+            self.arm_watchdog(id, deadline);
+            return;
+        }
+
+        ::tracing::error!(
+            "Service '{id}' did not ping its watchdog within {} - sending {}",
+            ::humantime::format_duration(watchdog.interval()),
+            watchdog.signal()
+        );
+        if let Err(error) = process.signal(watchdog.signal()) {
+            ::tracing::warn!("Could not signal service '{id}': {error}");
+        }
+        self.await_termination(id);
+    }
+
+    /// Sends [`Event::Exited`] once the process `pid` of a service exited
+    fn watch_exit(
+        &self,
+        id: &str,
+        pid: ::libc::pid_t,
+        exit_status: ::tokio::sync::oneshot::Receiver<ExitStatus>,
+    ) {
+        let events_sender = self.events_sender.clone();
+        let id = id.to_string();
+        ::tokio::spawn(async move {
+            if let Ok(status) = exit_status.await {
+                let _ = events_sender.send(Event::Exited { id, pid, status });
+            }
+        });
+    }
+
+    /// Reads the PID file of a forking service once its launcher exited
+    /// successfully, or marks the service as exited otherwise
+    fn launcher_exited(&mut self, id: &str, status: ExitStatus) {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        if !status.success() {
+            ::tracing::warn!("Launcher of service '{id}' exited unexpectedly ({status})");
+            unit.state = State::Exited(status);
+            self.report_oom_kills(id);
+            self.remove_cgroup(id);
+            self.start_services();
+            return;
+        }
+
+        if let State::Starting {
+            launcher_exited, ..
+        } = &mut unit.state
+        {
+            *launcher_exited = true;
+        }
+        let Some(pid_file) = unit.service.pid_file().map(std::path::Path::to_path_buf) else {
+            ::tracing::error!("Forking service '{id}' has no PID file");
+            unit.state = State::Failed;
+            self.start_services();
+            return;
+        };
+        ::tracing::debug!(
+            "Launcher of service '{id}' exited - reading PID file '{}'",
+            pid_file.display()
+        );
+
+        let events_sender = self.events_sender.clone();
+        let id = id.to_string();
+        ::tokio::spawn(async move {
+            let result = Process::read_pid_file(&pid_file, Self::PID_FILE_TIMEOUT).await;
+            let _ = events_sender.send(Event::PidFileRead { id, result });
+        });
+    }
+
+    /// Supervises the daemon of a forking service whose PID file was read
+    fn adopt_daemon(&mut self, id: &str, result: ::anyhow::Result<::libc::pid_t>) {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        let State::Starting {
+            mut launcher,
+            launcher_exited: true,
+        } = std::mem::replace(&mut unit.state, State::Failed)
+        else {
+            return;
+        };
+
+        match result.and_then(|pid| launcher.adopt(pid)) {
+            Ok((daemon, exit_status)) => {
+                ::tracing::info!("Service '{id}' runs its daemon with PID {}", daemon.pid());
+                self.watch_exit(id, daemon.pid(), exit_status);
+                self.set_state(id, State::Running(daemon));
+                // the instance may have been removed by scaling while its
+                // daemon was still starting
+                if self.shutting_down || self.units[id].removed {
+                    self.stop(id);
+                }
+            }
+            Err(error) => {
+                ::tracing::error!("Could not supervise the daemon of service '{id}': {error:#}");
+            }
+        }
+        self.start_services();
+    }
+
+    /// Sends [`Event::MemoryEvents`] whenever `memory.events` of the cgroup
+    /// of a service changes, until the cgroup is removed
+    fn watch_memory_events(&self, id: &str, cgroup: &Cgroup) {
+        let watcher = match cgroup.watch_events() {
+            Ok(watcher) => watcher,
+            Err(error) => {
+                ::tracing::debug!(
+                    "Could not watch '{}' for OOM kills: {error}",
+                    cgroup.path().join("memory.events").display()
+                );
+                return;
+            }
+        };
+        let events_sender = self.events_sender.clone();
+        let id = id.to_string();
+        ::tokio::spawn(async move {
+            while let Ok(true) = watcher.changed().await {
+                if events_sender
+                    .send(Event::MemoryEvents { id: id.clone() })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Removes the cgroup of a service that is no longer alive once no
+    /// process is left in it
+    ///
+    /// Cgroups that still contain leftover processes are removed once these
+    /// terminated, or by [`Supervisor::shut_down`].
+    fn remove_cgroup(&mut self, id: &str) {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        if unit.state.is_alive() || matches!(unit.state, State::Starting { .. }) {
+            return;
+        }
+        let Some(cgroup) = &unit.cgroup else {
+            return;
+        };
+        if !cgroup.pids().is_empty() {
+            return;
+        }
+        match cgroup.remove() {
+            Ok(()) => unit.cgroup = None,
+            Err(error) => ::tracing::debug!(
+                "Could not remove cgroup '{}': {error}",
+                cgroup.path().display()
+            ),
+        }
+    }
+
+    /// Warns about processes of a service killed by the OOM killer since
+    /// the last report, as counted in `memory.events` of its cgroup
+    fn report_oom_kills(&mut self, id: &str) {
+        let Some(unit) = self.units.get_mut(id) else {
+            return;
+        };
+        let Some(oom_kills) = unit.cgroup.as_ref().map(Cgroup::oom_kills) else {
+            return;
+        };
+        if oom_kills > unit.oom_kills {
+            ::tracing::warn!(
+                "{} process(es) of service '{id}' were killed by the OOM killer",
+                oom_kills - unit.oom_kills
+            );
+            unit.oom_kills = oom_kills;
+        }
+    }
+
+    /// Asks a running service (or the running launcher of a forking
+    /// service) to terminate
+    fn stop(&mut self, id: &str) {
+        let unit = &self.units[id];
+        let (State::Running(process)
+        | State::Starting {
+            launcher: process,
+            launcher_exited: false,
+        }) = &unit.state
+        else {
+            return;
+        };
+
+        let termination = unit.service.termination();
+        let signal = termination.signal();
+        ::tracing::info!("Stopping service '{id}' with {signal}");
+        let result = if termination.kill_descendants() {
+            process.signal_all(signal)
+        } else {
+            process.signal(signal)
+        };
+        if let Err(error) = result {
+            ::tracing::warn!("Could not signal service '{id}': {error}");
+        }
+
+        self.await_termination(id);
+    }
+
+    /// Marks a running service that was asked to terminate as stopping and
+    /// kills it if it does not terminate within its termination delay
+    fn await_termination(&mut self, id: &str) {
+        let unit = self.units.get_mut(id).expect("bug: unknown service ID");
+        let process = match std::mem::replace(&mut unit.state, State::Inactive) {
+            State::Running(process)
+            | State::Starting {
+                launcher: process,
+                launcher_exited: false,
+            } => process,
+            state => {
+                unit.state = state;
+                return;
+            }
+        };
+
+        let events_sender = self.events_sender.clone();
+        let delay = unit.service.termination().delay();
+        let event = Event::TerminationTimeout {
+            id: id.to_string(),
+            pid: process.pid(),
+        };
+        ::tokio::spawn(async move {
+            ::tokio::time::sleep(delay).await;
+            let _ = events_sender.send(event);
+        });
+
+        unit.state = State::Stopping(process);
+    }
+
+    /// Asks processes left behind by the exited main process of a service
+    /// to terminate and kills them if they do not terminate in time
+    fn terminate_leftovers(&mut self, id: &str, leftovers: Vec<process::ProcessHandle>) {
+        if leftovers.is_empty() {
+            return;
+        }
+        self.services_with_leftovers.insert(id.to_string());
+
+        let termination = self.units[id].service.termination();
+        ::tracing::info!(
+            "Stopping {} leftover process(es) of service '{id}' with {}",
+            leftovers.len(),
+            termination.signal()
+        );
+        for leftover in &leftovers {
+            let _ = leftover.signal(termination.signal());
+        }
+
+        let events_sender = self.events_sender.clone();
+        let delay = termination.delay();
+        let id = id.to_string();
+        ::tokio::spawn(async move {
+            let all_terminated = async {
+                for leftover in &leftovers {
+                    leftover.terminated().await;
+                }
+            };
+            let event = match ::tokio::time::timeout(delay, all_terminated).await {
+                Ok(()) => Event::LeftoversTerminated { id },
+                Err(_) => Event::LeftoversTimeout { id, leftovers },
+            };
+            let _ = events_sender.send(event);
+        });
+    }
+
+    /// Updates the state of a service
+    fn set_state(&mut self, id: &str, state: State) {
+        if let Some(unit) = self.units.get_mut(id) {
+            unit.state = state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a supervisor from YAML service definitions
+    fn create_supervisor(definitions: &[&str]) -> Supervisor {
+        let services = definitions
+            .iter()
+            .map(|definition| {
+                let service = Service::serde_from_slice(
+                    definition.as_bytes(),
+                    std::path::Path::new("test.yaml"),
+                )
+                .expect("Could not parse service definition");
+                (service.id().clone(), service)
+            })
+            .collect();
+        Supervisor::new(services).expect("Could not create supervisor")
+    }
+
+    /// Whether the tests run as root; tests that need root report that
+    /// they were skipped otherwise
+    fn running_as_root(test: &str) -> bool {
+        // SAFETY: `getuid(2)` has no memory safety preconditions
+        let root = unsafe { ::libc::getuid() } == 0;
+        if !root {
+            eprintln!("Skipping test {test} as it requires root");
+        }
+        root
+    }
+
+    #[::tokio::test]
+    async fn main_service_exit_code() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nstart: { command: sh, arguments: [-c, 'exit 3'] }",
+            "meta: { version: 0.1.0 }\nid: sidecar\nstart: { command: sleep, arguments: ['60'] }",
+        ]);
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 3);
+        assert!(matches!(
+            supervisor.units["sidecar"].state,
+            State::Exited(ExitStatus::Signaled(::libc::SIGTERM))
+        ));
+    }
+
+    #[::tokio::test]
+    async fn signal_forwarded_to_main_service() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nstart: { command: sleep, arguments: ['60'] }",
+        ]);
+        supervisor.start_services();
+        assert!(!supervisor.handle_signal(Signal::TERM));
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 128 + ::libc::SIGTERM);
+    }
+
+    #[::tokio::test]
+    async fn signal_without_main_service() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: sidecar\nstart: { command: sleep, arguments: ['60'] }",
+        ]);
+        supervisor.start_services();
+        assert!(!supervisor.handle_signal(Signal::HUP));
+        assert!(supervisor.handle_signal(Signal::TERM));
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn signal_translated() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: sidecar\nstart: { command: sleep, arguments: ['60'] }\nsignals: { process_group: true, TERM: KILL, HUP: ignore }",
+        ]);
+        supervisor.start_services();
+        assert!(!supervisor.handle_signal(Signal::HUP));
+        assert!(matches!(
+            supervisor.units["sidecar"].state,
+            State::Running(_)
+        ));
+        assert!(supervisor.handle_signal(Signal::TERM));
+        assert!(matches!(
+            supervisor.units["sidecar"].state,
+            State::Stopping(_)
+        ));
+        assert_eq!(supervisor.shut_down().await, 0);
+        assert!(matches!(
+            supervisor.units["sidecar"].state,
+            State::Exited(ExitStatus::Signaled(::libc::SIGKILL))
+        ));
+    }
+
+    #[::tokio::test]
+    async fn process_group_terminated() {
+        // the double-forked process is only attributed to the service once
+        // it was re-parented to a subreaper
+        crate::library::process::become_subreaper().expect("Could not become a child subreaper");
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: forking\nstart: { command: sh, arguments: [-c, 'sleep 61 & setsid sh -c \"sleep 62 &\"; sleep 60'] }\ntermination: { kill_descendants: true, delay: 5s }",
+        ]);
+        supervisor.start_services();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        let State::Running(process) = &supervisor.units["forking"].state else {
+            panic!("Service 'forking' is not running");
+        };
+        let descendants: Vec<::libc::pid_t> = process
+            .descendants()
+            .iter()
+            .map(process::ProcessHandle::pid)
+            .collect();
+        assert_eq!(descendants.len(), 3, "sleep 60, sleep 61 and sleep 62");
+
+        assert_eq!(supervisor.shut_down().await, 0);
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        for descendant in descendants {
+            let stat = std::fs::read_to_string(format!("/proc/{descendant}/stat"));
+            assert!(
+                stat.is_err() || stat.is_ok_and(|stat| stat.contains(") Z ")),
+                "descendant {descendant} is still running"
+            );
+        }
+    }
+
+    #[::tokio::test]
+    async fn credentials_dropped() {
+        if !running_as_root("credentials_dropped") {
+            return;
+        }
+
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nuser: 65534\ngroup: 65534\nsupplementary_groups: [1]\nstart: { command: sh, arguments: [-c, 'test \"$(id -u):$(id -g):$(id -G)\" = \"65534:65534:65534 1\"'] }",
+        ]);
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn capabilities_restricted() {
+        if !running_as_root("capabilities_restricted") {
+            return;
+        }
+
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nuser: 65534\nno_new_privileges: true\ncapabilities: { ambient: [NET_BIND_SERVICE] }\nstart: { command: sh, arguments: [-c, 'grep -q \"CapEff:.*0000000000000400\" /proc/self/status && grep -q \"CapAmb:.*0000000000000400\" /proc/self/status && grep -q \"NoNewPrivs:.*1\" /proc/self/status'], dependencies: [dropped] }",
+            "meta: { version: 0.1.0 }\nid: dropped\ntype: oneshot\nremain_active: true\ncapabilities: { drop_all: true, bounding: [NET_BIND_SERVICE] }\nstart: { command: sh, arguments: [-c, 'grep -q \"CapEff:.*0000000000000000\" /proc/self/status && grep -q \"CapPrm:.*0000000000000000\" /proc/self/status && grep -q \"CapBnd:.*0000000000000400\" /proc/self/status'] }",
+        ]);
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert!(matches!(supervisor.units["dropped"].state, State::Active));
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn namespaces_isolated() {
+        if !running_as_root("namespaces_isolated") {
+            return;
+        }
+
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nnamespaces: { mount: { private_tmp: true, read_only: [/etc, /dev] }, pid: true, network: true, ipc: true, uts: { hostname: sandbox } }\nstart: { command: sh, arguments: [-c, 'test \"$(cat /proc/sys/kernel/hostname)\" = sandbox && test \"$(grep -c : /proc/net/dev)\" = 1 && test -z \"$(ls -A /tmp)\" && touch /tmp/file && ! touch /etc/sysinitd-test 2>/dev/null && ! touch /dev/shm/sysinitd-test 2>/dev/null && test $$ = 2 && test -d /proc/1'] }",
+            "meta: { version: 0.1.0 }\nid: sleeper\nnamespaces: { pid: true }\nstart: { command: sleep, arguments: ['60'] }",
+        ]);
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert!(matches!(
+            supervisor.units["sleeper"].state,
+            State::Running(_)
+        ));
+        // the sleeper is terminated through the processes of its PID
+        // namespace, which report the signal even though PID 1 can not be
+        // killed by it
+        assert_eq!(supervisor.shut_down().await, 0);
+        assert_eq!(
+            supervisor.units["sleeper"].state.to_string(),
+            "exited (killed by SIGTERM)"
+        );
+    }
+
+    #[::tokio::test]
+    async fn seccomp_filtered() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: killed\nseccomp: { default_action: kill, deny: [uname] }\nstart: { command: uname }",
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nseccomp: { presets: [no-network], deny: [uname] }\nstart: { command: sh, arguments: [-c, 'sleep 1 && ! uname 2>/dev/null'] }",
+        ]);
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert!(matches!(
+            supervisor.units["killed"].state,
+            State::Exited(ExitStatus::Signaled(::libc::SIGSYS))
+        ));
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn scheduling_in_status() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: batch\nnice: 5\ncpu_affinity: [0]\nio_class: idle\noom_score_adjust: 500\nstart: { command: sleep, arguments: ['60'] }",
+        ]);
+        supervisor.start_services();
+        let status = supervisor.status();
+        assert!(
+            status.ends_with("(nice=5 cpu_affinity=0 io=idle/0 oom_score_adjust=500)\n"),
+            "{status}"
+        );
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn oneshot_dependencies() {
+        let marker = std::env::temp_dir().join(format!("sysinitd-oneshot-{}", std::process::id()));
+        let setup = format!(
+            "meta: {{ version: 0.1.0 }}\nid: setup\ntype: oneshot\nremain_active: true\nstart: {{ command: sh, arguments: [-c, 'sleep 0.2 && touch {}'] }}",
+            marker.display()
+        );
+        let app = format!(
+            "meta: {{ version: 0.1.0 }}\nid: app\nmain: true\nstart: {{ command: test, arguments: [-e, '{}'], dependencies: [setup] }}",
+            marker.display()
+        );
+        let mut supervisor = create_supervisor(&[
+            &setup,
+            &app,
+            "meta: { version: 0.1.0 }\nid: broken\ntype: oneshot\nstart: { command: 'false' }",
+            "meta: { version: 0.1.0 }\nid: never\nstart: { command: sleep, arguments: ['60'], dependencies: [broken] }",
+        ]);
+        supervisor.start_services();
+        assert!(matches!(supervisor.units["app"].state, State::Inactive));
+        assert!(matches!(supervisor.units["never"].state, State::Inactive));
+
+        supervisor.supervise().await;
+        let _ = std::fs::remove_file(&marker);
+        assert!(matches!(supervisor.units["setup"].state, State::Active));
+        assert!(matches!(supervisor.units["broken"].state, State::Exited(_)));
+        assert!(matches!(supervisor.units["never"].state, State::Failed));
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn forking_daemon() {
+        crate::library::process::become_subreaper().expect("Could not become a subreaper");
+        let pid_file =
+            std::env::temp_dir().join(format!("sysinitd-forking-{}.pid", std::process::id()));
+        let daemon = format!(
+            "meta: {{ version: 0.1.0 }}\nid: daemon\ntype: forking\npid_file: {0}\nstart: {{ command: sh, arguments: [-c, 'sleep 60 & sleep 0.2 && echo $! > {0}'] }}",
+            pid_file.display()
+        );
+        let app = format!(
+            "meta: {{ version: 0.1.0 }}\nid: app\nmain: true\nstart: {{ command: sh, arguments: [-c, 'kill -0 $(cat {})'], dependencies: [daemon] }}",
+            pid_file.display()
+        );
+        let mut supervisor = create_supervisor(&[&daemon, &app]);
+        supervisor.start_services();
+        assert!(matches!(
+            supervisor.units["daemon"].state,
+            State::Starting { .. }
+        ));
+        assert!(matches!(supervisor.units["app"].state, State::Inactive));
+
+        supervisor.supervise().await;
+        let daemon_pid: ::libc::pid_t = std::fs::read_to_string(&pid_file)
+            .expect("Could not read PID file")
+            .trim()
+            .parse()
+            .expect("Invalid PID file");
+        let _ = std::fs::remove_file(&pid_file);
+        let State::Running(process) = &supervisor.units["daemon"].state else {
+            panic!("Daemon is not running");
+        };
+        assert_eq!(process.pid(), daemon_pid);
+        assert_eq!(supervisor.shut_down().await, 0);
+        assert!(matches!(supervisor.units["daemon"].state, State::Exited(_)));
+    }
+
+    #[::tokio::test]
+    async fn forking_replica_removed_while_starting() {
+        crate::library::process::become_subreaper().expect("Could not become a subreaper");
+        let directory =
+            std::env::temp_dir().join(format!("sysinitd-forking-replicas-{}", std::process::id()));
+        let pid_file = directory.join("worker.pid");
+        std::fs::create_dir_all(&directory).expect("Could not create directory");
+        let template = format!(
+            "meta: {{ version: 0.1.0 }}\nid: worker\nreplicas: 1\ntype: forking\npid_file: {0}\nstart: {{ command: sh, arguments: [-c, 'sleep 60 & pid=$!; (sleep 0.3 && echo $pid > {0}) &'] }}",
+            pid_file.display()
+        );
+        let (services, template) = Service::serde_from_template_slice(
+            template.as_bytes(),
+            std::path::Path::new("test.yaml"),
+            &directory,
+        )
+        .expect("Could not parse template");
+        let mut supervisor = Supervisor::new(
+            services
+                .into_iter()
+                .map(|service| (service.id().clone(), service))
+                .collect(),
+        )
+        .expect("Could not create supervisor");
+        supervisor.set_templates(template);
+        supervisor.set_state_directory(&directory);
+        supervisor.start_services();
+
+        // scale down once the launcher exited, but before the daemon wrote
+        // its PID file
+        while !matches!(
+            supervisor.units["worker@0"].state,
+            State::Starting {
+                launcher_exited: true,
+                ..
+            }
+        ) {
+            let event = supervisor
+                .events_receiver
+                .recv()
+                .await
+                .expect("Event channel closed");
+            supervisor.handle(event);
+        }
+        let response = supervisor.respond(Request::Scale(String::from("worker"), 0));
+        assert!(matches!(response, Response::Ok(_)));
+
+        ::tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while !matches!(supervisor.units["worker@0"].state, State::Exited(_)) {
+                let event = supervisor
+                    .events_receiver
+                    .recv()
+                    .await
+                    .expect("Event channel closed");
+                supervisor.handle(event);
+            }
+        })
+        .await
+        .expect("The daemon of the removed instance was not stopped");
+
+        assert_eq!(supervisor.shut_down().await, 0);
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    /// Sends `message` to the datagram socket at `path` on behalf of the
+    /// process `pid`, which requires `CAP_SYS_ADMIN`
+    fn send_as(path: &std::path::Path, message: &str, pid: ::libc::pid_t) {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        // SAFETY: all structs are plain C structs for which all zeros is
+        // valid, and all pointers stay valid for the duration of the calls
+        unsafe {
+            let socket = ::libc::socket(
+                ::libc::AF_UNIX,
+                ::libc::SOCK_DGRAM | ::libc::SOCK_CLOEXEC,
+                0,
+            );
+            assert_ne!(socket, -1, "Could not create client");
+            let mut address: ::libc::sockaddr_un = std::mem::zeroed();
+            address.sun_family = ::libc::AF_UNIX as ::libc::sa_family_t;
+            for (target, source) in address.sun_path.iter_mut().zip(path.as_os_str().as_bytes()) {
+                *target = *source as ::libc::c_char;
+            }
+
+            let credentials = ::libc::ucred {
+                pid,
+                uid: ::libc::getuid(),
+                gid: ::libc::getgid(),
+            };
+            let mut control = [0_u64; 8];
+            let mut vector = ::libc::iovec {
+                iov_base: message.as_ptr().cast_mut().cast(),
+                iov_len: message.len(),
+            };
+            let mut header: ::libc::msghdr = std::mem::zeroed();
+            header.msg_name = (&raw mut address).cast();
+            header.msg_namelen = std::mem::size_of::<::libc::sockaddr_un>() as ::libc::socklen_t;
+            header.msg_iov = &raw mut vector;
+            header.msg_iovlen = 1;
+            header.msg_control = control.as_mut_ptr().cast();
+            header.msg_controllen =
+                ::libc::CMSG_SPACE(std::mem::size_of::<::libc::ucred>() as u32) as _;
+            let control_message = ::libc::CMSG_FIRSTHDR(&raw const header);
+            (*control_message).cmsg_level = ::libc::SOL_SOCKET;
+            (*control_message).cmsg_type = ::libc::SCM_CREDENTIALS;
+            (*control_message).cmsg_len =
+                ::libc::CMSG_LEN(std::mem::size_of::<::libc::ucred>() as u32) as _;
+            std::ptr::write_unaligned(::libc::CMSG_DATA(control_message).cast(), credentials);
+
+            let sent = ::libc::sendmsg(socket, &raw const header, 0);
+            let error = std::io::Error::last_os_error();
+            ::libc::close(socket);
+            assert_ne!(sent, -1, "Could not notify: {error}");
+        }
+    }
+
+    #[::tokio::test]
+    async fn notify_readiness_and_status() {
+        if !running_as_root("notify_readiness_and_status") {
+            return;
+        }
+
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: daemon\ntype: notify\ntermination: { delay: 200ms }\nstart: { command: sh, arguments: [-c, 'test -S \"$NOTIFY_SOCKET\" && exec sleep 60'] }",
+            "meta: { version: 0.1.0 }\nid: app\nstart: { command: sleep, arguments: ['60'], dependencies: [daemon] }",
+        ]);
+        let notify_directory =
+            std::env::temp_dir().join(format!("sysinitd-notify-{}", std::process::id()));
+        supervisor.set_notify_directory(&notify_directory);
+        supervisor.start_services();
+        assert!(supervisor.status().contains("daemon: starting, PID"));
+        assert!(matches!(supervisor.units["app"].state, State::Inactive));
+
+        let notify_socket = notify_directory.join("daemon.sock");
+        let State::Running(process) = &supervisor.units["daemon"].state else {
+            panic!("Daemon is not running");
+        };
+        let daemon_pid = process.pid();
+        let notify = async |supervisor: &mut Supervisor, message: &str, pid: ::libc::pid_t| {
+            send_as(&notify_socket, message, pid);
+            let event = ::tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                supervisor.events_receiver.recv(),
+            )
+            .await
+            .expect("No notification received")
+            .expect("Event channel closed");
+            supervisor.handle(event);
+        };
+        // notifications of processes that do not belong to the service
+        notify(
+            &mut supervisor,
+            "READY=1\n",
+            std::process::id() as ::libc::pid_t,
+        )
+        .await;
+        assert!(matches!(supervisor.units["app"].state, State::Inactive));
+
+        notify(&mut supervisor, "STATUS=Loading\n", daemon_pid).await;
+        notify(&mut supervisor, "READY=1\nSTATUS=Serving\n", daemon_pid).await;
+        assert!(matches!(supervisor.units["app"].state, State::Running(_)));
+        let status = supervisor.status();
+        assert!(status.contains("daemon: running, PID"));
+        assert!(status.contains("status 'Serving'"));
+
+        notify(&mut supervisor, "RELOADING=1", daemon_pid).await;
+        assert!(supervisor.status().contains("daemon: reloading, PID"));
+        // the daemon is killed once it does not terminate within its
+        // termination delay
+        notify(&mut supervisor, "STOPPING=1", daemon_pid).await;
+        assert!(matches!(
+            supervisor.units["daemon"].state,
+            State::Stopping(_)
+        ));
+        assert_eq!(supervisor.shut_down().await, 0);
+        assert_eq!(
+            supervisor.units["daemon"].state.to_string(),
+            "exited (killed by SIGKILL)"
+        );
+        assert!(!notify_directory.join("daemon.sock").exists());
+        let _ = std::fs::remove_dir(&notify_directory);
+    }
+
+    #[::tokio::test]
+    async fn watchdog_expired() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\ntype: notify\nwatchdog: { interval: 300ms, signal: ABRT }\nstart: { command: sh, arguments: [-c, 'test \"$WATCHDOG_USEC\" = 300000 && exec sleep 60'] }",
+        ]);
+        let notify_directory =
+            std::env::temp_dir().join(format!("sysinitd-watchdog-{}", std::process::id()));
+        supervisor.set_notify_directory(&notify_directory);
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 128 + ::libc::SIGABRT);
+        let _ = std::fs::remove_dir(&notify_directory);
+    }
+
+    #[::tokio::test]
+    async fn socket_activation() {
+        let socket_path =
+            std::env::temp_dir().join(format!("sysinitd-activation-{}.sock", std::process::id()));
+        let server = format!(
+            "meta: {{ version: 0.1.0 }}\nid: server\nmain: true\nsockets: {{ lazy: true, listen: [{{ unix_stream: {}, name: api }}] }}\nstart: {{ command: sh, arguments: [-c, 'test \"$LISTEN_FDS:$LISTEN_FDNAMES:$LISTEN_PID\" = \"1:api:$$\" && test -S /proc/self/fd/3'] }}",
+            socket_path.display()
+        );
+        let mut supervisor = create_supervisor(&[&server]);
+        supervisor.start_services();
+        assert!(matches!(supervisor.units["server"].state, State::Inactive));
+        assert!(supervisor.status().contains("server: listening"));
+
+        let _client =
+            std::os::unix::net::UnixStream::connect(&socket_path).expect("Could not connect");
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 0);
+        assert!(!socket_path.exists());
+    }
+
+    #[::tokio::test]
+    async fn socket_reactivation() {
+        let directory =
+            std::env::temp_dir().join(format!("sysinitd-reactivation-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).expect("Could not create directory");
+        let sink = format!(
+            "meta: {{ version: 0.1.0 }}\nid: sink\nsockets: {{ lazy: true, listen: [{{ fifo: {0}/fifo }}] }}\nstart: {{ command: sh, arguments: [-c, 'head -n 1 <&3 >> {0}/log'] }}",
+            directory.display()
+        );
+        // the main service starts while the sink still waits for a client
+        let main = format!(
+            "meta: {{ version: 0.1.0 }}\nid: main\nmain: true\nstart: {{ command: sh, arguments: [-c, 'echo a > {0}/fifo && sleep 0.5 && echo b > {0}/fifo && sleep 0.5 && test $(wc -l < {0}/log) = 2'], dependencies: [sink] }}",
+            directory.display()
+        );
+        let mut supervisor = create_supervisor(&[&sink, &main]);
+        supervisor.start_services();
+        assert!(matches!(supervisor.units["main"].state, State::Running(_)));
+
+        supervisor.supervise().await;
+        assert!(supervisor.status().contains("sink: listening"));
+        assert_eq!(supervisor.shut_down().await, 0);
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[::tokio::test]
+    async fn scheduled_runs() {
+        let directory =
+            std::env::temp_dir().join(format!("sysinitd-schedule-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).expect("Could not create directory");
+        let tick = format!(
+            "meta: {{ version: 0.1.0 }}\nid: tick\nschedule: {{ when: every 100ms, persistent: true }}\nstart: {{ command: sh, arguments: [-c, 'echo >> {}/ticks'] }}",
+            directory.display()
+        );
+        let main = format!(
+            "meta: {{ version: 0.1.0 }}\nid: main\nmain: true\nstart: {{ command: sh, arguments: [-c, 'sleep 1 && test $(wc -l < {}/ticks) -ge 3'] }}",
+            directory.display()
+        );
+        let mut supervisor = create_supervisor(&[&tick, &main]);
+        supervisor.set_state_directory(&directory);
+        supervisor.start_services();
+        assert!(matches!(supervisor.units["tick"].state, State::Inactive));
+        assert!(supervisor.status().contains("tick: inactive, next run "));
+
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 0);
+        assert!(schedule::last_run(&directory, "tick").is_some());
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[::tokio::test]
+    async fn scheduled_dependency_awaited() {
+        let spool = std::env::temp_dir().join(format!("sysinitd-awaited-{}", std::process::id()));
+        let import = format!(
+            "meta: {{ version: 0.1.0 }}\nid: import\ntype: oneshot\ntrigger: {{ path: [{{ exists: {} }}] }}\nstart: {{ command: 'true' }}",
+            spool.display()
+        );
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: backup\ntype: oneshot\nschedule: { when: every 1h }\nstart: { command: 'true' }",
+            &import,
+            "meta: { version: 0.1.0 }\nid: report\nstart: { command: 'true', dependencies: [backup, import] }",
+        ]);
+        supervisor.start_services();
+
+        // dependents wait for the next run instead of running them right away
+        for id in ["backup", "import", "report"] {
+            assert!(matches!(supervisor.units[id].state, State::Inactive));
+        }
+        assert!(!supervisor.units["backup"].due);
+        assert!(!supervisor.units["import"].due);
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn schedule_without_drift() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: tick\nschedule: { when: every 1h, random_delay: 30m }\nstart: { command: 'true' }",
+        ]);
+        supervisor.start_services();
+        let first = supervisor.units["tick"]
+            .next_elapse
+            .expect("Schedule not armed");
+
+        // the random delay of a run does not shift the following ones
+        supervisor.arm_schedule("tick", false);
+        let unit = &supervisor.units["tick"];
+        let second = first + std::time::Duration::from_secs(3600);
+        assert_eq!(unit.next_elapse, Some(second));
+        let next_run = unit.next_run.expect("Schedule not armed");
+        assert!(next_run >= second && next_run < second + std::time::Duration::from_secs(1800));
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn path_triggered_runs() {
+        let spool = std::env::temp_dir().join(format!("sysinitd-spool-{}", std::process::id()));
+        std::fs::create_dir_all(&spool).expect("Could not create directory");
+        let import = format!(
+            "meta: {{ version: 0.1.0 }}\nid: import\ntype: oneshot\ntrigger: {{ debounce: 50ms, path: [{{ directory_not_empty: {0} }}] }}\nstart: {{ command: sh, arguments: [-c, 'mv {0}/job {0}/../sysinitd-imported-{1}'] }}",
+            spool.display(),
+            std::process::id()
+        );
+        let main = format!(
+            "meta: {{ version: 0.1.0 }}\nid: main\nmain: true\nstart: {{ command: sh, arguments: [-c, 'echo > {}/job && sleep 1'] }}",
+            spool.display()
+        );
+        let mut supervisor = create_supervisor(&[&import, &main]);
+        supervisor.start_services();
+        assert!(supervisor.status().contains("import: watching"));
+
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 0);
+        let imported = spool.with_file_name(format!("sysinitd-imported-{}", std::process::id()));
+        assert!(imported.exists());
+        assert!(
+            matches!(supervisor.units["import"].state, State::Exited(status) if status.success())
+        );
+        let _ = std::fs::remove_file(&imported);
+        let _ = std::fs::remove_dir_all(&spool);
+    }
+
+    #[::tokio::test]
+    async fn manual_and_disabled_services() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: app\nautostart: false\nstart: { command: sleep, arguments: ['60'], dependencies: [database, metrics] }",
+            "meta: { version: 0.1.0 }\nid: database\nautostart: false\nstart: { command: sleep, arguments: ['60'] }",
+            "meta: { version: 0.1.0 }\nid: metrics\nenabled: false\nstart: { command: sleep, arguments: ['60'] }",
+            "meta: { version: 0.1.0 }\nid: tool\nautostart: false\nstart: { command: sleep, arguments: ['60'] }",
+        ]);
+        supervisor.start_services();
+        assert!(
+            supervisor
+                .units
+                .values()
+                .all(|unit| matches!(unit.state, State::Inactive))
+        );
+        assert!(supervisor.status().contains("metrics: disabled"));
+
+        // the dependency is pulled in, while the disabled one is ignored
+        let response = supervisor.respond(Request::Start(String::from("app")));
+        assert_eq!(response, Response::Ok(String::from("app: running\n")));
+        assert!(matches!(
+            supervisor.units["database"].state,
+            State::Running(_)
+        ));
+        assert!(matches!(supervisor.units["tool"].state, State::Inactive));
+        assert!(matches!(
+            supervisor.respond(Request::Start(String::from("metrics"))),
+            Response::Error(message) if message == "Service 'metrics' is disabled"
+        ));
+        assert!(matches!(
+            supervisor.respond(Request::Start(String::from("app"))),
+            Response::Error(message) if message == "Service 'app' is already running"
+        ));
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn target_switched() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: app\nstart: { command: sleep, arguments: ['60'], dependencies: [database] }",
+            "meta: { version: 0.1.0 }\nid: database\ntargets: []\nstart: { command: sleep, arguments: ['60'] }",
+            "meta: { version: 0.1.0 }\nid: migrate\ntargets: [migration-only]\nstart: { command: sleep, arguments: ['60'], dependencies: [database] }",
+            "meta: { version: 0.1.0 }\nid: shell\ntargets: [default, debug]\nstart: { command: sleep, arguments: ['60'] }",
+        ]);
+        assert!(supervisor.set_target("production").is_err());
+        supervisor
+            .set_target("migration-only")
+            .expect("Could not select target");
+        supervisor.start_services();
+        assert!(matches!(supervisor.units["app"].state, State::Inactive));
+        assert!(matches!(
+            supervisor.units["migrate"].state,
+            State::Running(_)
+        ));
+        assert!(matches!(
+            supervisor.units["database"].state,
+            State::Running(_)
+        ));
+
+        let response = supervisor.respond(Request::Target(String::from("default")));
+        assert!(matches!(response, Response::Ok(_)));
+        assert!(matches!(
+            supervisor.units["migrate"].state,
+            State::Stopping(_)
+        ));
+        assert!(matches!(
+            supervisor.units["database"].state,
+            State::Running(_)
+        ));
+        assert!(matches!(supervisor.units["app"].state, State::Running(_)));
+        assert!(matches!(supervisor.units["shell"].state, State::Running(_)));
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn condition_not_met() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nstart: { command: 'true', dependencies: [optional] }",
+            "meta: { version: 0.1.0 }\nid: optional\nconditions: [{ path_exists: / }, { path_exists: /nonexistent }]\nstart: { command: sleep, arguments: ['60'] }",
+        ]);
+        supervisor.start_services();
+        assert!(
+            supervisor
+                .status()
+                .contains("optional: condition not met ('/nonexistent' exists)")
+        );
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn template_instances() {
+        let template = "meta: { version: 0.1.0 }\nid: worker\nreplicas: 2\ntype: oneshot\nstart: { command: sh, arguments: [-c, 'test \"$SYSINITD_INSTANCE:$SYSINITD_INSTANCE_INDEX\" = {{instance}}:{{index}}'] }";
+        let mut services: std::collections::HashMap<String, Service> =
+            Service::serde_from_template_slice(
+                template.as_bytes(),
+                std::path::Path::new("test.yaml"),
+                std::path::Path::new("/nonexistent"),
+            )
+            .expect("Could not parse template")
+            .0
+            .into_iter()
+            .map(|service| (service.id().clone(), service))
+            .collect();
+        let main = Service::serde_from_slice(
+            b"meta: { version: 0.1.0 }\nid: main\nmain: true\nstart: { command: 'true', dependencies: [worker@0, worker@1] }",
+            std::path::Path::new("test.yaml"),
+        )
+        .expect("Could not parse service definition");
+        services.insert(main.id().clone(), main);
+
+        let mut supervisor = Supervisor::new(services).expect("Could not create supervisor");
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn replicas_scaled() {
+        let directory =
+            std::env::temp_dir().join(format!("sysinitd-replicas-{}", std::process::id()));
+        let template = "meta: { version: 0.1.0 }\nid: worker\nreplicas: 2\nstart: { command: sleep, arguments: ['60'], dependencies: [database] }";
+        let parse = |template: &str| {
+            Service::serde_from_template_slice(
+                template.as_bytes(),
+                std::path::Path::new("test.yaml"),
+                &directory,
+            )
+            .expect("Could not parse template")
+        };
+        let create_supervisor = |(services, template): (Vec<Service>, _)| {
+            let mut services: std::collections::HashMap<String, Service> = services
+                .into_iter()
+                .map(|service| (service.id().clone(), service))
+                .collect();
+            let database = Service::serde_from_slice(
+                b"meta: { version: 0.1.0 }\nid: database\nautostart: false\nstart: { command: sleep, arguments: ['60'] }",
+                std::path::Path::new("test.yaml"),
+            )
+            .expect("Could not parse service definition");
+            services.insert(database.id().clone(), database);
+
+            let mut supervisor = Supervisor::new(services).expect("Could not create supervisor");
+            supervisor.set_templates(template);
+            supervisor.set_state_directory(&directory);
+            supervisor
+        };
+
+        let mut supervisor = create_supervisor(parse(template));
+        supervisor.start_services();
+        assert!(matches!(
+            supervisor.respond(Request::Scale(String::from("database"), 2)),
+            Response::Error(_)
+        ));
+
+        let response = supervisor.respond(Request::Scale(String::from("worker"), 4));
+        assert!(matches!(response, Response::Ok(_)));
+        for id in ["worker@0", "worker@1", "worker@2", "worker@3"] {
+            assert!(
+                matches!(supervisor.units[id].state, State::Running(_)),
+                "{id}"
+            );
+        }
+
+        let response = supervisor.respond(Request::Scale(String::from("worker"), 1));
+        assert!(matches!(response, Response::Ok(_)));
+        assert!(matches!(
+            supervisor.units["worker@0"].state,
+            State::Running(_)
+        ));
+        for id in ["worker@1", "worker@2", "worker@3"] {
+            assert!(
+                matches!(supervisor.units[id].state, State::Stopping(_)),
+                "{id}"
+            );
+        }
+        while ["worker@1", "worker@2", "worker@3"]
+            .iter()
+            .any(|id| supervisor.units[*id].state.is_alive())
+        {
+            let event = supervisor
+                .events_receiver
+                .recv()
+                .await
+                .expect("Event channel closed");
+            supervisor.handle(event);
+        }
+        assert!(!supervisor.status().contains("worker@1"));
+
+        // the number of replicas survives a restart, unless the definition
+        // changed
+        assert_eq!(parse(template).0.len(), 1);
+        assert_eq!(parse(&template.replace("'60'", "'30'")).0.len(), 2);
+
+        // a template scaled to no replicas can be scaled up again after a
+        // restart
+        let response = supervisor.respond(Request::Scale(String::from("worker"), 0));
+        assert!(matches!(response, Response::Ok(_)));
+        assert_eq!(supervisor.shut_down().await, 0);
+        let (services, template) = parse(template);
+        assert!(services.is_empty());
+        let mut supervisor = create_supervisor((services, template));
+        supervisor.start_services();
+        let response = supervisor.respond(Request::Scale(String::from("worker"), 1));
+        assert!(matches!(response, Response::Ok(_)));
+        assert!(matches!(
+            supervisor.units["worker@0"].state,
+            State::Running(_)
+        ));
+
+        assert_eq!(supervisor.shut_down().await, 0);
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[::tokio::test]
+    async fn scaled_instances_checked() {
+        let directory =
+            std::env::temp_dir().join(format!("sysinitd-instances-{}", std::process::id()));
+        let create_supervisor = |template: &str| {
+            let (services, template) = Service::serde_from_template_slice(
+                template.as_bytes(),
+                std::path::Path::new("test.yaml"),
+                &directory,
+            )
+            .expect("Could not parse template");
+            assert!(services.is_empty());
+            let mut supervisor = Supervisor::new(std::collections::HashMap::new())
+                .expect("Could not create supervisor");
+            supervisor.set_templates(template);
+            supervisor.set_state_directory(&directory);
+            supervisor
+        };
+        let scale = |supervisor: &mut Supervisor, replicas| match supervisor
+            .respond(Request::Scale(String::from("worker"), replicas))
+        {
+            Response::Error(message) => message,
+            Response::Ok(_) => panic!("Scaling to {replicas} replicas must fail"),
+        };
+
+        let mut supervisor = create_supervisor(
+            "meta: { version: 0.1.0 }\nid: worker\nreplicas: 0\nstart: { command: sleep, arguments: ['60'], dependencies: [worker@0] }",
+        );
+        assert_eq!(
+            scale(&mut supervisor, 1),
+            "Service 'worker@0' depends on itself through its dependencies"
+        );
+        assert_eq!(
+            scale(&mut supervisor, service::MAX_REPLICAS + 1),
+            "Service 'worker' can have at most 1024 replicas"
+        );
+
+        let mut supervisor = create_supervisor(
+            "meta: { version: 0.1.0 }\nid: worker\nreplicas: 0\nstart: { command: sleep, arguments: ['60'], dependencies: [database] }",
+        );
+        assert_eq!(
+            scale(&mut supervisor, 1),
+            "Dependency 'database' of service 'worker@0' does not exist"
+        );
+
+        let mut supervisor = create_supervisor(
+            "meta: { version: 0.1.0 }\nid: worker\nreplicas: 0\nstart: { command: sleep, arguments: ['60'] }",
+        );
+        supervisor.set_instance_check(|_| ::anyhow::bail!("Instance rejected"));
+        assert_eq!(scale(&mut supervisor, 2), "Instance rejected");
+
+        assert!(
+            Service::serde_from_template_slice(
+                b"meta: { version: 0.1.0 }\nid: worker\nreplicas: 1025\nstart: { command: _ }",
+                std::path::Path::new("test.yaml"),
+                &directory,
+            )
+            .is_err()
+        );
+
+        // nothing changed, not even the recorded number of replicas
+        assert!(supervisor.units.is_empty());
+        assert!(!directory.join("replicas").exists());
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn working_directory_and_umask() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nstart: { command: sh, arguments: [-c, 'test \"$(pwd):$(umask)\" = /tmp:0027'], working_directory: /tmp, umask: 0027 }",
+        ]);
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn resource_limits() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nstart: { command: sh, arguments: [-c, 'test \"$(ulimit -Sn):$(ulimit -Hn):$(ulimit -c)\" = 512:1024:0'] }\nlimits: { NOFILE: { soft: 512, hard: 1024 }, CORE: 0 }",
+        ]);
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 0);
+    }
+
+    #[::tokio::test]
+    async fn status_via_control_socket() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: sleeper\nstart: { command: sleep, arguments: ['60'] }",
+            "meta: { version: 0.1.0 }\nid: waiting\nstart: { command: sleep, arguments: ['60'], dependencies: [missing] }",
+        ]);
+        supervisor.start_services();
+        let socket_path =
+            std::env::temp_dir().join(format!("sysinitd-test-{}.sock", std::process::id()));
+        supervisor
+            .listen(&socket_path)
+            .expect("Could not listen on control socket");
+
+        let request = ::tokio::spawn({
+            let socket_path = socket_path.clone();
+            async move { crate::library::control::request(&socket_path, &Request::Status).await }
+        });
+        let event = supervisor
+            .events_receiver
+            .recv()
+            .await
+            .expect("Event channel closed");
+        supervisor.handle(event);
+
+        let Response::Ok(output) = request
+            .await
+            .expect("Request task panicked")
+            .expect("Could not send request")
+        else {
+            panic!("Status request failed");
+        };
+        assert!(output.starts_with("sleeper: running, PID "), "{output}");
+        assert!(output.ends_with("\nwaiting: failed\n"), "{output}");
+
+        assert_eq!(supervisor.shut_down().await, 0);
+        assert!(!socket_path.exists());
+    }
+
+    #[::tokio::test]
+    async fn main_service_killed() {
+        let mut supervisor = create_supervisor(&[
+            "meta: { version: 0.1.0 }\nid: main\nmain: true\nstart: { command: sh, arguments: [-c, 'kill -KILL $$'] }",
+        ]);
+        supervisor.start_services();
+        supervisor.supervise().await;
+        assert_eq!(supervisor.shut_down().await, 128 + ::libc::SIGKILL);
+    }
+}
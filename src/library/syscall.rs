@@ -0,0 +1,236 @@
+//! Contains [`Syscall`], a system call that can be parsed from its name
+//! (e.g. `ptrace`) in service definitions, and [`SyscallPreset`], curated
+//! groups of system calls that are usually denied together.
+
+/// A system call of the architecture `sysinitd` was built for (see
+/// `syscalls(2)`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ::serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Syscall(::libc::c_long);
+
+/// Builds a table of system call names and numbers from `libc::SYS_*`
+/// constants
+macro_rules! syscalls {
+    ($($constant:ident)*) => {
+        &[$((stringify!($constant), ::libc::$constant)),*]
+    };
+}
+
+/// The system calls available on all supported architectures
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+const SYSCALLS: &[(&str, ::libc::c_long)] = syscalls!(
+    SYS_accept SYS_accept4 SYS_acct SYS_add_key SYS_adjtimex SYS_bind SYS_bpf SYS_brk
+    SYS_capget SYS_capset SYS_chdir SYS_chroot SYS_clock_adjtime SYS_clock_getres
+    SYS_clock_gettime SYS_clock_nanosleep SYS_clock_settime SYS_clone SYS_clone3 SYS_close
+    SYS_close_range SYS_connect SYS_copy_file_range SYS_delete_module SYS_dup SYS_dup3
+    SYS_epoll_create1 SYS_epoll_ctl SYS_epoll_pwait SYS_eventfd2 SYS_execve SYS_execveat
+    SYS_exit SYS_exit_group SYS_faccessat SYS_faccessat2 SYS_fadvise64 SYS_fallocate
+    SYS_fanotify_init SYS_fanotify_mark SYS_fchdir SYS_fchmod SYS_fchmodat SYS_fchown
+    SYS_fchownat SYS_fcntl SYS_fdatasync SYS_fgetxattr SYS_finit_module SYS_flistxattr
+    SYS_flock SYS_fremovexattr SYS_fsconfig SYS_fsetxattr SYS_fsmount SYS_fsopen SYS_fspick
+    SYS_fstat SYS_fstatfs SYS_fsync SYS_ftruncate SYS_futex SYS_get_mempolicy
+    SYS_get_robust_list SYS_getcpu SYS_getcwd SYS_getdents64 SYS_getegid SYS_geteuid
+    SYS_getgid SYS_getgroups SYS_getitimer SYS_getpeername SYS_getpgid SYS_getpid SYS_getppid
+    SYS_getpriority SYS_getrandom SYS_getresgid SYS_getresuid SYS_getrlimit SYS_getrusage
+    SYS_getsid SYS_getsockname SYS_getsockopt SYS_gettid SYS_gettimeofday SYS_getuid
+    SYS_getxattr SYS_init_module SYS_inotify_add_watch SYS_inotify_init1 SYS_inotify_rm_watch
+    SYS_io_cancel SYS_io_destroy SYS_io_getevents SYS_io_setup SYS_io_submit
+    SYS_io_uring_enter SYS_io_uring_register SYS_io_uring_setup SYS_ioctl SYS_ioprio_get
+    SYS_ioprio_set SYS_kcmp SYS_kexec_load SYS_keyctl SYS_kill
+    SYS_lgetxattr SYS_linkat SYS_listen SYS_listxattr SYS_llistxattr SYS_lremovexattr
+    SYS_lseek SYS_lsetxattr SYS_madvise SYS_mbind SYS_membarrier SYS_memfd_create
+    SYS_migrate_pages SYS_mincore SYS_mkdirat SYS_mknodat SYS_mlock SYS_mlock2 SYS_mlockall
+    SYS_mmap SYS_mount SYS_move_mount SYS_move_pages SYS_mprotect SYS_mq_getsetattr
+    SYS_mq_notify SYS_mq_open SYS_mq_timedreceive SYS_mq_timedsend SYS_mq_unlink SYS_mremap
+    SYS_msgctl SYS_msgget SYS_msgrcv SYS_msgsnd SYS_msync SYS_munlock SYS_munlockall
+    SYS_munmap SYS_name_to_handle_at SYS_nanosleep SYS_newfstatat SYS_open_by_handle_at
+    SYS_open_tree SYS_openat SYS_openat2 SYS_perf_event_open SYS_personality SYS_pidfd_getfd
+    SYS_pidfd_open SYS_pidfd_send_signal SYS_pipe2 SYS_pivot_root SYS_pkey_alloc
+    SYS_pkey_free SYS_pkey_mprotect SYS_ppoll SYS_prctl SYS_pread64 SYS_preadv SYS_preadv2
+    SYS_prlimit64 SYS_process_madvise SYS_process_vm_readv SYS_process_vm_writev
+    SYS_pselect6 SYS_ptrace SYS_pwrite64 SYS_pwritev SYS_pwritev2 SYS_quotactl SYS_read
+    SYS_readahead SYS_readlinkat SYS_readv SYS_reboot SYS_recvfrom SYS_recvmmsg SYS_recvmsg
+    SYS_removexattr SYS_renameat2 SYS_request_key SYS_restart_syscall SYS_rseq
+    SYS_rt_sigaction SYS_rt_sigpending SYS_rt_sigprocmask SYS_rt_sigqueueinfo
+    SYS_rt_sigreturn SYS_rt_sigsuspend SYS_rt_sigtimedwait SYS_rt_tgsigqueueinfo
+    SYS_sched_get_priority_max SYS_sched_get_priority_min SYS_sched_getaffinity
+    SYS_sched_getattr SYS_sched_getparam SYS_sched_getscheduler SYS_sched_rr_get_interval
+    SYS_sched_setaffinity SYS_sched_setattr SYS_sched_setparam SYS_sched_setscheduler
+    SYS_sched_yield SYS_seccomp SYS_semctl SYS_semget SYS_semop SYS_semtimedop SYS_sendfile
+    SYS_sendmmsg SYS_sendmsg SYS_sendto SYS_set_mempolicy SYS_set_robust_list
+    SYS_set_tid_address SYS_setdomainname SYS_setfsgid SYS_setfsuid SYS_setgid SYS_setgroups
+    SYS_sethostname SYS_setitimer SYS_setns SYS_setpgid SYS_setpriority SYS_setregid
+    SYS_setresgid SYS_setresuid SYS_setreuid SYS_setrlimit SYS_setsid SYS_setsockopt
+    SYS_settimeofday SYS_setuid SYS_setxattr SYS_shmat SYS_shmctl SYS_shmdt SYS_shmget
+    SYS_shutdown SYS_sigaltstack SYS_signalfd4 SYS_socket SYS_socketpair SYS_splice
+    SYS_statfs SYS_statx SYS_swapoff SYS_swapon SYS_symlinkat SYS_sync SYS_sync_file_range
+    SYS_syncfs SYS_sysinfo SYS_syslog SYS_tee SYS_tgkill SYS_timer_create SYS_timer_delete
+    SYS_timer_getoverrun SYS_timer_gettime SYS_timer_settime SYS_timerfd_create
+    SYS_timerfd_gettime SYS_timerfd_settime SYS_times SYS_tkill SYS_truncate SYS_umask
+    SYS_umount2 SYS_uname SYS_unlinkat SYS_unshare SYS_userfaultfd SYS_utimensat SYS_vhangup
+    SYS_vmsplice SYS_wait4 SYS_waitid SYS_write SYS_writev
+);
+
+/// No system calls are known on architectures seccomp filters are not
+/// supported on
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+const SYSCALLS: &[(&str, ::libc::c_long)] = &[];
+
+/// The system calls only available on `x86_64`, most of them legacy ones
+#[cfg(target_arch = "x86_64")]
+const ARCHITECTURE_SYSCALLS: &[(&str, ::libc::c_long)] = syscalls!(
+    SYS_kexec_file_load SYS_renameat
+    SYS_access SYS_alarm SYS_arch_prctl SYS_chmod SYS_chown SYS_creat SYS_dup2
+    SYS_epoll_create SYS_epoll_wait SYS_eventfd SYS_fork SYS_futimesat SYS_getdents
+    SYS_getpgrp SYS_inotify_init SYS_ioperm SYS_iopl SYS_lchown SYS_link SYS_lstat SYS_mkdir
+    SYS_mknod SYS_modify_ldt SYS_open SYS_pause SYS_pipe SYS_poll SYS_readlink SYS_rename
+    SYS_rmdir SYS_select SYS_signalfd SYS_stat SYS_symlink SYS_time SYS_unlink SYS_uselib
+    SYS_utime SYS_utimes SYS_vfork
+);
+
+/// The system calls only available on `aarch64`, which `riscv64` dropped
+#[cfg(target_arch = "aarch64")]
+const ARCHITECTURE_SYSCALLS: &[(&str, ::libc::c_long)] = syscalls!(SYS_renameat);
+
+/// There are no additional system calls on other architectures
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const ARCHITECTURE_SYSCALLS: &[(&str, ::libc::c_long)] = &[];
+
+/// All known system calls
+fn known() -> impl Iterator<Item = &'static (&'static str, ::libc::c_long)> {
+    SYSCALLS.iter().chain(ARCHITECTURE_SYSCALLS)
+}
+
+impl Syscall {
+    /// The raw system call number
+    pub fn as_raw(&self) -> ::libc::c_long {
+        self.0
+    }
+
+    /// The name of the system call
+    pub fn name(&self) -> &'static str {
+        known()
+            .find(|(_, number)| *number == self.0)
+            .and_then(|(constant, _)| constant.strip_prefix("SYS_"))
+            .unwrap_or("unknown")
+    }
+}
+
+impl std::fmt::Display for Syscall {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "{}", self.name())
+    }
+}
+
+impl std::str::FromStr for Syscall {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let name = value.trim();
+        known()
+            .find(|(constant, _)| constant.strip_prefix("SYS_") == Some(name))
+            .map(|(_, number)| Self(*number))
+            .ok_or_else(|| format!("'{value}' is not a known system call on this architecture"))
+    }
+}
+
+impl TryFrom<String> for Syscall {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// A curated group of system calls that are denied together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ::serde::Deserialize)]
+pub enum SyscallPreset {
+    /// Creating sockets and accepting or opening connections (inherited
+    /// sockets keep working)
+    #[serde(rename = "no-network")]
+    Network,
+    /// Tracing other processes and accessing their memory
+    #[serde(rename = "no-ptrace")]
+    Ptrace,
+    /// Mounting and unmounting file systems
+    #[serde(rename = "no-mount")]
+    Mount,
+    /// Loading and unloading kernel modules
+    #[serde(rename = "no-modules")]
+    Modules,
+    /// Rebooting and loading new kernels
+    #[serde(rename = "no-reboot")]
+    Reboot,
+    /// Setting the system clock
+    #[serde(rename = "no-clock")]
+    Clock,
+}
+
+impl SyscallPreset {
+    /// The names of the system calls in this preset
+    fn names(&self) -> &'static [&'static str] {
+        match self {
+            Self::Network => &[
+                "socket",
+                "socketpair",
+                "connect",
+                "bind",
+                "listen",
+                "accept",
+                "accept4",
+            ],
+            Self::Ptrace => &["ptrace", "process_vm_readv", "process_vm_writev"],
+            Self::Mount => &[
+                "mount",
+                "umount2",
+                "pivot_root",
+                "fsopen",
+                "fsconfig",
+                "fsmount",
+                "fspick",
+                "move_mount",
+                "open_tree",
+            ],
+            Self::Modules => &["init_module", "finit_module", "delete_module"],
+            Self::Reboot => &["reboot", "kexec_load", "kexec_file_load"],
+            Self::Clock => &["settimeofday", "clock_settime", "clock_adjtime", "adjtimex"],
+        }
+    }
+
+    /// The system calls in this preset
+    pub fn syscalls(&self) -> impl Iterator<Item = Syscall> {
+        self.names().iter().filter_map(|name| name.parse().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_syscall_names() {
+        let ptrace = "ptrace".parse::<Syscall>().expect("ptrace is known");
+        assert_eq!(ptrace.as_raw(), ::libc::SYS_ptrace);
+        assert_eq!(ptrace.to_string(), "ptrace");
+        assert!("fly".parse::<Syscall>().is_err());
+
+        for preset in [
+            SyscallPreset::Network,
+            SyscallPreset::Ptrace,
+            SyscallPreset::Mount,
+            SyscallPreset::Modules,
+            SyscallPreset::Reboot,
+            SyscallPreset::Clock,
+        ] {
+            assert_eq!(preset.syscalls().count(), preset.names().len());
+        }
+    }
+}
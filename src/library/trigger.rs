@@ -0,0 +1,235 @@
+//! Contains [`PathWatcher`], which watches the paths that start a service
+//! via `inotify(7)`
+//!
+//! Paths that are expected to appear or change are watched through their
+//! parent directory, as they may not exist yet or may be replaced by
+//! renaming; directories that are expected to become non-empty are watched
+//! themselves. Events are debounced: a service is only triggered once no
+//! further events arrived for the debounce delay.
+
+use crate::library::service::PathTrigger;
+
+/// The events that are watched for
+///
+/// All watches use the same mask, as watching a directory a second time
+/// replaces the mask of the first watch.
+const MASK: u32 = ::libc::IN_CREATE | ::libc::IN_MOVED_TO | ::libc::IN_CLOSE_WRITE;
+
+/// The size of the buffer `inotify(7)` events are read into
+const BUFFER_SIZE: usize = 4096;
+
+/// A single watch of a [`PathWatcher`]
+#[derive(Debug)]
+struct Watch {
+    /// The watch descriptor
+    descriptor: ::libc::c_int,
+    /// The name events have to refer to, if they do not refer to any entry
+    /// of the watched directory
+    name: Option<std::ffi::OsString>,
+    /// The index of the trigger in [`PathWatcher::triggers`]
+    trigger: usize,
+}
+
+/// Watches the paths that start a service
+#[derive(Debug)]
+pub struct PathWatcher {
+    /// The `inotify(7)` instance
+    inotify: ::tokio::io::unix::AsyncFd<std::os::fd::OwnedFd>,
+    /// The watches of the triggers
+    watches: Vec<Watch>,
+    /// The triggers that are watched
+    triggers: Vec<PathTrigger>,
+}
+
+impl PathWatcher {
+    /// Starts watching the paths of `triggers`
+    ///
+    /// Must be called from within a [`::tokio`] runtime.
+    pub fn new(triggers: &[PathTrigger]) -> std::io::Result<Self> {
+        use std::os::{fd::FromRawFd as _, unix::ffi::OsStrExt as _};
+
+        // SAFETY: `inotify_init1(2)` has no memory safety preconditions
+        let fd = unsafe { ::libc::inotify_init1(::libc::IN_NONBLOCK | ::libc::IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just opened and is owned by nobody else
+        let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) };
+
+        let mut watches = Vec::with_capacity(triggers.len());
+        for (index, trigger) in triggers.iter().enumerate() {
+            let path = trigger.path();
+            let (directory, name) = match trigger {
+                PathTrigger::DirectoryNotEmpty(_) => (path, None),
+                PathTrigger::Exists(_) | PathTrigger::Changed(_) => (
+                    path.parent().unwrap_or(std::path::Path::new("/")),
+                    path.file_name().map(std::ffi::OsStr::to_os_string),
+                ),
+            };
+            let c_directory = std::ffi::CString::new(directory.as_os_str().as_bytes())?;
+            // SAFETY: `c_directory` is a valid C string
+            let descriptor = unsafe {
+                ::libc::inotify_add_watch(
+                    std::os::fd::AsRawFd::as_raw_fd(&fd),
+                    c_directory.as_ptr(),
+                    MASK,
+                )
+            };
+            if descriptor == -1 {
+                let error = std::io::Error::last_os_error();
+                return Err(std::io::Error::new(
+                    error.kind(),
+                    format!("could not watch '{}': {error}", directory.display()),
+                ));
+            }
+            watches.push(Watch {
+                descriptor,
+                name,
+                trigger: index,
+            });
+        }
+
+        // SAFETY: the `AsyncFd` owns the file descriptor, which thus stays
+        // open for as long as it is registered
+        let inotify = unsafe {
+            ::tokio::io::unix::AsyncFd::register_with_interest(fd, ::tokio::io::Interest::READABLE)?
+        };
+        Ok(Self {
+            inotify,
+            watches,
+            triggers: triggers.to_vec(),
+        })
+    }
+
+    /// The first trigger that holds right now, e.g. because the path
+    /// already existed when `sysinitd` started
+    pub fn holding(&self) -> Option<&PathTrigger> {
+        self.triggers.iter().find(|trigger| trigger.holds())
+    }
+
+    /// Waits until one of the triggers fired and no further events arrived
+    /// for `debounce`, and returns it
+    ///
+    /// Triggers that are states rather than events (a path exists, a
+    /// directory is not empty) only fire if they still hold afterwards.
+    pub async fn triggered(&self, debounce: std::time::Duration) -> std::io::Result<&PathTrigger> {
+        loop {
+            let mut fired = self.read_events().await?;
+            if fired.is_empty() {
+                continue;
+            }
+            while let Ok(events) = ::tokio::time::timeout(debounce, self.read_events()).await {
+                fired.extend(events?);
+            }
+
+            let trigger = fired
+                .into_iter()
+                .map(|index| &self.triggers[index])
+                .find(|trigger| matches!(trigger, PathTrigger::Changed(_)) || trigger.holds());
+            if let Some(trigger) = trigger {
+                return Ok(trigger);
+            }
+        }
+    }
+
+    /// Waits for events and returns the indices of the triggers they refer to
+    async fn read_events(&self) -> std::io::Result<std::collections::BTreeSet<usize>> {
+        use std::os::{fd::AsRawFd as _, unix::ffi::OsStrExt as _};
+
+        let mut buffer = [0_u8; BUFFER_SIZE];
+        let length = loop {
+            let mut guard = self.inotify.readable().await?;
+            let result = guard.try_io(|inotify| {
+                // SAFETY: `buffer` is valid for writes of its length
+                let length = unsafe {
+                    ::libc::read(
+                        inotify.as_raw_fd(),
+                        buffer.as_mut_ptr().cast(),
+                        buffer.len(),
+                    )
+                };
+                if length == -1 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(length as usize)
+                }
+            });
+            if let Ok(result) = result {
+                break result?;
+            }
+        };
+
+        let mut fired = std::collections::BTreeSet::new();
+        let header_size = std::mem::size_of::<::libc::inotify_event>();
+        let mut offset = 0;
+        while offset + header_size <= length {
+            // SAFETY: the kernel only writes complete events, and the
+            // header lies within `buffer`
+            let event: ::libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr().cast()) };
+            let name_end = (offset + header_size + event.len as usize).min(length);
+            let name = &buffer[offset + header_size..name_end];
+            // the name is padded with NUL bytes
+            let name = &name[..name
+                .iter()
+                .position(|byte| *byte == 0)
+                .unwrap_or(name.len())];
+            offset = name_end;
+
+            if event.mask & ::libc::IN_Q_OVERFLOW != 0 {
+                // events were lost, so every trigger may have fired
+                fired.extend(0..self.triggers.len());
+                continue;
+            }
+            fired.extend(
+                self.watches
+                    .iter()
+                    .filter(|watch| watch.descriptor == event.wd)
+                    .filter(|watch| {
+                        watch
+                            .name
+                            .as_ref()
+                            .is_none_or(|watched| watched.as_bytes() == name)
+                    })
+                    .map(|watch| watch.trigger),
+            );
+        }
+        Ok(fired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[::tokio::test]
+    async fn debounced_triggers() {
+        let directory =
+            std::env::temp_dir().join(format!("sysinitd-trigger-{}", std::process::id()));
+        let spool = directory.join("spool");
+        std::fs::create_dir_all(&spool).expect("Could not create directory");
+        let watcher = PathWatcher::new(&[
+            PathTrigger::Exists(directory.join("ready")),
+            PathTrigger::DirectoryNotEmpty(spool.clone()),
+        ])
+        .expect("Could not watch paths");
+        assert_eq!(watcher.holding(), None);
+
+        // a file that appears and vanishes again within the debounce delay
+        // does not trigger anything
+        std::fs::write(directory.join("ready"), "").expect("Could not write file");
+        std::fs::remove_file(directory.join("ready")).expect("Could not remove file");
+        std::fs::write(spool.join("import.csv"), "a,b").expect("Could not write file");
+        let trigger = ::tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            watcher.triggered(std::time::Duration::from_millis(100)),
+        )
+        .await
+        .expect("No trigger fired")
+        .expect("Could not read events");
+        assert_eq!(trigger, &PathTrigger::DirectoryNotEmpty(spool.clone()));
+        assert_eq!(watcher.holding(), Some(trigger));
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
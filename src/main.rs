@@ -15,12 +15,105 @@
 //!    4. Parsing of service definitions
 //!    5. Execution of checks on service definitions
 //! 1. Initialization Phase
-//!    0. Startup of processes
-//!    1. Execution of post-start checks
-//! 2. SupervisionPhase
-//!    0. TODO
+//!    0. Setup of cgroups if services need them and `sysinitd` has a
+//!    delegated cgroup v2 subtree
+//!    1. Startup of processes, each in a session (and cgroup) of its own
+//!    2. Opening of the control socket
+//!    3. Execution of post-start checks
+//! 2. Supervision Phase
+//!    0. Reaping of exited processes until the main service exits
+//!    1. Adoption of the daemons of forking services via their PID files
+//!    2. Runs of scheduled services whenever their timers elapse, and of
+//!       triggered services whenever their paths trigger
+//!    3. Startup of services whose dependencies became ready (e.g. oneshot
+//!       services that completed)
+//!    4. Passing on of received signals to services (`SIGTERM` and
+//!       `SIGINT` shut down `sysinitd` if the main service is not running)
 //! 3. Shutdown Phase
-//!    0. TODO
+//!    0. Termination of all remaining services
+//!    1. Exit with the exit code of the main service
+//!
+//! ## Control Socket
+//!
+//! `sysinitd` listens on a Unix socket (`/run/sysinitd.sock` unless
+//! `--control-socket` or `SYSINITD_CONTROL_SOCKET` say otherwise). The
+//! `sysinitctl` binary talks to it: `sysinitctl status` shows the state
+//! of all services and, for services in a cgroup, their resource usage.
+//! `sysinitctl start <SERVICE>` starts a service, e.g. one with
+//! `autostart: false`.
+//!
+//! ## Targets
+//!
+//! Services join targets (e.g. `default`, `debug` or `migration-only`) via
+//! `targets:`; services that do not list any belong to `default`. Only
+//! the services of the target selected with `--target` or
+//! `SYSINITD_TARGET` (`default` unless said otherwise), their dependencies
+//! and the main service are started. `sysinitctl target <TARGET>` switches
+//! targets at runtime, stopping the services that are no longer needed.
+//!
+//! ## Notify Services
+//!
+//! Services of `type: notify` report their readiness like they would to
+//! systemd: `sysinitd` creates a datagram socket per service in the notify
+//! directory (`/run/sysinitd/notify` unless `--notify-directory` or
+//! `SYSINITD_NOTIFY_DIRECTORY` say otherwise) and passes its path in
+//! `NOTIFY_SOCKET`. Dependents are only started once `READY=1` arrived.
+//!
+//! ## Scheduled Services
+//!
+//! Services with a `schedule` are not started together with `sysinitd`,
+//! but whenever their timer elapses: either a cron expression (evaluated
+//! in UTC) or an interval like `every 15m`. The time of the next run is
+//! shown by `sysinitctl status`. Persistent schedules record their last
+//! run in the state directory (`/var/lib/sysinitd` unless
+//! `--state-directory` or `SYSINITD_STATE_DIRECTORY` say otherwise) and
+//! catch up on runs missed while `sysinitd` was not running.
+//!
+//! ## Triggered Services
+//!
+//! Services with `trigger.path` are started whenever a path appears
+//! (`exists`), changes (`changed`) or a directory becomes non-empty
+//! (`directory_not_empty`), which `sysinitd` learns about via `inotify(7)`.
+//! Events are debounced, and a trigger during a run queues another run.
+//!
+//! ## Templated Services
+//!
+//! A definition with `instances: [a, b]` or `replicas: 4` is a template:
+//! it yields one service per instance with the ID `<ID>@<INSTANCE>` (e.g.
+//! `worker@a`, or `worker@0` for replicas). `{{instance}}` and `{{index}}`
+//! are replaced by the instance name and its index in the arguments of
+//! the command, and are passed in `SYSINITD_INSTANCE` and
+//! `SYSINITD_INSTANCE_INDEX`. A placeholder on its own must be quoted
+//! (`"{{index}}"`), as YAML reads it as a mapping otherwise. Instance
+//! names may only contain letters, digits, `_`, `.` and `-`. A file named
+//! like `worker@.yaml` must define the service `worker` (or `worker@`),
+//! and it, like an ID ending in `@`, requires `instances` or `replicas`.
+//!
+//! `sysinitctl scale <ID> <COUNT>` changes the number of replicas (at most
+//! 1024) at runtime: new instances pass the same checks as definitions do
+//! when `sysinitd` starts, which check templates without instances with
+//! their next instance. They are started together once their dependencies
+//! are ready, and surplus ones are stopped with their termination
+//! settings, highest index first. The count is recorded in the state
+//! directory and used instead of `replicas` when `sysinitd` starts again,
+//! unless the definition changed since.
+//!
+//! ## Conditions and Assertions
+//!
+//! `conditions` are checked whenever a service is about to start (e.g. a
+//! path exists or is absent, an environment variable is set, a flag is on
+//! the kernel command line, `sysinitd` runs in a container or on an
+//! architecture). If one is not met, the service is skipped and reported
+//! as "condition not met"; services depending on it start nonetheless.
+//! `assertions` are checked together with the service definitions instead,
+//! and `sysinitd` refuses to start if one does not hold.
+//!
+//! ## Running a Single Command
+//!
+//! Everything after `--` is run as an implicit main service, similar to
+//! what [`tini`][tini::github] does: `sysinitd -- nginx -g 'daemon off;'`
+//! forwards signals to `nginx`, reaps zombies and exits with the exit code
+//! of `nginx`. Service directories given in addition supply sidecars.
 //!
 //! ## Technical Aspects
 //!
@@ -37,6 +130,7 @@
 //! [//]: # (Links)
 //!
 //! [sysinitd::github]: https://github.com/georglauterbach/sysinitd
+//! [tini::github]: https://github.com/krallin/tini
 //! [yaml::documentation]: https://en.wikipedia.org/wiki/YAML
 //! [rustdoc::documentation]: https://doc.rust-lang.org/rustdoc/index.html
 
@@ -46,31 +140,41 @@
 ///
 /// [`::tokio`] builds a runtime and the [`run`] functions is called.
 /// [`run`] is the "actual `main`" function that returns an
-/// [`::anyhow::Result<()>`]. In case of an error, we display it and
-/// abort.
+/// [`::anyhow::Result<i32>`] with the exit code. In case of an error,
+/// we display it and abort.
 #[::tokio::main(flavor = "multi_thread")]
 async fn main() {
-    if let Err(error) = run().await {
-        ::tracing::error!("{error:?}");
-        std::process::exit(1);
+    match run().await {
+        Ok(exit_code) => std::process::exit(exit_code),
+        Err(error) => {
+            ::tracing::error!("{error:?}");
+            std::process::exit(1);
+        }
     }
 }
 
 /// Contains the actual functionality of `sysinitd`
-async fn run() -> ::anyhow::Result<()> {
+async fn run() -> ::anyhow::Result<i32> {
     let tracing_reload_handle = phases::startup::initialize_tracing_early();
     let arguments = phases::startup::parse_arguments()?;
     phases::startup::update_log_level(&arguments, &tracing_reload_handle)?;
     ::tracing::info!("Starting sysinitd v{}", env!("CARGO_PKG_VERSION"));
     phases::startup::execute_environment_checks().await?;
-    let process_definitions = phases::startup::parse_service_definitions(&arguments).await?;
+    let (mut process_definitions, templates) =
+        phases::startup::parse_service_definitions(&arguments).await?;
     phases::startup::check_service_definitions(&process_definitions)
         .context("Service definition checks failed")?;
+    phases::startup::check_templates(&mut process_definitions, &templates)
+        .context("Template checks failed")?;
 
-    phases::initialization::start_services(&process_definitions);
+    let mut supervisor =
+        phases::initialization::start_services(process_definitions, templates, &arguments)?;
+    phases::initialization::open_control_socket(&mut supervisor, &arguments);
     phases::initialization::post_start_checks();
 
-    Ok(())
+    phases::supervision::supervise(&mut supervisor).await;
+
+    Ok(phases::shutdown::shut_down(&mut supervisor).await)
 }
 
 mod phases {
@@ -159,17 +263,31 @@ pub async fn execute_environment_checks() -> ::anyhow::Result<()> {
             .to_string();
             tracing::debug!("Running on kernel version {kernel_version}");
 
+            if sysinitd::process::pidfd::supported() {
+                tracing::debug!("Kernel supports pidfds - processes are tracked race-free");
+            } else {
+                tracing::debug!("Kernel does not support pidfds - processes are tracked by PID");
+            }
+
             Ok(())
         }
 
-        /// TODO
+        /// Parses the service definitions, returning the services keyed by
+        /// their ID and the templates that can be scaled at runtime
         pub async fn parse_service_definitions(
             arguments: &sysinitd::Arguments,
-        ) -> anyhow::Result<std::collections::HashMap<String, sysinitd::Service>> {
+        ) -> anyhow::Result<(
+            std::collections::HashMap<String, sysinitd::Service>,
+            Vec<std::sync::Arc<sysinitd::service::Template>>,
+        )> {
             /// TODO
             async fn parse_service_directory(
                 directory: std::path::PathBuf,
-            ) -> ::anyhow::Result<Vec<sysinitd::Service>> {
+                state_directory: std::path::PathBuf,
+            ) -> ::anyhow::Result<(
+                Vec<sysinitd::Service>,
+                Vec<std::sync::Arc<sysinitd::service::Template>>,
+            )> {
                 let canonical_dir = directory.canonicalize().unwrap();
                 if !canonical_dir.is_dir() {
                     anyhow::bail!(
@@ -179,6 +297,7 @@ async fn parse_service_directory(
                 }
 
                 let mut services = Vec::with_capacity(4);
+                let mut templates = Vec::new();
 
                 for dir_entry in std::fs::read_dir(canonical_dir).context(format!(
                     "Could not loop over elements of provided directory {directory:?}"
@@ -210,14 +329,19 @@ async fn parse_service_directory(
                     let file_content = std::fs::read(&path)
                         .context(format!("Could not read contents '{}'", path.display()))?;
 
-                    let service: sysinitd::Service =
-                        sysinitd::Service::serde_from_slice(&file_content, &path)?;
-
-                    ::tracing::debug!("Parsed service '{}'", service.id());
-                    services.push(service);
+                    let (new_services, template) = sysinitd::Service::serde_from_template_slice(
+                        &file_content,
+                        &path,
+                        &state_directory,
+                    )?;
+                    for service in new_services {
+                        ::tracing::debug!("Parsed service '{}'", service.id());
+                        services.push(service);
+                    }
+                    templates.extend(template);
                 }
 
-                Ok(services)
+                Ok((services, templates))
             }
 
             ::tracing::info!("Parsing process definitions");
@@ -225,14 +349,25 @@ async fn parse_service_directory(
             let mut service_directory_parsers = ::tokio::task::JoinSet::new();
 
             for service_directory in arguments.services_directories() {
-                service_directory_parsers.spawn(parse_service_directory(service_directory.clone()));
+                service_directory_parsers.spawn(parse_service_directory(
+                    service_directory.clone(),
+                    arguments.state_directory().to_path_buf(),
+                ));
+            }
+
+            let mut parsed_results = service_directory_parsers.join_all().await;
+            if let Some([command, command_arguments @ ..]) = arguments.command() {
+                let service = sysinitd::Service::from_command(command, command_arguments);
+                ::tracing::debug!("Created service '{}' from command line", service.id());
+                parsed_results.push(Ok((vec![service], Vec::new())));
             }
 
             let mut services = std::collections::HashMap::with_capacity(8);
-            let parsed_results = service_directory_parsers.join_all().await;
+            let mut templates = Vec::new();
             for service_list in parsed_results {
                 match service_list {
-                    Ok(new_services) => {
+                    Ok((new_services, new_templates)) => {
+                        templates.extend(new_templates);
                         for service in new_services {
                             let id = service.id().clone();
                             if services.insert(service.id().clone(), service).is_some() {
@@ -245,7 +380,7 @@ async fn parse_service_directory(
             }
 
             ::tracing::trace!("Parsed service definitions:\n{services:#?}\n");
-            Ok(services)
+            Ok((services, templates))
         }
 
         /// TODO
@@ -340,12 +475,457 @@ fn check_cyclic_dependencies<'a>(
             Ok(())
         }
 
+        /// Checks that at most one service is marked as main service and
+        /// that the main service is started together with `sysinitd`, i.e.
+        /// it is enabled, started automatically, and neither scheduled nor
+        /// triggered
+        fn check_main_service(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            let mut main_services: Vec<&String> = service_definitions
+                .values()
+                .filter(|service| service.main())
+                .map(|service| service.id())
+                .collect();
+            main_services.sort();
+
+            if main_services.len() > 1 {
+                ::anyhow::bail!(
+                    "Only one service may be the main service, but {} are: '{}'",
+                    main_services.len(),
+                    main_services
+                        .iter()
+                        .map(|id| id.as_str())
+                        .collect::<Vec<_>>()
+                        .join("', '")
+                );
+            }
+            if let Some(service) = service_definitions
+                .values()
+                .find(|service| service.main() && !(service.enabled() && service.autostart()))
+            {
+                ::anyhow::bail!(
+                    "Service '{}' is the main service, but is not started automatically",
+                    service.id()
+                );
+            }
+            if let Some(service) = service_definitions
+                .values()
+                .find(|service| service.main() && service.schedule().is_some())
+            {
+                ::anyhow::bail!(
+                    "Service '{}' is the main service, but has a schedule",
+                    service.id()
+                );
+            }
+            if let Some(service) = service_definitions
+                .values()
+                .find(|service| service.main() && !service.trigger().path().is_empty())
+            {
+                ::anyhow::bail!(
+                    "Service '{}' is the main service, but is triggered by paths",
+                    service.id()
+                );
+            }
+
+            Ok(())
+        }
+
+        /// Checks that services only map signals that `sysinitd` handles
+        fn check_signal_mappings(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                for signal in service.signals().mapped_signals() {
+                    if !sysinitd::Supervisor::HANDLED_SIGNALS.contains(signal) {
+                        ::anyhow::bail!(
+                            "Service '{}' maps {signal}, but sysinitd only passes on {}",
+                            service.id(),
+                            sysinitd::Supervisor::HANDLED_SIGNALS
+                                .map(|signal| signal.to_string())
+                                .join(", ")
+                        );
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Checks that the users and groups of all services exist
+        fn check_credentials(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                sysinitd::process::credentials::resolve(service).context(format!(
+                    "Could not resolve credentials of service '{}'",
+                    service.id()
+                ))?;
+            }
+
+            Ok(())
+        }
+
+        /// Checks that services only raise ambient capabilities that are in
+        /// their bounding set
+        fn check_capabilities(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                let capabilities = service.capabilities();
+                if let Some(bounding) = capabilities.bounding()
+                    && let Some(capability) = capabilities
+                        .ambient()
+                        .iter()
+                        .find(|capability| !bounding.contains(capability))
+                {
+                    ::anyhow::bail!(
+                        "Service '{}' raises the ambient capability {capability}, which is not in its bounding set",
+                        service.id()
+                    );
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Checks that `sysinitd` can create the new namespaces of enabled
+        /// services, that read-only paths of new mount namespaces exist and
+        /// that hostnames of new UTS namespaces are valid
+        fn check_namespaces(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                let namespaces = service.namespaces();
+                if service.enabled()
+                    && !namespaces.is_empty()
+                    && !sysinitd::process::capabilities::is_effective(
+                        sysinitd::Capability::SYS_ADMIN,
+                    )?
+                {
+                    ::anyhow::bail!(
+                        "Service '{}' requests new namespaces, but sysinitd lacks {} to create them",
+                        service.id(),
+                        sysinitd::Capability::SYS_ADMIN
+                    );
+                }
+
+                if let Some(path) = namespaces
+                    .mount()
+                    .into_iter()
+                    .flat_map(|mount| mount.read_only())
+                    .find(|path| !path.exists())
+                {
+                    ::anyhow::bail!(
+                        "Read-only path '{}' of service '{}' does not exist",
+                        path.display(),
+                        service.id()
+                    );
+                }
+
+                if let Some(hostname) = namespaces.uts().and_then(|uts| uts.hostname())
+                    && (hostname.is_empty() || hostname.len() > 64)
+                {
+                    ::anyhow::bail!(
+                        "Hostname '{hostname}' of service '{}' must be between 1 and 64 bytes long",
+                        service.id()
+                    );
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Warns about dependencies on disabled services, which are ignored
+        ///
+        /// This allows shipping definitions of optional services that are
+        /// only enabled in some deployments.
+        fn check_disabled_dependencies(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) {
+            for service in service_definitions.values() {
+                for dependency in service.start().dependencies() {
+                    if service_definitions
+                        .get(dependency)
+                        .is_some_and(|dependency| !dependency.enabled())
+                    {
+                        ::tracing::warn!(
+                            "Dependency '{dependency}' of service '{}' is disabled and therefore ignored",
+                            service.id()
+                        );
+                    }
+                }
+            }
+        }
+
+        /// Checks that the directories watched for path triggers exist
+        fn check_triggers(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                for trigger in service.trigger().path() {
+                    let directory = match trigger {
+                        sysinitd::service::PathTrigger::DirectoryNotEmpty(path) => path.as_path(),
+                        sysinitd::service::PathTrigger::Exists(path)
+                        | sysinitd::service::PathTrigger::Changed(path) => path
+                            .parent()
+                            .filter(|directory| !directory.as_os_str().is_empty())
+                            .unwrap_or(std::path::Path::new(".")),
+                    };
+                    if !directory.is_dir() {
+                        ::anyhow::bail!(
+                            "Directory '{}' watched for service '{}' does not exist",
+                            directory.display(),
+                            service.id()
+                        );
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Checks that the assertions of all enabled services hold
+        fn check_assertions(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                if !service.enabled() {
+                    continue;
+                }
+                if let Some(assertion) = service
+                    .assertions()
+                    .iter()
+                    .find(|assertion| !assertion.holds())
+                {
+                    ::anyhow::bail!(
+                        "Assertion of service '{}' does not hold: {assertion}",
+                        service.id()
+                    );
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Checks that target names can be sent over the control socket, i.e.
+        /// they are not empty and contain no whitespace
+        fn check_targets(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                for target in service.targets() {
+                    if target.is_empty() || target.contains(char::is_whitespace) {
+                        ::anyhow::bail!(
+                            "Target '{target}' of service '{}' must not be empty or contain whitespace",
+                            service.id()
+                        );
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Checks that lazily started services have sockets, that socket names
+        /// can be passed in `LISTEN_FDNAMES` and that the directories of Unix
+        /// sockets and FIFOs exist
+        fn check_sockets(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                let sockets = service.sockets();
+                if sockets.lazy() && sockets.listen().is_empty() {
+                    ::anyhow::bail!(
+                        "Service '{}' is started lazily, but has no sockets",
+                        service.id()
+                    );
+                }
+
+                for socket in sockets.listen() {
+                    if let Some(name) = socket.name()
+                        && (name.is_empty() || name.contains(':'))
+                    {
+                        ::anyhow::bail!(
+                            "Socket name '{name}' of service '{}' must not be empty or contain ':'",
+                            service.id()
+                        );
+                    }
+                    if let Some(directory) = socket
+                        .address()
+                        .path()
+                        .and_then(std::path::Path::parent)
+                        .filter(|directory| !directory.as_os_str().is_empty())
+                        && !directory.is_dir()
+                    {
+                        ::anyhow::bail!(
+                            "Directory '{}' of the {} of service '{}' does not exist",
+                            directory.display(),
+                            socket.address(),
+                            service.id()
+                        );
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Checks that seccomp filters do not deny executing the command
+        fn check_seccomp(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                if let Some(syscall) = service
+                    .seccomp()
+                    .denied()
+                    .into_iter()
+                    .find(|syscall| syscall.as_raw() == ::libc::SYS_execve)
+                {
+                    ::anyhow::bail!(
+                        "Service '{}' denies the system call {syscall}, without which its command can not be executed",
+                        service.id()
+                    );
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Checks that I/O priorities are only given for classes that have
+        /// them and that CPU affinities contain CPUs `sysinitd` may run on
+        fn check_scheduling(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            let available_cpus =
+                sysinitd::process::scheduling::Scheduling::of(std::process::id() as ::libc::pid_t)
+                    .map(|scheduling| scheduling.cpu_affinity)
+                    .ok();
+
+            for service in service_definitions.values() {
+                if service.io_class() == Some(sysinitd::service::IoClass::Idle)
+                    && service.io_priority().is_some()
+                {
+                    ::anyhow::bail!(
+                        "Service '{}' sets an I/O priority, but the idle I/O class has none",
+                        service.id()
+                    );
+                }
+
+                if let Some(cpu_affinity) = service.cpu_affinity()
+                    && let Some(available_cpus) = &available_cpus
+                    && !cpu_affinity
+                        .cpus()
+                        .any(|cpu| available_cpus.cpus().any(|available| available == cpu))
+                {
+                    ::anyhow::bail!(
+                        "CPU affinity {cpu_affinity} of service '{}' contains none of the available CPUs {available_cpus}",
+                        service.id()
+                    );
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Checks that only oneshot services remain active after completing,
+        /// that exactly the forking services have a PID file and that only
+        /// notify services have a watchdog
+        fn check_service_types(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                if service.remain_active()
+                    && service.service_type() != sysinitd::service::ServiceType::Oneshot
+                {
+                    ::anyhow::bail!(
+                        "Service '{}' sets remain_active, which only applies to oneshot services",
+                        service.id()
+                    );
+                }
+                match (service.service_type(), service.pid_file()) {
+                    (sysinitd::service::ServiceType::Forking, None) => ::anyhow::bail!(
+                        "Service '{}' is a forking service, but has no pid_file",
+                        service.id()
+                    ),
+                    (sysinitd::service::ServiceType::Forking, Some(_)) | (_, None) => {}
+                    (_, Some(_)) => ::anyhow::bail!(
+                        "Service '{}' sets pid_file, which only applies to forking services",
+                        service.id()
+                    ),
+                }
+                if service.watchdog().is_some()
+                    && service.service_type() != sysinitd::service::ServiceType::Notify
+                {
+                    ::anyhow::bail!(
+                        "Service '{}' has a watchdog, which only applies to notify services",
+                        service.id()
+                    );
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Checks that the root and working directories of all services exist
+        fn check_directories(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            for service in service_definitions.values() {
+                let root_directory = service.start().root_directory();
+                if let Some(root_directory) = root_directory
+                    && !root_directory.is_dir()
+                {
+                    ::anyhow::bail!(
+                        "Root directory '{}' of service '{}' does not exist",
+                        root_directory.display(),
+                        service.id()
+                    );
+                }
+
+                if let Some(working_directory) = service.start().working_directory() {
+                    let path = match root_directory {
+                        Some(root_directory) => root_directory.join(
+                            working_directory
+                                .strip_prefix("/")
+                                .unwrap_or(working_directory),
+                        ),
+                        None => working_directory.to_path_buf(),
+                    };
+                    if !path.is_dir() {
+                        ::anyhow::bail!(
+                            "Working directory '{}' of service '{}' does not exist",
+                            path.display(),
+                            service.id()
+                        );
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
         /// TODO
         pub fn check_service_definitions(
             service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
         ) -> ::anyhow::Result<()> {
             ::tracing::info!("Executing service definition checks");
 
+            check_main_service(service_definitions)?;
+            check_service_types(service_definitions)?;
+            check_signal_mappings(service_definitions)?;
+            check_credentials(service_definitions)?;
+            check_capabilities(service_definitions)?;
+            check_directories(service_definitions)?;
+            check_namespaces(service_definitions)?;
+            check_seccomp(service_definitions)?;
+            check_sockets(service_definitions)?;
+            check_triggers(service_definitions)?;
+            check_targets(service_definitions)?;
+            check_assertions(service_definitions)?;
+            check_disabled_dependencies(service_definitions);
+            check_scheduling(service_definitions)?;
+
             // an efficient measure to prevent infinite recursion: we list the nodes we already checked
             // and do not check them again; this is also a nice optimization
             let mut already_checked_for_cycles = std::collections::HashSet::with_capacity(8);
@@ -369,6 +949,70 @@ pub fn check_service_definitions(
             Ok(())
         }
 
+        /// Checks services that are added at runtime by scaling a template
+        ///
+        /// These are the checks of [`check_service_definitions`] that apply
+        /// to each service on its own; the supervisor checks dependencies.
+        pub fn check_instances(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) -> ::anyhow::Result<()> {
+            check_service_types(service_definitions)?;
+            check_signal_mappings(service_definitions)?;
+            check_credentials(service_definitions)?;
+            check_capabilities(service_definitions)?;
+            check_directories(service_definitions)?;
+            check_namespaces(service_definitions)?;
+            check_seccomp(service_definitions)?;
+            check_sockets(service_definitions)?;
+            check_triggers(service_definitions)?;
+            check_targets(service_definitions)?;
+            check_assertions(service_definitions)?;
+            check_scheduling(service_definitions)
+        }
+
+        /// Checks the templates that can be scaled at runtime with the next
+        /// instance each of them would create, so that a template without
+        /// instances is checked as well
+        pub fn check_templates(
+            service_definitions: &mut std::collections::HashMap<String, sysinitd::Service>,
+            templates: &[std::sync::Arc<sysinitd::service::Template>],
+        ) -> ::anyhow::Result<()> {
+            if templates.is_empty() {
+                return Ok(());
+            }
+
+            let mut instance_ids = Vec::with_capacity(templates.len());
+            let mut result = Ok(());
+            for template in templates {
+                let index = service_definitions
+                    .values()
+                    .filter_map(|service| service.instance())
+                    .filter(|instance| {
+                        instance.template().is_some_and(|instance_template| {
+                            instance_template.id() == template.id()
+                        })
+                    })
+                    .count();
+                match template.instantiate(&index.to_string(), index) {
+                    Ok(instance) => {
+                        instance_ids.push(instance.id().clone());
+                        service_definitions.insert(instance.id().clone(), instance);
+                    }
+                    Err(error) => {
+                        result = Err(error);
+                        break;
+                    }
+                }
+            }
+            if result.is_ok() {
+                result = check_service_definitions(service_definitions);
+            }
+            for id in instance_ids {
+                service_definitions.remove(&id);
+            }
+            result
+        }
+
         #[cfg(test)]
         mod tests {
             use ::std::str::FromStr;
@@ -404,7 +1048,9 @@ async fn create_service_definitions(
                     ),
                 ]);
 
-                parse_service_definitions(&arguments).await
+                parse_service_definitions(&arguments)
+                    .await
+                    .map(|(services, _)| services)
             }
 
             #[::tokio::test]
@@ -478,6 +1124,448 @@ async fn dependencies_nonexistent() {
                 );
             }
 
+            #[::tokio::test]
+            async fn command_with_service_directory() {
+                let path_to_service_definitions =
+                    std::path::PathBuf::from_str(env!("CARGO_MANIFEST_DIR"))
+                        .expect("Could not build path to workspace directory")
+                        .join("assets/tests/services/dependencies/nonexistent");
+                let arguments = <sysinitd::Arguments as ::clap::Parser>::parse_from([
+                    "sysinitd",
+                    path_to_service_definitions.to_str().expect(
+                        "Could not construct valid string from path to service definitions",
+                    ),
+                    "--",
+                    "/usr/bin/service-b",
+                    "--flag",
+                ]);
+
+                let (service_definitions, _) = parse_service_definitions(&arguments)
+                    .await
+                    .expect("Could not parse service defintions");
+                let service = &service_definitions["service-b"];
+                assert!(service.main());
+                assert_eq!(service.start().command(), "/usr/bin/service-b");
+                assert_eq!(service.start().arguments(), &[String::from("--flag")]);
+                check_service_definitions(&service_definitions)
+                    .expect("The command should satisfy the dependency of 'service-a'");
+            }
+
+            #[::tokio::test]
+            async fn main_service_multiple() {
+                let service_definitions = create_service_definitions("services/main/multiple")
+                    .await
+                    .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert!(result.is_err());
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Only one service may be the main service, but 2 are: 'service-a', 'service-b'"
+                );
+            }
+
+            #[::tokio::test]
+            async fn main_service_manual() {
+                let service_definitions = create_service_definitions("services/main/manual")
+                    .await
+                    .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' is the main service, but is not started automatically"
+                );
+            }
+
+            #[::tokio::test]
+            async fn targets_invalid() {
+                let service_definitions =
+                    create_service_definitions("services/targets/invalid_name")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Target 'migration only' of service 'service-a' must not be empty or contain whitespace"
+                );
+            }
+
+            #[::tokio::test]
+            async fn assertion_failed() {
+                let service_definitions =
+                    create_service_definitions("services/conditions/assertion_failed")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Assertion of service 'service-a' does not hold: '/nonexistent/license' is not empty"
+                );
+            }
+
+            #[::tokio::test]
+            async fn dependency_disabled() {
+                let service_definitions =
+                    create_service_definitions("services/dependencies/disabled")
+                        .await
+                        .expect("Could not parse service defintions");
+                assert!(check_service_definitions(&service_definitions).is_ok());
+            }
+
+            #[::tokio::test]
+            async fn signals_unhandled() {
+                let service_definitions = create_service_definitions("services/signals/unhandled")
+                    .await
+                    .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert!(result.is_err());
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' maps SIGQUIT, but sysinitd only passes on SIGTERM, SIGINT, SIGHUP, SIGUSR1, SIGUSR2, SIGWINCH"
+                );
+            }
+
+            #[::tokio::test]
+            async fn credentials_unknown_user() {
+                let service_definitions =
+                    create_service_definitions("services/credentials/unknown_user")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert!(result.is_err());
+                let error = result.unwrap_err();
+                assert_eq!(
+                    &error.to_string(),
+                    "Could not resolve credentials of service 'service-a'"
+                );
+                assert!(matches!(
+                    error.downcast_ref::<sysinitd::process::credentials::CredentialsError>(),
+                    Some(sysinitd::process::credentials::CredentialsError::UnknownUser(user))
+                        if user == "sysinitd-does-not-exist"
+                ));
+            }
+
+            #[::tokio::test]
+            async fn directories_nonexistent() {
+                let service_definitions =
+                    create_service_definitions("services/directories/nonexistent_root")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Root directory '/sysinitd-does-not-exist' of service 'service-a' does not exist"
+                );
+
+                let service_definitions =
+                    create_service_definitions("services/directories/nonexistent_working")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Working directory '/tmp/sysinitd-does-not-exist' of service 'service-a' does not exist"
+                );
+            }
+
+            #[::tokio::test]
+            async fn limits_soft_above_hard() {
+                let service_definitions =
+                    create_service_definitions("services/limits/soft_above_hard").await;
+                let error = service_definitions.unwrap_err();
+                assert!(
+                    format!("{error:#}").contains(
+                        "the soft limit (unlimited) must not be larger than the hard limit (1024)"
+                    ),
+                    "unexpected error: {error:#}"
+                );
+            }
+
+            #[::tokio::test]
+            async fn capabilities_invalid() {
+                let service_definitions =
+                    create_service_definitions("services/capabilities/unknown").await;
+                let error = service_definitions.unwrap_err();
+                assert!(
+                    format!("{error:#}").contains("'CAP_FLY' is not a known capability"),
+                    "unexpected error: {error:#}"
+                );
+
+                let service_definitions =
+                    create_service_definitions("services/capabilities/ambient_not_bounding")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' raises the ambient capability CAP_NET_BIND_SERVICE, which is not in its bounding set"
+                );
+            }
+
+            #[::tokio::test]
+            async fn namespaces_nonexistent_read_only() {
+                let service_definitions =
+                    create_service_definitions("services/namespaces/nonexistent_read_only")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Read-only path '/sysinitd-does-not-exist' of service 'service-a' does not exist"
+                );
+            }
+
+            #[::tokio::test]
+            async fn seccomp_invalid() {
+                let service_definitions =
+                    create_service_definitions("services/seccomp/unknown").await;
+                let error = service_definitions.unwrap_err();
+                assert!(
+                    format!("{error:#}")
+                        .contains("'fly' is not a known system call on this architecture"),
+                    "unexpected error: {error:#}"
+                );
+
+                let service_definitions =
+                    create_service_definitions("services/seccomp/execve_denied")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' denies the system call execve, without which its command can not be executed"
+                );
+            }
+
+            #[::tokio::test]
+            async fn scheduling_invalid() {
+                let service_definitions =
+                    create_service_definitions("services/scheduling/nice_out_of_range").await;
+                let error = service_definitions.unwrap_err();
+                assert!(
+                    format!("{error:#}").contains("nice 20 is not between -20 and 19"),
+                    "unexpected error: {error:#}"
+                );
+
+                let service_definitions =
+                    create_service_definitions("services/scheduling/idle_with_priority")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' sets an I/O priority, but the idle I/O class has none"
+                );
+            }
+
+            #[::tokio::test]
+            async fn oneshot_remain_active_simple() {
+                let service_definitions =
+                    create_service_definitions("services/oneshot/remain_active_simple")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' sets remain_active, which only applies to oneshot services"
+                );
+            }
+
+            #[::tokio::test]
+            async fn forking_pid_file() {
+                let service_definitions =
+                    create_service_definitions("services/forking/missing_pid_file")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' is a forking service, but has no pid_file"
+                );
+
+                let service_definitions =
+                    create_service_definitions("services/forking/pid_file_simple")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' sets pid_file, which only applies to forking services"
+                );
+            }
+
+            #[::tokio::test]
+            async fn templates_instantiated() {
+                let service_definitions =
+                    create_service_definitions("services/templates/instances")
+                        .await
+                        .expect("Could not parse service defintions");
+                assert!(check_service_definitions(&service_definitions).is_ok());
+                let worker = &service_definitions["worker@invoices"];
+                assert_eq!(
+                    worker.start().arguments(),
+                    ["--queue", "invoices", "--slot", "1"]
+                );
+                assert_eq!(worker.instance().map(|instance| instance.index()), Some(1));
+
+                let service_definitions = create_service_definitions("services/templates/replicas")
+                    .await
+                    .expect("Could not parse service defintions");
+                let mut ids: Vec<&String> = service_definitions.keys().collect();
+                ids.sort();
+                assert_eq!(ids, ["worker@0", "worker@1", "worker@2"]);
+                // a quoted placeholder on its own stays a string
+                assert_eq!(
+                    service_definitions["worker@2"].start().arguments(),
+                    ["--name=worker-2", "2"]
+                );
+
+                let result = create_service_definitions("services/templates/unquoted").await;
+                assert!(
+                    result.unwrap_err().to_string().starts_with(
+                        "Placeholders on their own such as '{{index}}' must be quoted"
+                    )
+                );
+
+                let result = create_service_definitions("services/templates/both").await;
+                assert!(
+                    result
+                        .unwrap_err()
+                        .to_string()
+                        .starts_with("Service 'worker' in '")
+                );
+
+                let result = create_service_definitions("services/templates/uninstantiated").await;
+                assert!(
+                    result
+                        .unwrap_err()
+                        .to_string()
+                        .ends_with("is a template, but sets neither instances nor replicas")
+                );
+                // the file name alone makes the definition a template
+                let result =
+                    create_service_definitions("services/templates/uninstantiated_file").await;
+                assert!(
+                    result
+                        .unwrap_err()
+                        .to_string()
+                        .ends_with("is a template, but sets neither instances nor replicas")
+                );
+                let result = create_service_definitions("services/templates/mismatch").await;
+                assert!(
+                    result
+                        .unwrap_err()
+                        .to_string()
+                        .ends_with("must define the service 'worker@', not 'consumer@'")
+                );
+
+                // a template without instances is checked with its next one
+                let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+                path.push("assets/tests/services/templates/unscaled");
+                let arguments = <sysinitd::Arguments as ::clap::Parser>::parse_from([
+                    "sysinitd",
+                    path.to_str().expect("Invalid path"),
+                ]);
+                let (mut service_definitions, templates) = parse_service_definitions(&arguments)
+                    .await
+                    .expect("Could not parse service defintions");
+                assert!(service_definitions.is_empty());
+                assert_eq!(
+                    check_templates(&mut service_definitions, &templates)
+                        .unwrap_err()
+                        .to_string(),
+                    "Service 'worker@0' is a forking service, but has no pid_file"
+                );
+                assert!(service_definitions.is_empty());
+
+                let result =
+                    create_service_definitions("services/templates/invalid_instance").await;
+                assert_eq!(
+                    result.unwrap_err().to_string(),
+                    "Instance 'queue: high' of service 'worker' must not be empty and only contain letters, digits, '_', '.' and '-'"
+                );
+            }
+
+            #[::tokio::test]
+            async fn watchdog_invalid() {
+                let service_definitions = create_service_definitions("services/watchdog/simple")
+                    .await
+                    .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' has a watchdog, which only applies to notify services"
+                );
+
+                let result = create_service_definitions("services/watchdog/zero_interval").await;
+                assert!(
+                    format!("{:#}", result.unwrap_err())
+                        .contains("watchdog interval must be greater than zero")
+                );
+            }
+
+            #[::tokio::test]
+            async fn schedule_invalid() {
+                let service_definitions = create_service_definitions("services/schedule/main")
+                    .await
+                    .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' is the main service, but has a schedule"
+                );
+
+                let result = create_service_definitions("services/schedule/invalid_cron").await;
+                assert!(
+                    format!("{:#}", result.unwrap_err())
+                        .contains("hour 25 is not between 0 and 23")
+                );
+            }
+
+            #[::tokio::test]
+            async fn trigger_invalid() {
+                let service_definitions = create_service_definitions("services/trigger/main")
+                    .await
+                    .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' is the main service, but is triggered by paths"
+                );
+
+                let service_definitions =
+                    create_service_definitions("services/trigger/nonexistent_directory")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Directory '/nonexistent/spool' watched for service 'service-a' does not exist"
+                );
+            }
+
+            #[::tokio::test]
+            async fn sockets_invalid() {
+                let service_definitions =
+                    create_service_definitions("services/sockets/lazy_without_sockets")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Service 'service-a' is started lazily, but has no sockets"
+                );
+
+                let service_definitions =
+                    create_service_definitions("services/sockets/nonexistent_directory")
+                        .await
+                        .expect("Could not parse service defintions");
+                let result = check_service_definitions(&service_definitions);
+                assert_eq!(
+                    &result.unwrap_err().to_string(),
+                    "Directory '/nonexistent' of the Unix stream socket '/nonexistent/a.sock' of service 'service-a' does not exist"
+                );
+            }
+
             #[::tokio::test]
             async fn services_non_unique_id() {
                 let service_definitions =
@@ -492,14 +1580,115 @@ async fn services_non_unique_id() {
     pub mod initialization {
         //! Contains all functionality of the initialization phase (1)
 
-        /// TODO
+        use ::anyhow::Context as _;
+
+        /// Creates the [`sysinitd::Supervisor`] and starts all services
+        /// whose dependencies are met
         pub fn start_services(
-            _service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
-        ) {
+            service_definitions: std::collections::HashMap<String, sysinitd::Service>,
+            templates: Vec<std::sync::Arc<sysinitd::service::Template>>,
+            arguments: &sysinitd::Arguments,
+        ) -> ::anyhow::Result<sysinitd::Supervisor> {
             ::tracing::info!("Starting processes");
+            if std::process::id() != 1 {
+                sysinitd::process::become_subreaper()
+                    .context("Could not make sysinitd a child subreaper")?;
+                ::tracing::debug!("sysinitd is now a child subreaper");
+            }
+
+            initialize_cgroups(&service_definitions);
+
+            let mut supervisor = sysinitd::Supervisor::new(service_definitions)?;
+            supervisor.set_templates(templates);
+            supervisor.set_instance_check(super::startup::check_instances);
+            supervisor.set_notify_directory(arguments.notify_directory());
+            supervisor.set_state_directory(arguments.state_directory());
+            supervisor
+                .set_target(arguments.target())
+                .context("Could not select the target")?;
+            ::tracing::debug!("Starting the services of target '{}'", arguments.target());
+            supervisor.start_services();
+            Ok(supervisor)
+        }
+
+        /// Sets up the delegated cgroup v2 subtree of `sysinitd` if there is
+        /// one and warns about cgroup settings that can not be applied
+        ///
+        /// Without any cgroup settings, the cgroup of `sysinitd` is left
+        /// alone, as setting it up moves all of its processes.
+        fn initialize_cgroups(
+            service_definitions: &std::collections::HashMap<String, sysinitd::Service>,
+        ) {
+            if service_definitions
+                .values()
+                .all(|service| service.cgroup().is_empty())
+            {
+                ::tracing::debug!("No service has cgroup settings, so cgroups are not set up");
+                return;
+            }
+
+            let reason = match sysinitd::process::cgroup::initialize() {
+                Ok(Some(root)) => {
+                    ::tracing::debug!("Managing cgroups of services below '{}'", root.display());
+                    return;
+                }
+                Ok(None) => String::from("sysinitd has no delegated cgroup v2 subtree"),
+                Err(error) => format!("cgroups could not be set up: {error}"),
+            };
+            ::tracing::debug!("Services do not get cgroups of their own because {reason}");
+
+            for service in service_definitions.values() {
+                if !service.cgroup().is_empty() {
+                    ::tracing::warn!(
+                        "Ignoring cgroup settings of service '{}' because {reason}",
+                        service.id()
+                    );
+                }
+            }
+        }
+
+        /// Opens the control socket
+        ///
+        /// `sysinitd` works without the control socket, so failing to open
+        /// it is not an error.
+        pub fn open_control_socket(
+            supervisor: &mut sysinitd::Supervisor,
+            arguments: &sysinitd::Arguments,
+        ) {
+            match supervisor.listen(arguments.control_socket()) {
+                Ok(()) => ::tracing::debug!(
+                    "Listening on control socket '{}'",
+                    arguments.control_socket().display()
+                ),
+                Err(error) => ::tracing::warn!("{error:#}"),
+            }
         }
 
         /// TODO
         pub fn post_start_checks() {}
     }
+
+    pub mod supervision {
+        //! Contains all functionality of the supervision phase (2)
+
+        /// Supervises all services until the main service exits or
+        /// `sysinitd` is asked to shut down
+        pub async fn supervise(supervisor: &mut sysinitd::Supervisor) {
+            ::tracing::info!("Supervising processes");
+            supervisor.supervise().await;
+        }
+    }
+
+    pub mod shutdown {
+        //! Contains all functionality of the shutdown phase (3)
+
+        /// Terminates all remaining services and returns the exit code
+        /// of `sysinitd`
+        pub async fn shut_down(supervisor: &mut sysinitd::Supervisor) -> i32 {
+            ::tracing::info!("Shutting down");
+            let exit_code = supervisor.shut_down().await;
+            ::tracing::info!("Exiting with exit code {exit_code}");
+            exit_code
+        }
+    }
 }